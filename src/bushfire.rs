@@ -1,81 +1,509 @@
 //! Monitor feed of bushfires and post notification for any nearby.
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::Formatter;
-use std::time::Duration;
+use std::io::Read;
+use std::str::FromStr;
 use std::{fmt, io};
 
+use encoding_rs::{Encoding, UTF_8};
 use roxmltree::Node;
-use time::format_description::well_known::Rfc3339;
+use time::format_description::well_known::{Iso8601, Rfc2822, Rfc3339};
 use time::OffsetDateTime;
 use ureq::Agent;
+use url::Url;
 
 // NOTE: This URL redirects to the actual feed. It's a permanent redirect to an S3 file but I'm
 // not sure I trust the permanence of it.
-// const FEED_URL: &str = "https://www.qfes.qld.gov.au/data/alerts/bushfireAlert.xml";
-const FEED_URL: &str = "http://publiccontent-gis-psba-qld-gov-au.s3-ap-southeast-2.amazonaws.com/content/Feeds/BushfireCurrentIncidents/bushfireAlert.xml";
+// pub const FEED_URL: &str = "https://www.qfes.qld.gov.au/data/alerts/bushfireAlert.xml";
+pub const FEED_URL: &str = "http://publiccontent-gis-psba-qld-gov-au.s3-ap-southeast-2.amazonaws.com/content/Feeds/BushfireCurrentIncidents/bushfireAlert.xml";
+/// Prefixes of the category terms used for alert levels.
+const ALERT_LEVELS: [&str; 3] = ["Emergency", "Watch and Act", "Advice"];
+/// Maximum number of pages of the feed that are fetched in one check.
+const MAX_PAGES: usize = 10;
 const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
 const GEORSS_NS: &str = "http://www.georss.org/georss";
+/// W3C Basic Geo vocabulary
+const GEO_NS: &str = "http://www.w3.org/2003/01/geo/wgs84_pos#";
 
 pub type LatLong = (f64, f64);
 
 #[derive(PartialEq, Eq, Debug, Hash, Default)]
 pub struct EntryId(pub(crate) String);
 
+/// The alert level of an entry, from most to least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Emergency,
+    WatchAndAct,
+    Advice,
+    /// No recognised alert level, such as planned burns
+    Unknown,
+}
+
+impl Severity {
+    /// Identifies the alert level in machine readable output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Emergency => "emergency",
+            Severity::WatchAndAct => "watch_and_act",
+            Severity::Advice => "advice",
+            Severity::Unknown => "unknown",
+        }
+    }
+}
+
 #[derive(Debug, Default, PartialEq)]
 pub struct Entry {
-    pub category: Option<String>,
+    /// Category terms in the order they appear, usually the alert level and incident type
+    pub categories: Vec<String>,
     pub content: Option<String>,
+    pub summary: Option<String>,
     pub id: EntryId,
     pub published: Option<OffsetDateTime>,
     pub title: Option<String>,
     pub updated: Option<OffsetDateTime>,
     pub point: Option<LatLong>,
+    /// Vertices of the incident area, if the feed supplies one
+    pub polygon: Vec<LatLong>,
+    /// Other georss fields, such as `elev`, keyed by element name
+    pub extra: HashMap<String, String>,
+    /// Links in the order they appear
+    pub links: Vec<Link>,
+}
+
+/// A link from an entry to a related resource.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Link {
+    pub href: String,
+    /// How the resource relates to the entry, `alternate` if not given
+    pub rel: Option<String>,
+    /// Media type of the resource, such as `text/html`
+    pub media_type: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum BushfireError {
     Xml(roxmltree::Error),
-    Http(ureq::Error),
+    Http(Box<ureq::Error>),
     Io(io::Error),
+    /// The feed was larger than the maximum size in bytes
+    TooLarge(u64),
+    /// The response wasn't a feed, such as an HTML error page. Holds the root element name.
+    NotAFeed(String),
+    /// The response `Content-Type` wasn't one of those accepted. Holds the media type.
+    ContentType(String),
 }
 
-/// Check for entries to notify about.
-pub fn check(notify_near: LatLong) -> Result<Vec<Entry>, BushfireError> {
-    let agent: Agent = ureq::AgentBuilder::new()
-        .timeout_read(Duration::from_secs(15))
-        .timeout_write(Duration::from_secs(15))
-        .build();
+/// A source of bushfire entries.
+pub trait Feed {
+    /// Check for entries near `notify_near` to notify about.
+    fn check(&self, notify_near: LatLong) -> Result<Vec<Entry>, BushfireError>;
+}
 
-    // Fetch the feed
-    let body: String = agent.get(FEED_URL).call()?.into_string()?;
+/// The QFES bushfire alert feed.
+///
+/// The agent is kept for the life of the feed so connections are reused between polls.
+pub struct QfesFeed {
+    agent: Agent,
+    url: String,
+    shape: AlertShape,
+    max_size: u64,
+    /// Points monitored in addition to the reference point
+    extra_points: Vec<LatLong>,
+    /// Entries with a point further than this many kilometres from every monitored point are
+    /// skipped without being fully parsed
+    prefilter: Option<f64>,
+    /// Media types the feed may be served as, [DEFAULT_CONTENT_TYPES] if empty
+    content_types: Vec<String>,
+}
 
-    // Parse and note entries that are in range
+/// The shape of the region around the reference point that entries are notified within.
+///
+/// Points on the boundary of the box, circle, or region are near.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlertShape {
+    /// A square extending [ALERT_DISTANCE] from the reference point in each direction
+    Box,
+    /// A circle with a radius of [ALERT_DISTANCE]
+    #[default]
+    Circle,
+    /// Entries with an incident area are near if it contains the reference point, others use
+    /// the circle
+    Polygon,
+    /// A fixed region, such as a council area, regardless of the reference point
+    Region(BoundingBox),
+}
+
+/// An area between two corners, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    /// The south west corner
+    pub min: LatLong,
+    /// The north east corner
+    pub max: LatLong,
+}
+
+impl QfesFeed {
+    pub fn new(agent: Agent) -> Self {
+        QfesFeed {
+            agent,
+            url: String::from(FEED_URL),
+            shape: AlertShape::default(),
+            max_size: DEFAULT_MAX_FEED_SIZE,
+            extra_points: Vec::new(),
+            prefilter: None,
+            content_types: Vec::new(),
+        }
+    }
+
+    /// Only accept responses with one of `content_types`, or [DEFAULT_CONTENT_TYPES] if empty.
+    pub fn with_content_types(mut self, content_types: Vec<String>) -> Self {
+        self.content_types = content_types;
+        self
+    }
+
+    /// Skip entries whose point is more than `distance` km from every monitored point before
+    /// parsing the rest of the entry.
+    pub fn with_prefilter(mut self, distance: Option<f64>) -> Self {
+        self.prefilter = distance;
+        self
+    }
+
+    /// Also notify about entries near each of `points`.
+    pub fn with_extra_points(mut self, points: Vec<LatLong>) -> Self {
+        self.extra_points = points;
+        self
+    }
+
+    /// Fetch the feed from `url` instead of the QFES feed.
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_owned();
+        self
+    }
+
+    /// Fail polls where a page of the feed is larger than `max_size` bytes.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Notify about entries within `shape` around the reference point.
+    pub fn with_alert_shape(mut self, shape: AlertShape) -> Self {
+        self.shape = shape;
+        self
+    }
+}
+
+impl Feed for QfesFeed {
+    fn check(&self, notify_near: LatLong) -> Result<Vec<Entry>, BushfireError> {
+        let mut points = vec![notify_near];
+        points.extend_from_slice(&self.extra_points);
+        check_url(
+            &self.agent,
+            &self.url,
+            &points,
+            self.shape,
+            self.prefilter,
+            &self.content_types,
+            self.max_size,
+        )
+    }
+}
+
+/// Fetches feed documents.
+pub trait Transport {
+    /// Fetch `url`, returning the body and `Content-Type` header if present.
+    ///
+    /// Fails with [BushfireError::TooLarge] if the body is larger than `max_size` bytes.
+    fn get(&self, url: &str, max_size: u64) -> Result<(Vec<u8>, Option<String>), BushfireError>;
+}
+
+impl Transport for Agent {
+    fn get(&self, url: &str, max_size: u64) -> Result<(Vec<u8>, Option<String>), BushfireError> {
+        let response = Agent::get(self, url).call()?;
+        let content_type = response.header("Content-Type").map(ToOwned::to_owned);
+        let bytes = read_limited(response.into_reader(), max_size)?;
+        Ok((bytes, content_type))
+    }
+}
+
+/// Read all of `reader`, failing if there are more than `max_size` bytes.
+fn read_limited<R: Read>(reader: R, max_size: u64) -> Result<Vec<u8>, BushfireError> {
+    let mut bytes = Vec::new();
+    reader
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > max_size {
+        return Err(BushfireError::TooLarge(max_size));
+    }
+    Ok(bytes)
+}
+
+/// Check the feed at `url` for entries near any of `points`, following `rel="next"` links to
+/// subsequent pages.
+fn check_url(
+    transport: &dyn Transport,
+    url: &str,
+    points: &[LatLong],
+    shape: AlertShape,
+    prefilter: Option<f64>,
+    content_types: &[String],
+    max_size: u64,
+) -> Result<Vec<Entry>, BushfireError> {
     let mut notify = Vec::new();
-    let doc = roxmltree::Document::parse(&body)?;
-    for node in doc.descendants() {
-        if node.is_element() && node.has_tag_name((ATOM_NS, "entry")) {
-            let entry = Entry::parse(node);
-            if entry.near(notify_near) {
-                notify.push(entry);
+    let mut next = Some(url.to_owned());
+    let mut seen = Vec::new();
+    while let Some(url) = next.take() {
+        let (bytes, content_type) = transport.get(&url, max_size)?;
+        if let Some(media_type) = content_type.as_deref().map(media_type) {
+            if !accepted_content_type(content_types, &media_type) {
+                return Err(BushfireError::ContentType(media_type));
             }
         }
+        let body = decode_body(&bytes, content_type.as_deref());
+        let page = parse_page(&body, points, shape, prefilter)?;
+        notify.extend(page.entries);
+        seen.push(url);
+
+        if seen.len() >= MAX_PAGES {
+            break;
+        }
+        next = page
+            .next
+            // Resolve relative links against the page they came from
+            .and_then(|href| Url::parse(&seen[seen.len() - 1]).ok()?.join(&href).ok())
+            .map(String::from)
+            .filter(|url| !seen.contains(url));
     }
 
     Ok(notify)
 }
 
+/// The entries near the notify points in a page of the feed.
+struct Page {
+    entries: Vec<Entry>,
+    /// Link to the next page, as it appears in the feed
+    next: Option<String>,
+}
+
+/// Parse the feed in `body` and return the entries near `notify_near`.
+#[cfg(feature = "test-endpoints")]
+pub fn parse_feed(
+    body: &str,
+    notify_near: LatLong,
+    shape: AlertShape,
+) -> Result<Vec<Entry>, BushfireError> {
+    parse_page(body, &[notify_near], shape, None).map(|page| page.entries)
+}
+
+fn parse_page(
+    body: &str,
+    points: &[LatLong],
+    shape: AlertShape,
+    prefilter: Option<f64>,
+) -> Result<Page, BushfireError> {
+    let mut page = Page {
+        entries: Vec::new(),
+        next: None,
+    };
+    let doc = match roxmltree::Document::parse(body) {
+        Ok(doc) => doc,
+        // HTML error pages often aren't well-formed XML
+        Err(_) if looks_like_html(body) => {
+            return Err(BushfireError::NotAFeed(String::from("html")))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    // Only Atom feeds are understood, an RSS feed's items would silently be ignored
+    let root = doc.root_element().tag_name().name();
+    if root != "feed" {
+        return Err(BushfireError::NotAFeed(root.to_owned()));
+    }
+    for node in doc.root_element().children() {
+        if node.has_tag_name((ATOM_NS, "link")) && node.attribute("rel") == Some("next") {
+            page.next = node.attribute("href").map(ToOwned::to_owned);
+        }
+    }
+    for node in doc.descendants() {
+        if !node.is_element() || node.tag_name().name() != "entry" {
+            continue;
+        }
+        // Matched on the namespace rather than the prefix, so any prefix bound to Atom works
+        if node.tag_name().namespace() != Some(ATOM_NS) {
+            warn!(
+                "ignoring entry element in unexpected namespace: {}",
+                node.tag_name().namespace().unwrap_or("none")
+            );
+            continue;
+        }
+        if prefilter.is_some_and(|distance| far_from_all(node, points, shape, distance)) {
+            continue;
+        }
+        trace!("feed entry: {}", &body[node.range()]);
+        let entry = Entry::parse(node);
+        // Without an id the entry can't be told apart from others that are missing one
+        if entry.id.0.is_empty() {
+            warn!(
+                "skipping feed entry without an id: {}",
+                entry.title.as_deref().unwrap_or("untitled")
+            );
+            continue;
+        }
+        if points.iter().any(|&point| entry.near(point, shape)) {
+            page.entries.push(entry);
+        }
+    }
+
+    Ok(page)
+}
+
+/// Determine if the `georss:point` of the `entry` element is more than `distance` km from all of
+/// `points`, without parsing the rest of the entry.
+///
+/// Entries without a point aren't far, nor are any for a region or, with the polygon shape,
+/// entries with an incident area as it may still contain a point.
+fn far_from_all(entry: Node, points: &[LatLong], shape: AlertShape, distance: f64) -> bool {
+    let mut georss = entry
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().namespace() == Some(GEORSS_NS));
+    match shape {
+        AlertShape::Region(_) => return false,
+        AlertShape::Polygon
+            if georss
+                .clone()
+                .any(|node| node.tag_name().name() == "polygon") =>
+        {
+            return false
+        }
+        _ => {}
+    }
+    let point = georss
+        .find(|node| node.tag_name().name() == "point")
+        .and_then(|node| node.text())
+        .and_then(|text| {
+            let mut coords = parse_coords(text);
+            Some((coords.next()?, coords.next()?))
+        });
+    point.is_some_and(|point| {
+        points
+            .iter()
+            .all(|&reference| distance_km(reference, point) > distance)
+    })
+}
+
+/// Determine if `body` appears to be an HTML document.
+fn looks_like_html(body: &str) -> bool {
+    let start = body.trim_start();
+    let start = start.get(..14).unwrap_or(start).to_ascii_lowercase();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// Decode the feed body into a string.
+///
+/// The encoding is taken from the `Content-Type` charset, then the XML declaration, falling back
+/// to UTF-8. Any byte sequences that are invalid in that encoding are replaced.
+fn decode_body<'a>(bytes: &'a [u8], content_type: Option<&str>) -> Cow<'a, str> {
+    let encoding = content_type
+        .and_then(charset_param)
+        .or_else(|| xml_declared_encoding(bytes))
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    let (body, _encoding, _had_errors) = encoding.decode(bytes);
+    body
+}
+
+/// The media type of a `Content-Type` header value, without parameters and in lowercase.
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Determine if `media_type` is one of `accepted`, or [DEFAULT_CONTENT_TYPES] if it's empty.
+///
+/// [GENERIC_CONTENT_TYPES] are also accepted by default, leaving the root element to decide.
+fn accepted_content_type(accepted: &[String], media_type: &str) -> bool {
+    if accepted.is_empty() {
+        DEFAULT_CONTENT_TYPES.contains(&media_type) || GENERIC_CONTENT_TYPES.contains(&media_type)
+    } else {
+        accepted
+            .iter()
+            .any(|accepted| accepted.eq_ignore_ascii_case(media_type))
+    }
+}
+
+/// Extract the charset parameter from a `Content-Type` header value.
+fn charset_param(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Extract the encoding from the XML declaration, if present.
+fn xml_declared_encoding(bytes: &[u8]) -> Option<&str> {
+    // The declaration must be ASCII compatible for us to find it, which covers the encodings
+    // likely to be used.
+    let end = bytes.iter().take(256).position(|&b| b == b'>')?;
+    let declaration = std::str::from_utf8(&bytes[..end]).ok()?;
+    if !declaration.starts_with("<?xml") {
+        return None;
+    }
+    let (_, rest) = declaration.split_once("encoding")?;
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let rest = &rest[1..];
+    rest.find(quote).map(|end| &rest[..end])
+}
+
+impl Link {
+    /// Determine if this links to an HTML version of the entry.
+    fn is_html_alternate(&self) -> bool {
+        let html = self.media_type.as_deref().is_some_and(|media_type| {
+            let essence = media_type.split(';').next().unwrap_or_default();
+            essence.trim().eq_ignore_ascii_case("text/html")
+        });
+        html && self.rel.as_deref().unwrap_or("alternate") == "alternate"
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of entries fully parsed on this thread.
+    static PARSED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 impl Entry {
     fn parse(node: Node) -> Entry {
+        #[cfg(test)]
+        PARSED.with(|parsed| parsed.set(parsed.get() + 1));
         let mut entry = Entry::default();
+        let (mut geo_lat, mut geo_long) = (None, None);
         for node in node.descendants() {
             if node.is_element() {
                 let tag_name = node.tag_name();
                 match (tag_name.name(), tag_name.namespace()) {
                     ("category", Some(ATOM_NS)) => {
-                        entry.category = node.attribute("term").map(ToOwned::to_owned);
+                        if let Some(term) = node.attribute("term") {
+                            entry.categories.push(term.to_owned());
+                        }
                     }
                     ("content", Some(ATOM_NS)) => {
-                        entry.content = node.text().map(ToOwned::to_owned)
+                        entry.content = match node.attribute("type") {
+                            Some("html") => node.text().map(html_to_text),
+                            _ => node.text().map(ToOwned::to_owned),
+                        }
+                    }
+                    ("summary", Some(ATOM_NS)) => {
+                        entry.summary = node.text().map(ToOwned::to_owned)
                     }
                     ("id", Some(ATOM_NS)) => {
                         if let Some(text) = node.text() {
@@ -84,43 +512,310 @@ impl Entry {
                     }
                     ("published", Some(ATOM_NS)) => {
                         if let Some(text) = node.text() {
-                            entry.published = OffsetDateTime::parse(text, &Rfc3339).ok();
+                            entry.published = parse_timestamp(text);
                         }
                     }
                     ("title", Some(ATOM_NS)) => entry.title = node.text().map(ToOwned::to_owned),
+                    ("link", Some(ATOM_NS)) => {
+                        if let Some(href) = node.attribute("href") {
+                            entry.links.push(Link {
+                                href: href.to_owned(),
+                                rel: node.attribute("rel").map(ToOwned::to_owned),
+                                media_type: node.attribute("type").map(ToOwned::to_owned),
+                            });
+                        }
+                    }
                     ("updated", Some(ATOM_NS)) => {
                         if let Some(text) = node.text() {
-                            entry.updated = OffsetDateTime::parse(text, &Rfc3339).ok();
+                            entry.updated = parse_timestamp(text);
                         }
                     }
                     ("point", Some(GEORSS_NS)) => {
                         if let Some(text) = node.text() {
-                            let mut coords = text
-                                .trim()
-                                .split(' ')
-                                .flat_map(|val| val.parse::<f64>().ok());
+                            let mut coords = parse_coords(text);
                             if let (Some(lat), Some(long)) = (coords.next(), coords.next()) {
                                 entry.point = Some((lat, long));
                             }
                         }
                     }
+                    ("polygon", Some(GEORSS_NS)) => {
+                        if let Some(text) = node.text() {
+                            let mut coords = parse_coords(text);
+                            while let (Some(lat), Some(long)) = (coords.next(), coords.next()) {
+                                entry.polygon.push((lat, long));
+                            }
+                        }
+                    }
+                    (name, Some(GEORSS_NS)) => {
+                        let text = node.text().unwrap_or_default().trim();
+                        entry.extra.insert(name.to_owned(), text.to_owned());
+                    }
+                    ("lat", Some(GEO_NS)) => {
+                        geo_lat = node.text().and_then(|text| text.trim().parse().ok())
+                    }
+                    ("long", Some(GEO_NS)) => {
+                        geo_long = node.text().and_then(|text| text.trim().parse().ok())
+                    }
                     _ => {}
                 }
             }
         }
+        // georss:point takes precedence if both are present
+        if let (None, Some(lat), Some(long)) = (entry.point, geo_lat, geo_long) {
+            entry.point = Some((lat, long));
+        }
 
         entry
     }
 
-    /// Determine if the point in `self` is near the supplied `reference` point.
-    fn near(&self, reference: LatLong) -> bool {
+    /// The link to the entry, preferring an HTML alternate version over the first link.
+    pub fn link(&self) -> Option<&str> {
+        self.links
+            .iter()
+            .find(|link| link.is_html_alternate())
+            .or(self.links.first())
+            .map(|link| link.href.as_str())
+    }
+
+    /// The category describing the alert level of this entry.
+    ///
+    /// This is the first recognised alert level, falling back to the first category.
+    pub fn category(&self) -> Option<&str> {
+        self.categories
+            .iter()
+            .find(|category| ALERT_LEVELS.iter().any(|level| category.starts_with(level)))
+            .or(self.categories.first())
+            .map(String::as_str)
+    }
+
+    /// A hash of the entry's title and content that is stable across restarts.
+    pub fn content_hash(&self) -> u64 {
+        // FNV-1a, as the standard library's hashers may change between releases
+        let title = self.title.as_deref().unwrap_or_default();
+        let content = self.content.as_deref().or(self.summary.as_deref());
+        [
+            title.as_bytes(),
+            &[0],
+            content.unwrap_or_default().as_bytes(),
+        ]
+        .concat()
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    /// The alert level of this entry, derived from its category.
+    pub fn severity(&self) -> Severity {
+        match self.category() {
+            Some(category) if category.starts_with("Emergency") => Severity::Emergency,
+            Some(category) if category.starts_with("Watch and Act") => Severity::WatchAndAct,
+            Some(category) if category.starts_with("Advice") => Severity::Advice,
+            _ => Severity::Unknown,
+        }
+    }
+
+    /// Determine if this entry is an emergency warning, the highest alert level.
+    pub fn is_emergency(&self) -> bool {
+        self.severity() == Severity::Emergency
+    }
+
+    /// Determine if `self` is within the alert region `shape` around the `reference` point.
+    fn near(&self, reference: LatLong, shape: AlertShape) -> bool {
+        if shape == AlertShape::Polygon && self.polygon.len() >= 3 {
+            return polygon_contains(&self.polygon, reference);
+        }
         // If we don't know where this entry is then just assume it is nearby to be safe.
-        self.point
-            .map_or(true, |point| near(reference, point, ALERT_DISTANCE))
+        self.point.is_none_or(|point| match shape {
+            AlertShape::Box => near(reference, point, ALERT_DISTANCE),
+            AlertShape::Region(region) => region.contains(point),
+            AlertShape::Circle | AlertShape::Polygon => {
+                within_radius(reference, point, ALERT_DISTANCE)
+            }
+        })
+    }
+}
+
+/// Parse a timestamp, which should be RFC 3339 but RFC 2822 and other ISO 8601 forms are seen too.
+fn parse_timestamp(text: &str) -> Option<OffsetDateTime> {
+    let text = text.trim();
+    let timestamp = OffsetDateTime::parse(text, &Rfc3339)
+        .or_else(|_| OffsetDateTime::parse(text, &Rfc2822))
+        .or_else(|_| OffsetDateTime::parse(text, &Iso8601::DEFAULT));
+    if timestamp.is_err() {
+        debug!("unable to parse timestamp '{text}'");
     }
+    timestamp.ok()
 }
 
-/// Distance from reference point to edge of square representing the alert region
+/// Convert HTML content to plain text.
+///
+/// Entities are decoded before and after removing tags so markup that was escaped twice, such as
+/// `&lt;p&gt;` inside CDATA, is removed too.
+fn html_to_text(html: &str) -> String {
+    let text = strip_tags(&decode_entities(html));
+    decode_entities(&text)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Remove tags from `html`, ending lines at block level elements.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim_start_matches('/');
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        if ["br", "p", "div", "li"]
+            .iter()
+            .any(|block| name.eq_ignore_ascii_case(block))
+        {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Decode character references in `html`, leaving unknown ones as they are.
+fn decode_entities(html: &str) -> Cow<'_, str> {
+    if !html.contains('&') {
+        return Cow::Borrowed(html);
+    }
+
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('&') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                name => {
+                    let code = name.strip_prefix('#')?;
+                    let code = match code.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => code.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((end, c))
+        });
+        match decoded {
+            Some((end, c)) => {
+                text.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                text.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    Cow::Owned(text)
+}
+
+/// Parse a list of coordinates.
+///
+/// GeoRSS specifies whitespace separated values but some feeds use commas, or pad the values with
+/// extra whitespace.
+fn parse_coords(text: &str) -> impl Iterator<Item = f64> + '_ {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|val| !val.is_empty())
+        .flat_map(|val| val.parse::<f64>().ok())
+}
+
+/// Determine if `point` is inside `polygon` using ray casting.
+fn polygon_contains(polygon: &[LatLong], point: LatLong) -> bool {
+    let (y, x) = point;
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (yi, xi) = polygon[i];
+        let (yj, xj) = polygon[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+impl BoundingBox {
+    /// Determine if `point` is within the box, including on its edges.
+    pub fn contains(&self, point: LatLong) -> bool {
+        (self.min.0..=self.max.0).contains(&point.0) && (self.min.1..=self.max.1).contains(&point.1)
+    }
+
+    /// The point midway between the corners.
+    pub fn centre(&self) -> LatLong {
+        (
+            (self.min.0 + self.max.0) / 2.,
+            (self.min.1 + self.max.1) / 2.,
+        )
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    /// Parse `minlat,minlong,maxlat,maxlong`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords = s
+            .split(',')
+            .map(|coord| coord.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid coordinate in '{s}': {err}"))?;
+        let [min_lat, min_long, max_lat, max_long] = coords[..] else {
+            return Err(format!(
+                "expected minlat,minlong,maxlat,maxlong but found '{s}'"
+            ));
+        };
+        if min_lat > max_lat || min_long > max_long {
+            return Err(format!("minimum is greater than maximum in '{s}'"));
+        }
+        Ok(BoundingBox {
+            min: (min_lat, min_long),
+            max: (max_lat, max_long),
+        })
+    }
+}
+
+impl FromStr for AlertShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(AlertShape::Box),
+            "circle" => Ok(AlertShape::Circle),
+            "polygon" => Ok(AlertShape::Polygon),
+            _ => Err(format!(
+                "unknown alert shape '{s}', expected box, circle, or polygon"
+            )),
+        }
+    }
+}
+
+/// Distance from reference point to edge of the alert region, shown here as a box
 ///
 /// ```text
 /// |--------| ALERT_DISTANCE
@@ -133,19 +828,62 @@ impl Entry {
 /// |                 |
 /// +-----------------+
 /// ```
-const ALERT_DISTANCE: f64 = 10.0;
+pub const ALERT_DISTANCE: f64 = 10.0;
+
+/// Distances within this many kilometres (1 µm) of the alert distance count as on the boundary,
+/// so rounding doesn't decide whether a point at exactly [ALERT_DISTANCE] is near.
+const BOUNDARY_TOLERANCE: f64 = 1e-9;
+
+/// Media types the feed is accepted as unless configured otherwise.
+pub const DEFAULT_CONTENT_TYPES: &[&str] = &["application/atom+xml", "application/xml", "text/xml"];
+
+/// Media types that say nothing about the content, as object stores such as S3 may serve the feed.
+const GENERIC_CONTENT_TYPES: &[&str] = &["binary/octet-stream", "application/octet-stream"];
+
+/// Largest page of the feed that will be read, in bytes.
+pub const DEFAULT_MAX_FEED_SIZE: u64 = 10 * 1024 * 1024;
 
 /// Construct a box around `reference` and then see of it contains `point`.
 ///
-/// This is done crudely and assumes that the offsets applied to the reference point won't
-/// under/overflow or cross zero.
+/// This is done crudely and assumes that the offsets applied to the reference point won't wrap
+/// around the poles or the antimeridian. Crossing the equator or prime meridian is fine as the
+/// coordinates are signed.
 fn near(reference: LatLong, point: LatLong, alert_distance: f64) -> bool {
     // 0.1 is 11.1 km https://gis.stackexchange.com/a/8655
     let offset = alert_distance / 111.;
     let top_left = (point.0 - offset, point.1 - offset);
     let bottom_right = (point.0 + offset, point.1 + offset);
-    (top_left.0..bottom_right.0).contains(&reference.0)
-        && (top_left.1..bottom_right.1).contains(&reference.1)
+    (top_left.0..=bottom_right.0).contains(&reference.0)
+        && (top_left.1..=bottom_right.1).contains(&reference.1)
+}
+
+/// Determine if `point` is within `radius` km of `reference`, including on the boundary.
+fn within_radius(reference: LatLong, point: LatLong, radius: f64) -> bool {
+    distance_km(reference, point) <= radius + BOUNDARY_TOLERANCE
+}
+
+/// Great-circle distance between two points in kilometres, using the haversine formula.
+pub fn distance_km(a: LatLong, b: LatLong) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+    let (lat_a, lat_b) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.1 - a.1).to_radians();
+    let h = (d_lat / 2.).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.).sin().powi(2);
+    2. * EARTH_RADIUS_KM * h.sqrt().asin()
+}
+
+/// Sort `entries` nearest to `reference` first, with entries at unknown points last.
+pub fn sort_by_distance(entries: &mut [Entry], reference: LatLong) {
+    entries.sort_by(|a, b| {
+        let a = a.point.map(|point| distance_km(reference, point));
+        let b = b.point.map(|point| distance_km(reference, point));
+        match (a, b) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    });
 }
 
 impl From<roxmltree::Error> for BushfireError {
@@ -156,7 +894,7 @@ impl From<roxmltree::Error> for BushfireError {
 
 impl From<ureq::Error> for BushfireError {
     fn from(err: ureq::Error) -> Self {
-        BushfireError::Http(err)
+        BushfireError::Http(Box::new(err))
     }
 }
 
@@ -178,6 +916,18 @@ impl fmt::Display for BushfireError {
             BushfireError::Io(err) => {
                 write!(f, "I/O error: {err}")
             }
+            BushfireError::TooLarge(max_size) => {
+                write!(f, "feed is larger than the maximum of {max_size} bytes")
+            }
+            BushfireError::NotAFeed(root) => {
+                write!(f, "response is not a feed, found a <{root}> document")
+            }
+            BushfireError::ContentType(media_type) => {
+                write!(
+                    f,
+                    "response is not a feed, unexpected content type {media_type}"
+                )
+            }
         }
     }
 }
@@ -186,6 +936,8 @@ impl std::error::Error for BushfireError {}
 
 #[cfg(test)]
 mod tests {
+    use time::macros::datetime;
+
     use super::*;
 
     #[test]
@@ -199,6 +951,756 @@ mod tests {
         assert!(!near(brisbane, noosa, 50.));
     }
 
+    /// Serves feed pages from memory.
+    struct Pages(HashMap<&'static str, &'static str>);
+
+    impl Transport for Pages {
+        fn get(
+            &self,
+            url: &str,
+            max_size: u64,
+        ) -> Result<(Vec<u8>, Option<String>), BushfireError> {
+            let body = self.0.get(url).ok_or_else(|| {
+                BushfireError::Io(io::Error::new(io::ErrorKind::NotFound, url.to_owned()))
+            })?;
+            Ok((read_limited(body.as_bytes(), max_size)?, None))
+        }
+    }
+
+    #[test]
+    fn check_follows_next_link() {
+        let first = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <link rel="next" href="page2.xml"/>
+    <entry>
+        <id>IF39-1</id>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </entry>
+</feed>"#;
+        let second = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <link rel="next" href="http://example.com/feed/page1.xml"/>
+    <entry>
+        <id>IF39-2</id>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </entry>
+</feed>"#;
+        let pages = Pages(HashMap::from([
+            ("http://example.com/feed/page1.xml", first),
+            ("http://example.com/feed/page2.xml", second),
+        ]));
+
+        // The link back to the first page isn't followed
+        let entries = check_url(
+            &pages,
+            "http://example.com/feed/page1.xml",
+            &[(-27.46844, 153.02334)],
+            AlertShape::Circle,
+            None,
+            &[],
+            DEFAULT_MAX_FEED_SIZE,
+        )
+        .unwrap();
+        let ids: Vec<_> = entries.iter().map(|entry| entry.id.0.as_str()).collect();
+        assert_eq!(ids, ["IF39-1", "IF39-2"]);
+    }
+
+    #[test]
+    fn skip_entry_without_id() {
+        let feed = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <title>No id</title>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </entry>
+    <entry>
+        <id></id>
+        <title>Empty id</title>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </entry>
+    <entry>
+        <id>IF40</id>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </entry>
+</feed>"#;
+        let pages = Pages(HashMap::from([("http://example.com/feed.xml", feed)]));
+
+        // Entries without an id never reach the monitor so can't be notified or recorded
+        let entries = check_url(
+            &pages,
+            "http://example.com/feed.xml",
+            &[(-27.46844, 153.02334)],
+            AlertShape::Circle,
+            None,
+            &[],
+            DEFAULT_MAX_FEED_SIZE,
+        )
+        .unwrap();
+        let ids: Vec<_> = entries.iter().map(|entry| entry.id.0.as_str()).collect();
+        assert_eq!(ids, ["IF40"]);
+    }
+
+    #[test]
+    fn multiple_points() {
+        let feed = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF44</id>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </entry>
+    <entry>
+        <id>IF45</id>
+        <georss:point>-26.65 153.06</georss:point>
+    </entry>
+    <entry>
+        <id>IF46</id>
+        <georss:point>-19.26 146.81</georss:point>
+    </entry>
+</feed>"#;
+        let ids = |points: &[LatLong]| -> Vec<String> {
+            parse_page(feed, points, AlertShape::Circle, None)
+                .unwrap()
+                .entries
+                .into_iter()
+                .map(|entry| entry.id.0)
+                .collect()
+        };
+        assert_eq!(ids(&[(-27.46844, 153.02334)]), ["IF44"]);
+        assert_eq!(
+            ids(&[(-27.46844, 153.02334), (-26.65, 153.06)]),
+            ["IF44", "IF45"]
+        );
+    }
+
+    #[test]
+    fn prefilter_skips_distant_entries() {
+        let feed = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF55</id>
+        <georss:point>-27.47 153.03</georss:point>
+    </entry>
+    <entry>
+        <id>IF56</id>
+        <georss:point>-19.26 146.81</georss:point>
+    </entry>
+    <entry>
+        <id>IF57</id>
+    </entry>
+</feed>"#;
+        let check = |prefilter| {
+            PARSED.with(|parsed| parsed.set(0));
+            let ids: Vec<_> = parse_page(
+                feed,
+                &[(-27.46844, 153.02334)],
+                AlertShape::Circle,
+                prefilter,
+            )
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|entry| entry.id.0)
+            .collect();
+            (ids, PARSED.with(|parsed| parsed.get()))
+        };
+        assert_eq!(
+            check(None),
+            (vec![String::from("IF55"), String::from("IF57")], 3)
+        );
+        // The distant entry isn't parsed, the one without a point still is
+        assert_eq!(
+            check(Some(50.)),
+            (vec![String::from("IF55"), String::from("IF57")], 2)
+        );
+    }
+
+    #[test]
+    fn prefixed_atom_namespace() {
+        use crate::logging::{self, Level};
+
+        let feed = r#"<atom:feed xmlns:atom="http://www.w3.org/2005/Atom" xmlns:georss="http://www.georss.org/georss">
+    <atom:entry>
+        <atom:id>IF41</atom:id>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </atom:entry>
+    <a:entry xmlns:a="http://www.w3.org/2005/Atom">
+        <a:id>IF42</a:id>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </a:entry>
+    <entry>
+        <id>IF43</id>
+        <georss:point>-27.46844 153.02334</georss:point>
+    </entry>
+</atom:feed>"#;
+        let mut entries = Vec::new();
+        let lines = logging::capture(Level::Info, || {
+            entries = parse_page(feed, &[(-27.46844, 153.02334)], AlertShape::Circle, None)
+                .unwrap()
+                .entries;
+        });
+        let ids: Vec<_> = entries.iter().map(|entry| entry.id.0.as_str()).collect();
+        assert_eq!(ids, ["IF41", "IF42"]);
+        assert_eq!(
+            lines,
+            ["WARN: ignoring entry element in unexpected namespace: none"]
+        );
+    }
+
+    #[test]
+    fn unexpected_content_type() {
+        /// Serves a feed with the given `Content-Type`.
+        struct Typed(&'static str);
+
+        impl Transport for Typed {
+            fn get(
+                &self,
+                _url: &str,
+                _max_size: u64,
+            ) -> Result<(Vec<u8>, Option<String>), BushfireError> {
+                let feed = r#"<feed xmlns="http://www.w3.org/2005/Atom"><entry><id>IF58</id></entry></feed>"#;
+                Ok((feed.as_bytes().to_vec(), Some(self.0.to_owned())))
+            }
+        }
+
+        let check = |content_type, accepted: &[String]| {
+            check_url(
+                &Typed(content_type),
+                "http://example.com/feed.xml",
+                &[(-27.46844, 153.02334)],
+                AlertShape::Circle,
+                None,
+                accepted,
+                DEFAULT_MAX_FEED_SIZE,
+            )
+        };
+        assert!(check("application/atom+xml; charset=utf-8", &[]).is_ok());
+        assert!(check("Text/XML", &[]).is_ok());
+
+        let err = check("application/json", &[]).unwrap_err();
+        assert!(
+            matches!(&err, BushfireError::ContentType(media_type) if media_type == "application/json")
+        );
+        assert_eq!(
+            err.to_string(),
+            "response is not a feed, unexpected content type application/json"
+        );
+        let err = check("text/html;charset=UTF-8", &[]).unwrap_err();
+        assert!(
+            matches!(&err, BushfireError::ContentType(media_type) if media_type == "text/html")
+        );
+
+        let accepted = [String::from("text/plain")];
+        assert!(check("text/plain", &accepted).is_ok());
+        assert!(check("application/atom+xml", &accepted).is_err());
+
+        // Generic types are left to the root element check, unless a list is configured
+        assert!(check("binary/octet-stream", &[]).is_ok());
+        assert!(check("application/octet-stream", &[]).is_ok());
+        assert!(check("application/octet-stream", &accepted).is_err());
+        assert!(check("application/rss+xml", &[]).is_err());
+    }
+
+    #[test]
+    fn feed_size_cap() {
+        let pages = Pages(HashMap::from([("http://example.com/feed.xml", "<feed/>")]));
+        let check = |max_size| {
+            check_url(
+                &pages,
+                "http://example.com/feed.xml",
+                &[(-27.46844, 153.02334)],
+                AlertShape::Circle,
+                None,
+                &[],
+                max_size,
+            )
+        };
+        assert!(check(7).is_ok());
+        let err = check(6).unwrap_err();
+        assert!(matches!(err, BushfireError::TooLarge(6)));
+        assert_eq!(
+            err.to_string(),
+            "feed is larger than the maximum of 6 bytes"
+        );
+    }
+
+    #[test]
+    fn agent_reused_across_polls() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = Arc::clone(&connections);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                // Serve requests on the connection until the client closes it
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    if !line.trim().is_empty() {
+                        continue;
+                    }
+                    let body = "<feed/>";
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .unwrap();
+                }
+            }
+        });
+
+        let feed = QfesFeed::new(ureq::agent()).with_url(&format!("http://{addr}/feed.xml"));
+        feed.check((-27.46844, 153.02334)).unwrap();
+        feed.check((-27.46844, 153.02334)).unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn html_error_page() {
+        let maintenance = "<!DOCTYPE html>\n<html><head><title>Maintenance</title></head><body><p>Back soon<br></body></html>";
+        let xhtml =
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\"><body><p>Back soon</p></body></html>";
+        let rss =
+            "<rss version=\"2.0\"><channel><item><title>Incident</title></item></channel></rss>";
+        let pages = Pages(HashMap::from([
+            ("http://example.com/maintenance", maintenance),
+            ("http://example.com/xhtml", xhtml),
+            ("http://example.com/rss", rss),
+        ]));
+        let check = |url| {
+            check_url(
+                &pages,
+                url,
+                &[(-27.46844, 153.02334)],
+                AlertShape::Circle,
+                None,
+                &[],
+                DEFAULT_MAX_FEED_SIZE,
+            )
+        };
+
+        let err = check("http://example.com/maintenance").unwrap_err();
+        assert!(matches!(&err, BushfireError::NotAFeed(root) if root == "html"));
+        assert_eq!(
+            err.to_string(),
+            "response is not a feed, found a <html> document"
+        );
+        let err = check("http://example.com/xhtml").unwrap_err();
+        assert!(matches!(&err, BushfireError::NotAFeed(root) if root == "html"));
+        let err = check("http://example.com/rss").unwrap_err();
+        assert!(matches!(&err, BushfireError::NotAFeed(root) if root == "rss"));
+    }
+
+    #[test]
+    fn distance_between_cities() {
+        let brisbane = (-27.4698, 153.0251);
+        let sydney = (-33.8688, 151.2093);
+        let melbourne = (-37.8136, 144.9631);
+        let london = (51.5074, -0.1278);
+        let paris = (48.8566, 2.3522);
+
+        // Published great-circle distances
+        for (a, b, expected) in [
+            (brisbane, sydney, 732.),
+            (sydney, melbourne, 713.),
+            (london, paris, 344.),
+        ] {
+            let distance = distance_km(a, b);
+            assert!((distance - expected).abs() < 5., "{distance} != {expected}");
+            assert_eq!(distance, distance_km(b, a));
+        }
+        assert_eq!(distance_km(brisbane, brisbane), 0.);
+    }
+
+    #[test]
+    fn sort_nearest_first() {
+        let brisbane = (-27.46844, 153.02334);
+        let entry = |id: &str, point| Entry {
+            id: EntryId(id.to_string()),
+            point,
+            ..Default::default()
+        };
+        let mut entries = vec![
+            entry("unknown", None),
+            entry("noosa", Some((-26.400054, 153.0223421))),
+            entry("ocean-view", Some((-27.127664662091, 152.87902054721))),
+            entry("brisbane", Some(brisbane)),
+        ];
+        sort_by_distance(&mut entries, brisbane);
+        let ids: Vec<_> = entries.iter().map(|entry| entry.id.0.as_str()).collect();
+        assert_eq!(ids, ["brisbane", "ocean-view", "noosa", "unknown"]);
+    }
+
+    #[test]
+    fn alert_shapes() {
+        let brisbane = (-27.46844, 153.02334);
+        // About 12 km north east, inside the corner of the box but outside the circle
+        let mut entry = Entry {
+            point: Some((-27.38844, 153.10334)),
+            ..Default::default()
+        };
+        assert!(entry.near(brisbane, AlertShape::Box));
+        assert!(!entry.near(brisbane, AlertShape::Circle));
+        // Without an incident area polygon falls back to the circle
+        assert!(!entry.near(brisbane, AlertShape::Polygon));
+
+        // An incident area that covers the reference point
+        entry.polygon = vec![
+            (-27.3, 152.9),
+            (-27.3, 153.2),
+            (-27.6, 153.2),
+            (-27.6, 152.9),
+        ];
+        assert!(entry.near(brisbane, AlertShape::Box));
+        assert!(!entry.near(brisbane, AlertShape::Circle));
+        assert!(entry.near(brisbane, AlertShape::Polygon));
+
+        // One that doesn't, even though the point is within the box
+        entry.polygon = vec![(-27.3, 153.05), (-27.3, 153.2), (-27.45, 153.2)];
+        assert!(!entry.near(brisbane, AlertShape::Polygon));
+    }
+
+    #[test]
+    fn region_shape() {
+        let region: BoundingBox = "-27.5,152.75,-27.25,153.25".parse().unwrap();
+        assert_eq!(region.centre(), (-27.375, 153.0));
+        let shape = AlertShape::Region(region);
+        // The reference point doesn't matter for a region
+        let reference = (0., 0.);
+        let inside = Entry {
+            point: Some((-27.46844, 153.02334)),
+            ..Default::default()
+        };
+        assert!(inside.near(reference, shape));
+        let edge = Entry {
+            point: Some((-27.25, 153.25)),
+            ..Default::default()
+        };
+        assert!(edge.near(reference, shape));
+        let outside = Entry {
+            point: Some((-26.400054, 153.0223421)),
+            ..Default::default()
+        };
+        assert!(!outside.near(reference, shape));
+
+        assert!("-27.3,152.9,-27.6,153.2".parse::<BoundingBox>().is_err());
+        assert!("-27.6,152.9,-27.3".parse::<BoundingBox>().is_err());
+        assert!("-27.6,152.9,-27.3,east".parse::<BoundingBox>().is_err());
+    }
+
+    #[test]
+    fn point_on_boundary() {
+        let brisbane = (-27.46844, 153.02334);
+        // Exactly ALERT_DISTANCE due north, along the great circle
+        let degrees = (ALERT_DISTANCE / 6371.0).to_degrees();
+        let north = (brisbane.0 + degrees, brisbane.1);
+        assert!((distance_km(brisbane, north) - ALERT_DISTANCE).abs() < 1e-9);
+        let entry = Entry {
+            point: Some(north),
+            ..Default::default()
+        };
+        assert!(entry.near(brisbane, AlertShape::Circle));
+        let beyond = Entry {
+            point: Some((north.0 + 0.00001, north.1)),
+            ..Default::default()
+        };
+        assert!(!beyond.near(brisbane, AlertShape::Circle));
+
+        // Both edges of the box are included
+        let offset = ALERT_DISTANCE / 111.;
+        let point = (-27.5, 153.);
+        assert!(near((point.0 - offset, point.1), point, ALERT_DISTANCE));
+        assert!(near((point.0 + offset, point.1), point, ALERT_DISTANCE));
+        assert!(near((point.0, point.1 - offset), point, ALERT_DISTANCE));
+        assert!(near((point.0, point.1 + offset), point, ALERT_DISTANCE));
+    }
+
+    #[test]
+    fn near_across_equator() {
+        // About 4.5 km apart, on opposite sides of the equator and prime meridian
+        let reference = (-0.02, -0.01);
+        let entry = Entry {
+            point: Some((0.02, 0.01)),
+            ..Default::default()
+        };
+        assert!(near(reference, entry.point.unwrap(), ALERT_DISTANCE));
+        assert!(entry.near(reference, AlertShape::Box));
+        assert!(entry.near(reference, AlertShape::Circle));
+
+        let far = Entry {
+            point: Some((0.1, 0.)),
+            ..Default::default()
+        };
+        assert!(!far.near(reference, AlertShape::Box));
+        assert!(!far.near(reference, AlertShape::Circle));
+    }
+
+    #[test]
+    fn parse_polygon() {
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <georss:polygon>-27.3 152.9 -27.3 153.2 -27.6 153.2 -27.3 152.9</georss:polygon>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(
+            entry.polygon,
+            [
+                (-27.3, 152.9),
+                (-27.3, 153.2),
+                (-27.6, 153.2),
+                (-27.3, 152.9)
+            ]
+        );
+        assert!(!entry.extra.contains_key("polygon"));
+    }
+
+    fn parse_first_entry(xml: &str) -> Entry {
+        let doc = roxmltree::Document::parse(xml).unwrap();
+        let node = doc
+            .descendants()
+            .find(|node| node.is_element() && node.has_tag_name((ATOM_NS, "entry")))
+            .unwrap();
+        Entry::parse(node)
+    }
+
+    #[test]
+    fn parse_point_comma_separated() {
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <georss:point>-27.584701903466,151.06082028616</georss:point>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(entry.point, Some((-27.584701903466, 151.06082028616)));
+    }
+
+    #[test]
+    fn parse_point_extra_whitespace() {
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <georss:point>
+            -27.584701903466    151.06082028616	0
+        </georss:point>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(entry.point, Some((-27.584701903466, 151.06082028616)));
+    }
+
+    #[test]
+    fn decode_non_utf8_body() {
+        // "Café" in ISO-8859-1
+        let feed: &[u8] = b"<feed xmlns=\"http://www.w3.org/2005/Atom\"><entry><id>IF39-1</id><title>Caf\xe9</title></entry></feed>";
+        let declared = [
+            &b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\n"[..],
+            feed,
+        ]
+        .concat();
+
+        let body = decode_body(&declared, None);
+        let entry = parse_first_entry(&body);
+        assert_eq!(entry.title.as_deref(), Some("Café"));
+
+        let body = decode_body(feed, Some("application/atom+xml; charset=iso-8859-1"));
+        let entry = parse_first_entry(&body);
+        assert_eq!(entry.title.as_deref(), Some("Café"));
+
+        // Falls back to UTF-8
+        let body = decode_body("<feed>Café</feed>".as_bytes(), Some("text/xml"));
+        assert_eq!(body, "<feed>Café</feed>");
+    }
+
+    #[test]
+    fn parse_w3c_geo() {
+        let xml = r#"<feed xmlns:geo="http://www.w3.org/2003/01/geo/wgs84_pos#" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <geo:lat> -27.584701903466 </geo:lat>
+        <geo:long>151.06082028616</geo:long>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(entry.point, Some((-27.584701903466, 151.06082028616)));
+
+        // Both are needed
+        let xml = r#"<feed xmlns:geo="http://www.w3.org/2003/01/geo/wgs84_pos#" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <geo:lat>-27.584701903466</geo:lat>
+    </entry>
+</feed>"#;
+        assert_eq!(parse_first_entry(xml).point, None);
+    }
+
+    #[test]
+    fn parse_extra_georss() {
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <georss:point>-27.584701903466 151.06082028616</georss:point>
+        <georss:elev> 412 </georss:elev>
+        <georss:featureName>Kumbarilla State Forest</georss:featureName>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(entry.extra.get("elev").map(String::as_str), Some("412"));
+        assert_eq!(
+            entry.extra.get("featureName").map(String::as_str),
+            Some("Kumbarilla State Forest")
+        );
+        assert!(!entry.extra.contains_key("point"));
+    }
+
+    #[test]
+    fn alert_level_category() {
+        let entry = Entry {
+            categories: vec!["Bushfire".to_string(), "Emergency Warning".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(entry.category(), Some("Emergency Warning"));
+        assert!(entry.is_emergency());
+
+        let entry = Entry {
+            categories: vec!["Bushfire".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(entry.category(), Some("Bushfire"));
+        assert_eq!(Entry::default().category(), None);
+    }
+
+    #[test]
+    fn severity_from_category() {
+        let severity = |categories: &[&str]| {
+            Entry {
+                categories: categories.iter().map(|&c| c.to_string()).collect(),
+                ..Default::default()
+            }
+            .severity()
+        };
+        assert_eq!(severity(&["Emergency Warning"]), Severity::Emergency);
+        assert_eq!(severity(&["Watch and Act"]), Severity::WatchAndAct);
+        assert_eq!(severity(&["Advice"]), Severity::Advice);
+        assert_eq!(severity(&["Bushfire", "Advice"]), Severity::Advice);
+        assert_eq!(severity(&["Planned Burn"]), Severity::Unknown);
+        assert_eq!(severity(&[]), Severity::Unknown);
+        assert!(Severity::Emergency < Severity::Advice);
+    }
+
+    #[test]
+    fn parse_summary_only_entry() {
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <summary>Fire near Kumbarilla</summary>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(entry.content, None);
+        assert_eq!(entry.summary.as_deref(), Some("Fire near Kumbarilla"));
+    }
+
+    #[test]
+    fn parse_timestamp_formats() {
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <published>Fri, 08 Sep 2023 17:12:08 +1000</published>
+        <updated>20230908T154100+1000</updated>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(entry.published, Some(datetime!(2023-09-08 17:12:08 +10)));
+        assert_eq!(entry.updated, Some(datetime!(2023-09-08 15:41:00 +10)));
+
+        let lines = crate::logging::capture(crate::logging::Level::Debug, || {
+            assert_eq!(parse_timestamp("yesterday"), None);
+        });
+        assert_eq!(lines, vec!["DEBUG: unable to parse timestamp 'yesterday'"]);
+    }
+
+    #[test]
+    fn trace_entry_xml() {
+        use crate::logging::{self, Level};
+
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry><id>IF39-1</id></entry>
+</feed>"#;
+        let lines = logging::capture(Level::Trace, || {
+            parse_page(xml, &[(0., 0.)], AlertShape::Circle, None).unwrap();
+        });
+        assert_eq!(
+            lines,
+            vec!["TRACE: feed entry: <entry><id>IF39-1</id></entry>"]
+        );
+
+        // Off by default
+        let lines = logging::capture(Level::Info, || {
+            parse_page(xml, &[(0., 0.)], AlertShape::Circle, None).unwrap();
+        });
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn prefer_html_alternate_link() {
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <link rel="self" href="https://example.com/IF39-1.xml"/>
+        <link rel="alternate" type="text/html" href="https://example.com/IF39-1"/>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(entry.links.len(), 2);
+        assert_eq!(entry.link(), Some("https://example.com/IF39-1"));
+
+        // Otherwise the first link is used
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <link rel="self" href="https://example.com/IF39-1.xml"/>
+        <link rel="related" type="text/html" href="https://example.com/"/>
+    </entry>
+</feed>"#;
+        assert_eq!(
+            parse_first_entry(xml).link(),
+            Some("https://example.com/IF39-1.xml")
+        );
+        assert_eq!(Entry::default().link(), None);
+    }
+
+    #[test]
+    fn parse_html_content() {
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <content type="html">&lt;p&gt;A fire is burning near &lt;b&gt;Kumbarilla&lt;/b&gt;.&lt;/p&gt;&lt;p&gt;Leave &amp;amp; go&lt;br/&gt;now&lt;/p&gt;</content>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(
+            entry.content.as_deref(),
+            Some("A fire is burning near Kumbarilla.\nLeave & go\nnow")
+        );
+
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <content type="html"><![CDATA[<p>Call Triple&nbsp;Zero &#40;000&#x29;</p>]]></content>
+    </entry>
+</feed>"#;
+        let entry = parse_first_entry(xml);
+        assert_eq!(entry.content.as_deref(), Some("Call Triple Zero (000)"));
+    }
+
     #[test]
     fn parse_entry() {
         let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
@@ -217,6 +1719,7 @@ mod tests {
           <name>Queensland Fire and Emergency Services</name>
         </author>
         <category term="Watch and Act"/>
+        <category term="Bushfire"/>
         <content>A large fire is burning in the Kumbarilla State Forest and Dunmore State Forest. It is travelling towards Wilkin Road within the Dunmore State Forest.
 
           Conditions could get worse quickly.
@@ -233,7 +1736,7 @@ mod tests {
 </feed>"#;
 
         let expected = Entry {
-            category: Some("Watch and Act".to_string()),
+            categories: vec!["Watch and Act".to_string(), "Bushfire".to_string()],
             content: Some("A large fire is burning in the Kumbarilla State Forest and Dunmore State Forest. It is travelling towards Wilkin Road within the Dunmore State Forest.
 
           Conditions could get worse quickly.
@@ -241,14 +1744,18 @@ mod tests {
           Firefighters are working to contain the fire. You should not expect a firefighter at your door. Firefighting aircraft are helping ground crews.
 
           If your life is in danger, call Triple Zero (000) immediately.".to_string()),
+            summary: None,
             id: EntryId("IF39-1919322".to_string()),
             published: Some(OffsetDateTime::parse("2023-09-08T17:12:08+10:00", &Rfc3339).unwrap()),
             title: Some("PREPARE TO LEAVE - Cecil Plains and Dunmore (near Kumbarilla) - fire as at  3:52pm Friday,  8 September 2023".to_string()),
             updated: Some(OffsetDateTime::parse("2023-09-08T15:41:00+10:00", &Rfc3339).unwrap()),
             point: Some((-27.584701903466, 151.06082028616)),
+            polygon: Vec::new(),
+            extra: HashMap::new(),
+            links: Vec::new(),
         };
 
-        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let doc = roxmltree::Document::parse(xml).unwrap();
         for node in doc.descendants() {
             if node.is_element() && node.has_tag_name((ATOM_NS, "entry")) {
                 let entry = Entry::parse(node);
@@ -0,0 +1,198 @@
+//! Outbound HTTP client configuration.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use ureq::{Agent, AgentBuilder};
+
+/// User-Agent sent with outbound requests unless configured otherwise.
+///
+/// The revision is included when `WIZARDS_BOT_REVISION` is set at build time.
+pub fn default_user_agent() -> String {
+    let name = env!("CARGO_PKG_NAME");
+    let version = env!("CARGO_PKG_VERSION");
+    match option_env!("WIZARDS_BOT_REVISION") {
+        Some(revision) => format!("{name}/{version} ({revision})"),
+        None => format!("{name}/{version}"),
+    }
+}
+
+/// Build the agent used for fetching the feed and posting to webhooks.
+pub fn agent(user_agent: &str) -> Agent {
+    builder(user_agent).build()
+}
+
+/// Build the agent used for fetching the feed.
+///
+/// When `insecure` is set certificates aren't verified, for mirrors with self-signed
+/// certificates.
+pub fn feed_agent(user_agent: &str, insecure: bool) -> Agent {
+    if !insecure {
+        return agent(user_agent);
+    }
+    let provider = Arc::new(crypto::ring::default_provider());
+    // NOTE(unwrap): the default protocol versions are supported by the ring provider
+    let tls_config = rustls::ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+        .with_no_client_auth();
+    builder(user_agent).tls_config(Arc::new(tls_config)).build()
+}
+
+fn builder(user_agent: &str) -> AgentBuilder {
+    AgentBuilder::new()
+        .timeout_read(Duration::from_secs(15))
+        .timeout_write(Duration::from_secs(15))
+        .user_agent(user_agent)
+}
+
+/// Accepts any server certificate, handshake signatures are still checked.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Build an agent that doesn't follow redirects, for resolving shortened links.
+pub fn resolver_agent(user_agent: &str) -> Agent {
+    AgentBuilder::new()
+        .timeout_read(Duration::from_secs(5))
+        .timeout_write(Duration::from_secs(5))
+        .redirects(0)
+        .user_agent(user_agent)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::thread;
+
+    use rustls::pki_types::PrivateKeyDer;
+
+    use super::*;
+
+    #[test]
+    fn agent_sends_user_agent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                headers.push(line.trim().to_string());
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            headers
+        });
+
+        agent("wizards-bot-test/1.0")
+            .get(&format!("http://{addr}/"))
+            .call()
+            .unwrap();
+        let headers = server.join().unwrap();
+        assert!(headers
+            .iter()
+            .any(|header| header.eq_ignore_ascii_case("user-agent: wizards-bot-test/1.0")));
+    }
+
+    #[test]
+    fn insecure_feed_agent() {
+        let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let cert = fs::read(testdata.join("cert.der")).unwrap();
+        let key = fs::read(testdata.join("key.der")).unwrap();
+        let tls_config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.into()], PrivateKeyDer::Pkcs8(key.into()))
+            .unwrap();
+        let tls_config = Arc::new(tls_config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "https://localhost:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let conn = rustls::ServerConnection::new(Arc::clone(&tls_config)).unwrap();
+                let mut stream = rustls::StreamOwned::new(conn, stream);
+                let mut line = String::new();
+                // The handshake fails when the client rejects the certificate
+                if BufReader::new(&mut stream).read_line(&mut line).is_err() {
+                    continue;
+                }
+                stream
+                    .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+                stream.conn.send_close_notify();
+                let _ = stream.flush();
+            }
+        });
+
+        // The self-signed certificate is only accepted when verification is disabled
+        let err = feed_agent("test", false).get(&url).call();
+        assert!(matches!(err, Err(ureq::Error::Transport(_))));
+        let response = feed_agent("test", true).get(&url).call().unwrap();
+        assert_eq!(response.status(), 204);
+        server.join().unwrap();
+    }
+}
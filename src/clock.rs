@@ -0,0 +1,48 @@
+//! Source of the current time, so time dependent behaviour can be tested.
+
+use time::OffsetDateTime;
+
+/// Provides the current time.
+pub trait Clock {
+    /// The current time in UTC.
+    fn now(&self) -> OffsetDateTime;
+}
+
+/// Reads the system clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// A clock that only moves when advanced.
+    pub(crate) struct MockClock(Mutex<OffsetDateTime>);
+
+    impl MockClock {
+        pub(crate) fn new(now: OffsetDateTime) -> Self {
+            MockClock(Mutex::new(now))
+        }
+
+        pub(crate) fn advance(&self, duration: Duration) {
+            // NOTE(unwrap): The lock is never held across a panic
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> OffsetDateTime {
+            // NOTE(unwrap): The lock is never held across a panic
+            *self.0.lock().unwrap()
+        }
+    }
+}
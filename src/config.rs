@@ -0,0 +1,1192 @@
+//! Configuration read from the environment.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fs};
+use std::{fmt, io};
+
+use time::format_description;
+use time::UtcOffset;
+
+use crate::bushfire::{
+    AlertShape, BoundingBox, LatLong, ALERT_DISTANCE, DEFAULT_MAX_FEED_SIZE, FEED_URL,
+};
+use crate::client;
+use crate::datastore::{Backups, DEFAULT_BACKUP_INTERVAL, DEFAULT_BACKUP_KEEP};
+use crate::logging::{Level, LogFormat};
+use crate::monitor::{QuietHours, SeedMode, DEFAULT_ERROR_REMINDER, DEFAULT_RENOTIFY_INTERVAL};
+use crate::notify::{MapProvider, MessageOptions};
+use crate::substitute::{self, Substitutions, DEFAULT_MAX_SHORTLINK_LOOKUPS};
+use crate::{HomePage, RateLimit, RequestLimits, DEFAULT_HTTP_WORKERS, DEFAULT_MAX_MESSAGE_LENGTH};
+
+pub struct Config {
+    pub run_mode: RunMode,
+    pub log_format: LogFormat,
+    /// Most verbose level of log events written.
+    pub log_level: Level,
+    /// Write logs to this file rather than stdout/stderr.
+    pub log_file: Option<PathBuf>,
+    pub mattermost_token: String,
+    pub bushfire_webhook: String,
+    /// Webhook poll errors are posted to instead of `bushfire_webhook`.
+    pub error_webhook: Option<String>,
+    /// Shared secret used to sign webhook posts.
+    pub webhook_secret: Option<String>,
+    /// Log notifications instead of posting them to the webhook.
+    pub dry_run: bool,
+    pub data_path: PathBuf,
+    /// Periodically copy the datastore file, if set.
+    pub backups: Option<Backups>,
+    pub bushfire_point: LatLong,
+    /// Every point monitored, starting with `bushfire_point`.
+    pub bushfire_points: Vec<LatLong>,
+    /// Shape of the region around `bushfire_point` that is notified about.
+    pub alert_shape: AlertShape,
+    /// URL of the bushfire feed.
+    pub feed_url: String,
+    /// Skip certificate verification when fetching the feed.
+    pub feed_insecure: bool,
+    /// Largest page of the feed that will be read, in bytes.
+    pub max_feed_size: u64,
+    /// Skip parsing entries further than this many kilometres from every point.
+    pub feed_prefilter: Option<f64>,
+    /// Media types the feed is accepted as, the defaults if empty.
+    pub feed_content_types: Vec<String>,
+    pub address: String,
+    pub port: u16,
+    /// Listen on all IPv6 and IPv4 addresses, ignoring `address`.
+    pub dual_stack: bool,
+    /// Prefix of all HTTP routes, for mounting behind a reverse proxy.
+    pub base_path: String,
+    /// Serve HTTPS instead of HTTP.
+    pub tls: Option<TlsConfig>,
+    pub limits: RequestLimits,
+    /// Maximum rate of /nit requests.
+    pub nit_rate_limit: Option<RateLimit>,
+    /// Maximum characters in a /nit slash command response.
+    pub max_message_length: usize,
+    pub http_workers: usize,
+    /// Window during which non-emergency notifications are deferred.
+    pub quiet_hours: Option<QuietHours>,
+    /// Offset used to determine local time.
+    pub utc_offset: UtcOffset,
+    pub message_options: MessageOptions,
+    /// How the first poll after startup is handled when the datastore is new.
+    pub seed_on_start: SeedMode,
+    /// Post follow-ups when a notified entry is updated. They're only threaded if the webhook
+    /// responds with the created post, which Mattermost incoming webhooks don't.
+    pub notify_updates: bool,
+    /// Minimum time between follow-ups for the same entry.
+    pub renotify_interval: Duration,
+    /// Notify about an entry again once this long has passed since it was notified.
+    pub dedup_window: Option<Duration>,
+    /// Skip entries with the same content as a notified entry.
+    pub dedupe_content: bool,
+    /// Hold new entries for a poll before notifying. Held entries are only kept in memory, so
+    /// this can't be used in once mode.
+    pub grace_poll: bool,
+    /// How often a recurring poll error is posted again. Ignored in once mode, where every
+    /// failed run posts its error.
+    pub error_reminder: Duration,
+    /// Maximum seconds that poll intervals are randomly varied by.
+    pub poll_jitter: u32,
+    /// Post a note after the first poll when the datastore was newly created.
+    pub announce_new_datastore: bool,
+    /// Polls a notified entry must be absent for before a cleared message is posted. Notified
+    /// entries are only tracked in memory, so this can't be used in once mode.
+    pub clear_after: Option<u32>,
+    pub substitutions: Substitutions,
+    /// Expand up to this many shortened links in each /nit message, if set.
+    pub max_shortlink_lookups: Option<usize>,
+    /// User-Agent sent with outbound HTTP requests.
+    pub user_agent: String,
+    /// Revision shown on the home page.
+    pub revision: Option<String>,
+    /// Operator details shown on the home page.
+    pub home_page: HomePage,
+}
+
+/// Shown in place of secrets when the configuration is printed.
+const REDACTED: &str = "<redacted>";
+
+impl fmt::Debug for Config {
+    /// Secrets are redacted so the output can be shared.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("run_mode", &self.run_mode)
+            .field("log_format", &self.log_format)
+            .field("log_level", &self.log_level)
+            .field("log_file", &self.log_file)
+            .field("mattermost_token", &REDACTED)
+            .field("bushfire_webhook", &REDACTED)
+            .field(
+                "error_webhook",
+                &self.error_webhook.as_ref().map(|_| REDACTED),
+            )
+            .field(
+                "webhook_secret",
+                &self.webhook_secret.as_ref().map(|_| REDACTED),
+            )
+            .field("dry_run", &self.dry_run)
+            .field("data_path", &self.data_path)
+            .field("backups", &self.backups)
+            .field("bushfire_point", &self.bushfire_point)
+            .field("bushfire_points", &self.bushfire_points)
+            .field("alert_shape", &self.alert_shape)
+            .field("feed_url", &self.feed_url)
+            .field("feed_insecure", &self.feed_insecure)
+            .field("max_feed_size", &self.max_feed_size)
+            .field("feed_prefilter", &self.feed_prefilter)
+            .field("feed_content_types", &self.feed_content_types)
+            .field("address", &self.address)
+            .field("port", &self.port)
+            .field("dual_stack", &self.dual_stack)
+            .field("base_path", &self.base_path)
+            .field("tls", &self.tls)
+            .field("limits", &self.limits)
+            .field("nit_rate_limit", &self.nit_rate_limit)
+            .field("max_message_length", &self.max_message_length)
+            .field("http_workers", &self.http_workers)
+            .field("quiet_hours", &self.quiet_hours)
+            .field("utc_offset", &self.utc_offset)
+            .field("message_options", &self.message_options)
+            .field("seed_on_start", &self.seed_on_start)
+            .field("notify_updates", &self.notify_updates)
+            .field("renotify_interval", &self.renotify_interval)
+            .field("dedup_window", &self.dedup_window)
+            .field("dedupe_content", &self.dedupe_content)
+            .field("grace_poll", &self.grace_poll)
+            .field("error_reminder", &self.error_reminder)
+            .field("poll_jitter", &self.poll_jitter)
+            .field("announce_new_datastore", &self.announce_new_datastore)
+            .field("clear_after", &self.clear_after)
+            .field("substitutions", &self.substitutions)
+            .field("max_shortlink_lookups", &self.max_shortlink_lookups)
+            .field("user_agent", &self.user_agent)
+            .field("revision", &self.revision)
+            .field("home_page", &self.home_page)
+            .finish()
+    }
+}
+
+/// Certificate and private key used to serve HTTPS.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// Path to the PEM encoded certificate chain.
+    pub certificate: PathBuf,
+    /// Path to the PEM encoded private key.
+    pub private_key: PathBuf,
+}
+
+/// How the bot runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunMode {
+    /// Serve HTTP requests and poll the feed until signalled to stop.
+    Daemon,
+    /// Poll the feed once and exit.
+    Once,
+    /// Print the resolved configuration and exit.
+    PrintConfig,
+}
+
+impl Config {
+    /// Configuration for tests, storing data at `data_path`.
+    #[cfg(test)]
+    pub fn test(data_path: PathBuf) -> Config {
+        let data_path = data_path.into_os_string();
+        Self::from_vars(|name| match name {
+            "MM_SLASH_TOKEN" => Some(OsString::from("test")),
+            "MM_BUSHFIRE_WEBHOOK" => Some(OsString::from("http://localhost/hook")),
+            "WIZARDS_BOT_DATA_PATH" => Some(data_path.clone()),
+            "WIZARDS_BOT_BUSHFIRE_POINT" => Some(OsString::from("-27.46844,153.02334")),
+            "WIZARDS_BOT_UTC_OFFSET" => Some(OsString::from("+10:00")),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    /// Read the configuration from environment variables.
+    ///
+    /// This should be called before any threads are spawned as determining the local UTC offset
+    /// is only possible while the process is single threaded.
+    pub fn from_env() -> Result<Config, io::Error> {
+        Self::from_vars(|name| env::var_os(name))
+    }
+
+    fn from_vars<F>(var: F) -> Result<Config, io::Error>
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        let run_mode = match string(&var, "WIZARDS_BOT_RUN_MODE")?.as_deref() {
+            None | Some("daemon") => RunMode::Daemon,
+            Some("once") => RunMode::Once,
+            Some("print-config") => RunMode::PrintConfig,
+            Some(mode) => {
+                return Err(io::Error::other(format!(
+                    "Unknown WIZARDS_BOT_RUN_MODE '{mode}', expected daemon, once, or print-config"
+                )))
+            }
+        };
+        let log_format = string(&var, "WIZARDS_BOT_LOG_FORMAT")?
+            .map(|format| {
+                format.parse().map_err(|err| {
+                    io::Error::other(format!("Unable to parse WIZARDS_BOT_LOG_FORMAT: {err}"))
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let log_level = string(&var, "WIZARDS_BOT_LOG_LEVEL")?
+            .map(|level| {
+                level.parse().map_err(|err| {
+                    io::Error::other(format!("Unable to parse WIZARDS_BOT_LOG_LEVEL: {err}"))
+                })
+            })
+            .transpose()?
+            .unwrap_or(Level::Info);
+        let mattermost_token = secret(&var, "MM_SLASH_TOKEN")?;
+        let bushfire_webhook = secret(&var, "MM_BUSHFIRE_WEBHOOK")?;
+        let data_path = var("WIZARDS_BOT_DATA_PATH")
+            .map(PathBuf::from)
+            .ok_or_else(|| io::Error::other("WIZARDS_BOT_DATA_PATH is not set"))?;
+        let bushfire_bbox = string(&var, "WIZARDS_BOT_BUSHFIRE_BBOX")?
+            .map(|bbox| {
+                bbox.parse::<BoundingBox>().map_err(|err| {
+                    io::Error::other(format!("Unable to parse WIZARDS_BOT_BUSHFIRE_BBOX: {err}"))
+                })
+            })
+            .transpose()?;
+        let alert_shape = string(&var, "WIZARDS_BOT_ALERT_SHAPE")?
+            .map(|shape| {
+                shape.parse().map_err(|err| {
+                    io::Error::other(format!("Unable to parse WIZARDS_BOT_ALERT_SHAPE: {err}"))
+                })
+            })
+            .transpose()?;
+        let mut bushfire_points = Vec::new();
+        if let Some(point) = string(&var, "WIZARDS_BOT_BUSHFIRE_POINT")? {
+            bushfire_points.push(parse_point("WIZARDS_BOT_BUSHFIRE_POINT", &point)?);
+        }
+        // Numbered points are read in order until one is missing
+        for number in 1.. {
+            let name = format!("WIZARDS_BOT_BUSHFIRE_POINT_{number}");
+            let Some(point) = string(&var, &name)? else {
+                break;
+            };
+            bushfire_points.push(parse_point(&name, &point)?);
+        }
+        // A region is monitored instead of a point, with its centre used for distances
+        let (bushfire_point, alert_shape) = match bushfire_bbox {
+            Some(bbox) => {
+                if !bushfire_points.is_empty() || alert_shape.is_some() {
+                    return Err(io::Error::other(
+                        "WIZARDS_BOT_BUSHFIRE_BBOX can't be used with WIZARDS_BOT_BUSHFIRE_POINT or WIZARDS_BOT_ALERT_SHAPE",
+                    ));
+                }
+                bushfire_points.push(bbox.centre());
+                (bbox.centre(), AlertShape::Region(bbox))
+            }
+            None => {
+                // The first point is the reference for distances
+                let point = bushfire_points
+                    .first()
+                    .copied()
+                    .ok_or_else(|| io::Error::other("WIZARDS_BOT_BUSHFIRE_POINT is not set"))?;
+                (point, alert_shape.unwrap_or_default())
+            }
+        };
+
+        let defaults = RequestLimits::default();
+        let limits = RequestLimits {
+            max_body_size: optional(&var, "WIZARDS_BOT_MAX_BODY_SIZE")
+                .unwrap_or(defaults.max_body_size),
+            read_timeout: optional(&var, "WIZARDS_BOT_READ_TIMEOUT")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.read_timeout),
+        };
+
+        // Leave room for the corners of the alert box
+        let min_prefilter = 2. * ALERT_DISTANCE;
+        let feed_prefilter = string(&var, "WIZARDS_BOT_PREFILTER_KM")?
+            .map(|distance| match distance.parse::<f64>() {
+                Ok(distance) if distance >= min_prefilter => Ok(distance),
+                _ => Err(io::Error::other(format!(
+                    "Unable to parse WIZARDS_BOT_PREFILTER_KM, expected a distance of at least {min_prefilter} km"
+                ))),
+            })
+            .transpose()?;
+        let nit_rate_limit = string(&var, "WIZARDS_BOT_NIT_RATE_LIMIT")?
+            .map(|limit| {
+                RateLimit::parse(&limit).ok_or_else(|| {
+                    io::Error::other(
+                        "Unable to parse WIZARDS_BOT_NIT_RATE_LIMIT, expected REQUESTS/SECONDS",
+                    )
+                })
+            })
+            .transpose()?;
+
+        let quiet_hours = string(&var, "WIZARDS_BOT_QUIET_HOURS")?
+            .map(|hours| {
+                hours.parse().map_err(|err| {
+                    io::Error::other(format!("Unable to parse WIZARDS_BOT_QUIET_HOURS: {err}"))
+                })
+            })
+            .transpose()?;
+        let utc_offset = match string(&var, "WIZARDS_BOT_UTC_OFFSET")? {
+            Some(offset) => parse_utc_offset(&offset).ok_or_else(|| {
+                io::Error::other("Unable to parse WIZARDS_BOT_UTC_OFFSET, expected +HH:MM")
+            })?,
+            None => UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC),
+        };
+
+        let map_provider = match string(&var, "WIZARDS_BOT_MAP_PROVIDER")?.as_deref() {
+            None => Some(MapProvider::default()),
+            Some("none") => None,
+            Some(provider) => Some(provider.parse().map_err(|err| {
+                io::Error::other(format!("Unable to parse WIZARDS_BOT_MAP_PROVIDER: {err}"))
+            })?),
+        };
+
+        let locale = string(&var, "WIZARDS_BOT_LOCALE")?
+            .map(|locale| {
+                locale.parse().map_err(|err| {
+                    io::Error::other(format!("Unable to parse WIZARDS_BOT_LOCALE: {err}"))
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        let mentions = match string(&var, "WIZARDS_BOT_MENTIONS")?.as_deref() {
+            None => None,
+            Some("none") => Some(Vec::new()),
+            Some(mentions) => Some(
+                mentions
+                    .split(',')
+                    .filter(|mention| !mention.trim().is_empty())
+                    .map(|mention| match mention.split_once('=') {
+                        Some((category, mention)) => {
+                            Ok((category.trim().to_string(), mention.trim().to_string()))
+                        }
+                        None => Err(io::Error::other(format!(
+                            "Unable to parse WIZARDS_BOT_MENTIONS, expected category=mention: {mention}"
+                        ))),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+
+        let rules = match var("WIZARDS_BOT_RULES_FILE").map(PathBuf::from) {
+            Some(path) => substitute::load_rules(&path).map_err(|err| {
+                io::Error::other(format!(
+                    "Unable to load rules from {}: {err}",
+                    path.display()
+                ))
+            })?,
+            None => Vec::new(),
+        };
+
+        let disabled_rules = list(&var, "WIZARDS_BOT_DISABLE_RULES")?;
+        if let Some(name) = disabled_rules
+            .iter()
+            .find(|name| !substitute::is_builtin_rule(name))
+        {
+            return Err(io::Error::other(format!(
+                "Unknown rule '{name}' in WIZARDS_BOT_DISABLE_RULES"
+            )));
+        }
+
+        let source_archive = match string(&var, "WIZARDS_BOT_SOURCE_ARCHIVE")?.as_deref() {
+            None | Some("none") => None,
+            Some(archive) => Some(archive.parse().map_err(|err| {
+                io::Error::other(format!("Unable to parse WIZARDS_BOT_SOURCE_ARCHIVE: {err}"))
+            })?),
+        };
+
+        let template = match string(&var, "WIZARDS_BOT_TEMPLATE_PATH")? {
+            Some(path) => Some(fs::read_to_string(&path).map_err(|err| {
+                io::Error::other(format!("Unable to read template from {path}: {err}"))
+            })?),
+            None => string(&var, "WIZARDS_BOT_TEMPLATE")?,
+        };
+
+        let tls = match (
+            var("WIZARDS_BOT_TLS_CERT").map(PathBuf::from),
+            var("WIZARDS_BOT_TLS_KEY").map(PathBuf::from),
+        ) {
+            (None, None) => None,
+            (Some(_), Some(_)) if !cfg!(feature = "tls") => {
+                return Err(io::Error::other(
+                    "WIZARDS_BOT_TLS_CERT is set but TLS support was not enabled at build time",
+                ))
+            }
+            (Some(certificate), Some(private_key)) => Some(TlsConfig {
+                certificate,
+                private_key,
+            }),
+            (Some(_), None) => return Err(io::Error::other("WIZARDS_BOT_TLS_KEY is not set")),
+            (None, Some(_)) => return Err(io::Error::other("WIZARDS_BOT_TLS_CERT is not set")),
+        };
+
+        let grace_poll = flag(&var, "WIZARDS_BOT_GRACE_POLL")?;
+        if grace_poll && run_mode == RunMode::Once {
+            return Err(io::Error::other(
+                "WIZARDS_BOT_GRACE_POLL can't be used with WIZARDS_BOT_RUN_MODE=once",
+            ));
+        }
+
+        let clear_after =
+            optional(&var, "WIZARDS_BOT_CLEAR_AFTER_POLLS").filter(|&polls: &u32| polls > 0);
+        if clear_after.is_some() && run_mode == RunMode::Once {
+            return Err(io::Error::other(
+                "WIZARDS_BOT_CLEAR_AFTER_POLLS can't be used with WIZARDS_BOT_RUN_MODE=once",
+            ));
+        }
+
+        let seed_on_start = string(&var, "WIZARDS_BOT_SEED_ON_START")?
+            .map(|seed| {
+                seed.parse().map_err(|err| {
+                    io::Error::other(format!("Unable to parse WIZARDS_BOT_SEED_ON_START: {err}"))
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Config {
+            run_mode,
+            log_format,
+            log_level,
+            log_file: var("WIZARDS_BOT_LOG_FILE").map(PathBuf::from),
+            mattermost_token,
+            bushfire_webhook,
+            error_webhook: optional_secret(&var, "WIZARDS_BOT_ERROR_WEBHOOK")?,
+            webhook_secret: optional_secret(&var, "WIZARDS_BOT_WEBHOOK_SECRET")?,
+            dry_run: flag(&var, "WIZARDS_BOT_DRY_RUN")?,
+            data_path,
+            backups: var("WIZARDS_BOT_BACKUP_DIR")
+                .map(PathBuf::from)
+                .map(|dir| Backups {
+                    dir,
+                    interval: optional(&var, "WIZARDS_BOT_BACKUP_INTERVAL_HOURS")
+                        .filter(|&hours| hours > 0)
+                        .map_or(DEFAULT_BACKUP_INTERVAL, |hours: u64| {
+                            Duration::from_secs(hours.saturating_mul(60 * 60))
+                        }),
+                    keep: optional(&var, "WIZARDS_BOT_BACKUP_KEEP")
+                        .filter(|&keep| keep > 0)
+                        .unwrap_or(DEFAULT_BACKUP_KEEP),
+                }),
+            bushfire_point,
+            bushfire_points,
+            alert_shape,
+            feed_url: string(&var, "WIZARDS_BOT_FEED_URL")?
+                .unwrap_or_else(|| String::from(FEED_URL)),
+            feed_insecure: flag(&var, "WIZARDS_BOT_FEED_INSECURE")?,
+            max_feed_size: optional(&var, "WIZARDS_BOT_MAX_FEED_SIZE")
+                .unwrap_or(DEFAULT_MAX_FEED_SIZE),
+            feed_prefilter,
+            feed_content_types: list(&var, "WIZARDS_BOT_FEED_CONTENT_TYPES")?
+                .iter()
+                .map(|content_type| content_type.to_ascii_lowercase())
+                .collect(),
+            address: string(&var, "WIZARDS_BOT_ADDRESS")?
+                .unwrap_or_else(|| String::from("0.0.0.0")),
+            port: optional(&var, "WIZARDS_BOT_PORT").unwrap_or(8888),
+            dual_stack: flag(&var, "WIZARDS_BOT_DUAL_STACK")?,
+            base_path: string(&var, "WIZARDS_BOT_BASE_PATH")?.unwrap_or_default(),
+            tls,
+            limits,
+            nit_rate_limit,
+            max_message_length: optional(&var, "WIZARDS_BOT_MAX_MESSAGE_LENGTH")
+                .filter(|&length| length > 0)
+                .unwrap_or(DEFAULT_MAX_MESSAGE_LENGTH),
+            http_workers: optional(&var, "WIZARDS_BOT_HTTP_WORKERS")
+                .filter(|&workers| workers > 0)
+                .unwrap_or(DEFAULT_HTTP_WORKERS),
+            quiet_hours,
+            utc_offset,
+            message_options: MessageOptions {
+                map_provider,
+                template,
+                reference: Some(bushfire_point),
+                attachments: flag(&var, "WIZARDS_BOT_ATTACHMENTS")?,
+                coordinate_precision: optional(&var, "WIZARDS_BOT_COORDINATE_PRECISION"),
+                mentions,
+                locale,
+                footer: flag(&var, "WIZARDS_BOT_FOOTER")?,
+                location: flag(&var, "WIZARDS_BOT_LOCATION_PROPS")?,
+            },
+            seed_on_start,
+            notify_updates: flag(&var, "WIZARDS_BOT_NOTIFY_UPDATES")?,
+            renotify_interval: optional(&var, "WIZARDS_BOT_RENOTIFY_INTERVAL_MINS")
+                .map_or(DEFAULT_RENOTIFY_INTERVAL, |minutes: u64| {
+                    Duration::from_secs(minutes.saturating_mul(60))
+                }),
+            dedup_window: optional(&var, "WIZARDS_BOT_DEDUP_WINDOW").map(Duration::from_secs),
+            dedupe_content: flag(&var, "WIZARDS_BOT_DEDUPE_CONTENT")?,
+            grace_poll,
+            error_reminder: optional(&var, "WIZARDS_BOT_ERROR_REMINDER_MINS")
+                .map_or(DEFAULT_ERROR_REMINDER, |minutes: u64| {
+                    Duration::from_secs(minutes.saturating_mul(60))
+                }),
+            poll_jitter: optional(&var, "WIZARDS_BOT_POLL_JITTER_SECS").unwrap_or(0),
+            announce_new_datastore: flag(&var, "WIZARDS_BOT_ANNOUNCE_NEW_DATASTORE")?,
+            clear_after,
+            substitutions: Substitutions {
+                dedupe: flag(&var, "WIZARDS_BOT_DEDUPE_URLS")?,
+                strip_params: list(&var, "WIZARDS_BOT_STRIP_PARAMS")?,
+                preserve_params: list(&var, "WIZARDS_BOT_PRESERVE_PARAMS")?,
+                expand: flag(&var, "WIZARDS_BOT_NIT_EXPAND")?,
+                rules,
+                disabled_rules,
+                source_archive,
+                fxtwitter: flag(&var, "WIZARDS_BOT_NIT_FXTWITTER")?,
+                // Parsed URLs always have lowercase schemes
+                schemes: list(&var, "WIZARDS_BOT_REWRITE_SCHEMES")?
+                    .iter()
+                    .map(|scheme| scheme.to_ascii_lowercase())
+                    .collect(),
+                max_rewrites: optional(&var, "WIZARDS_BOT_MAX_REWRITES"),
+            },
+            max_shortlink_lookups: flag(&var, "WIZARDS_BOT_EXPAND_SHORTLINKS")?.then(|| {
+                optional(&var, "WIZARDS_BOT_MAX_SHORTLINK_LOOKUPS")
+                    .unwrap_or(DEFAULT_MAX_SHORTLINK_LOOKUPS)
+            }),
+            user_agent: string(&var, "WIZARDS_BOT_USER_AGENT")?
+                .unwrap_or_else(client::default_user_agent),
+            revision: string(&var, "WIZARDS_BOT_REVISION")?,
+            home_page: HomePage {
+                title: string(&var, "WIZARDS_BOT_HOME_TITLE")?,
+                contact: string(&var, "WIZARDS_BOT_HOME_CONTACT")?,
+                area: string(&var, "WIZARDS_BOT_HOME_AREA")?,
+            },
+        })
+    }
+}
+
+/// Read a required secret from the file named by `{name}_FILE`, or the variable itself.
+///
+/// Trailing newlines are trimmed from the file.
+fn secret<F>(var: &F, name: &str) -> Result<String, io::Error>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    optional_secret(var, name)?.ok_or_else(|| io::Error::other(format!("{name} is not set")))
+}
+
+/// Read a secret that may be unset, from the file named by `{name}_FILE` or the variable itself.
+fn optional_secret<F>(var: &F, name: &str) -> Result<Option<String>, io::Error>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    let file_var = format!("{name}_FILE");
+    match string(var, &file_var)? {
+        Some(path) => {
+            let mut value = fs::read_to_string(&path).map_err(|err| {
+                io::Error::other(format!("Unable to read {file_var} {path}: {err}"))
+            })?;
+            let len = value.trim_end_matches(['\r', '\n']).len();
+            value.truncate(len);
+            Ok(Some(value))
+        }
+        None => string(var, name),
+    }
+}
+
+/// Read a variable that may be unset but must be valid UTF-8 when set.
+fn string<F>(var: &F, name: &str) -> Result<Option<String>, io::Error>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    var(name)
+        .map(|value| {
+            value
+                .into_string()
+                .map_err(|_| io::Error::other(format!("{name} is not valid UTF-8")))
+        })
+        .transpose()
+}
+
+/// Read a comma separated list, empty when unset.
+fn list<F>(var: &F, name: &str) -> Result<Vec<String>, io::Error>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    Ok(string(var, name)?
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(ToOwned::to_owned)
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Read an optional variable, ignoring it if it can't be parsed.
+fn optional<F, T>(var: &F, name: &str) -> Option<T>
+where
+    F: Fn(&str) -> Option<OsString>,
+    T: FromStr,
+{
+    var(name)
+        .and_then(|value| value.into_string().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// Read a boolean variable, defaulting to false when unset.
+fn flag<F>(var: &F, name: &str) -> Result<bool, io::Error>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    match string(var, name)?.as_deref() {
+        None | Some("0" | "false" | "no") => Ok(false),
+        Some("1" | "true" | "yes") => Ok(true),
+        Some(value) => Err(io::Error::other(format!(
+            "Unable to parse {name} '{value}', expected true or false"
+        ))),
+    }
+}
+
+/// Parse a point given as `LAT,LONG` in the variable `name`.
+fn parse_point(name: &str, point: &str) -> Result<LatLong, io::Error> {
+    point
+        .split_once(',')
+        .and_then(|(lat, long)| match (lat.parse(), long.parse()) {
+            (Ok(lat), Ok(long)) => Some((lat, long)),
+            _ => None,
+        })
+        .ok_or_else(|| io::Error::other(format!("Unable to parse {name}")))
+}
+
+fn parse_utc_offset(offset: &str) -> Option<UtcOffset> {
+    // NOTE(unwrap): This is a known valid format description
+    let format = format_description::parse("[offset_hour sign:mandatory]:[offset_minute]").unwrap();
+    UtcOffset::parse(offset, &format).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::locale::Locale;
+    use crate::substitute::Archive;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Config, io::Error> {
+        let vars: HashMap<&str, OsString> = vars
+            .iter()
+            .map(|&(name, value)| (name, OsString::from(value)))
+            .collect();
+        Config::from_vars(|name| vars.get(name).cloned())
+    }
+
+    const REQUIRED: [(&str, &str); 4] = [
+        ("MM_SLASH_TOKEN", "test"),
+        ("MM_BUSHFIRE_WEBHOOK", "http://localhost/hook"),
+        ("WIZARDS_BOT_DATA_PATH", "data"),
+        ("WIZARDS_BOT_BUSHFIRE_POINT", "-27.46844,153.02334"),
+    ];
+
+    /// The required variables, except for the bushfire point.
+    fn without_point() -> Vec<(&'static str, &'static str)> {
+        REQUIRED
+            .into_iter()
+            .filter(|&(name, _)| name != "WIZARDS_BOT_BUSHFIRE_POINT")
+            .collect()
+    }
+
+    #[test]
+    fn required_vars() {
+        let config = config(&REQUIRED).unwrap();
+        assert_eq!(config.mattermost_token, "test");
+        assert_eq!(config.bushfire_point, (-27.46844, 153.02334));
+        assert_eq!(config.port, 8888);
+        assert_eq!(config.run_mode, RunMode::Daemon);
+        assert!(config.quiet_hours.is_none());
+        assert!(!config.substitutions.dedupe);
+        assert!(config.user_agent.starts_with("wizards-bot/"));
+        assert!(config.dedup_window.is_none());
+
+        let err = self::config(&REQUIRED[1..]).err().unwrap();
+        assert_eq!(err.to_string(), "MM_SLASH_TOKEN is not set");
+    }
+
+    #[test]
+    fn quiet_hours_and_offset() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_QUIET_HOURS", "22:00-07:00"));
+        vars.push(("WIZARDS_BOT_UTC_OFFSET", "+10:00"));
+        let config = config(&vars).unwrap();
+        assert!(config.quiet_hours.is_some());
+        assert_eq!(config.utc_offset, UtcOffset::from_hms(10, 0, 0).unwrap());
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_QUIET_HOURS", "late"));
+        assert!(self::config(&vars).is_err());
+    }
+
+    #[test]
+    fn map_provider() {
+        let config = config(&REQUIRED).unwrap();
+        assert_eq!(
+            config.message_options.map_provider,
+            Some(MapProvider::OpenStreetMap)
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_MAP_PROVIDER", "none"));
+        assert_eq!(
+            self::config(&vars).unwrap().message_options.map_provider,
+            None
+        );
+    }
+
+    #[test]
+    fn mentions() {
+        assert_eq!(config(&REQUIRED).unwrap().message_options.mentions, None);
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push((
+            "WIZARDS_BOT_MENTIONS",
+            "Emergency=@channel, Watch and Act=@here",
+        ));
+        assert_eq!(
+            config(&vars).unwrap().message_options.mentions,
+            Some(vec![
+                ("Emergency".to_string(), "@channel".to_string()),
+                ("Watch and Act".to_string(), "@here".to_string()),
+            ])
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_MENTIONS", "none"));
+        assert_eq!(
+            config(&vars).unwrap().message_options.mentions,
+            Some(Vec::new())
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_MENTIONS", "@channel"));
+        assert!(config(&vars).is_err());
+    }
+
+    #[test]
+    fn template() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_TEMPLATE", "{title} {distance}"));
+        let config = config(&vars).unwrap();
+        assert_eq!(
+            config.message_options.template.as_deref(),
+            Some("{title} {distance}")
+        );
+        assert_eq!(
+            config.message_options.reference,
+            Some((-27.46844, 153.02334))
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_TEMPLATE_PATH", "/nonexistent/template"));
+        assert!(self::config(&vars).is_err());
+    }
+
+    #[test]
+    fn disable_rules() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_DISABLE_RULES", "medium"));
+        assert_eq!(
+            config(&vars).unwrap().substitutions.disabled_rules,
+            ["medium"]
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_DISABLE_RULES", "medium,myspace"));
+        assert_eq!(
+            config(&vars).err().unwrap().to_string(),
+            "Unknown rule 'myspace' in WIZARDS_BOT_DISABLE_RULES"
+        );
+    }
+
+    #[test]
+    fn locale() {
+        assert_eq!(
+            config(&REQUIRED).unwrap().message_options.locale,
+            Locale::English
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_LOCALE", "fr-FR"));
+        assert_eq!(
+            config(&vars).unwrap().message_options.locale,
+            Locale::French
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_LOCALE", "tlh"));
+        assert!(config(&vars).is_err());
+    }
+
+    #[test]
+    fn source_archive() {
+        assert_eq!(
+            config(&REQUIRED).unwrap().substitutions.source_archive,
+            None
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_SOURCE_ARCHIVE", "wayback"));
+        assert_eq!(
+            config(&vars).unwrap().substitutions.source_archive,
+            Some(Archive::Wayback)
+        );
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_SOURCE_ARCHIVE", "geocities"));
+        assert!(config(&vars).is_err());
+    }
+
+    #[test]
+    fn secrets_from_files() {
+        let dir = std::env::temp_dir();
+        let token_path = dir.join(format!("wizards-bot-token-{}", std::process::id()));
+        let webhook_path = dir.join(format!("wizards-bot-webhook-{}", std::process::id()));
+        fs::write(&token_path, "file-token\n").unwrap();
+        fs::write(&webhook_path, "http://localhost/file-hook\r\n\n").unwrap();
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("MM_SLASH_TOKEN_FILE", token_path.to_str().unwrap()));
+        vars.push(("MM_BUSHFIRE_WEBHOOK_FILE", webhook_path.to_str().unwrap()));
+        let config = config(&vars).unwrap();
+        assert_eq!(config.mattermost_token, "file-token");
+        assert_eq!(config.bushfire_webhook, "http://localhost/file-hook");
+
+        // The inline variable isn't needed when the file is given
+        let vars = [
+            ("MM_SLASH_TOKEN_FILE", token_path.to_str().unwrap()),
+            REQUIRED[1],
+            REQUIRED[2],
+            REQUIRED[3],
+        ];
+        assert_eq!(self::config(&vars).unwrap().mattermost_token, "file-token");
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("MM_SLASH_TOKEN_FILE", "/nonexistent/token"));
+        assert!(self::config(&vars).is_err());
+
+        let _ = fs::remove_file(token_path);
+        let _ = fs::remove_file(webhook_path);
+    }
+
+    #[test]
+    fn param_lists() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_STRIP_PARAMS", "utm_*, fbclid,,"));
+        vars.push(("WIZARDS_BOT_PRESERVE_PARAMS", "lang"));
+        let config = config(&vars).unwrap();
+        assert_eq!(config.substitutions.strip_params, ["utm_*", "fbclid"]);
+        assert_eq!(config.substitutions.preserve_params, ["lang"]);
+    }
+
+    #[test]
+    fn dedup_window() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_DEDUP_WINDOW", "604800"));
+        assert_eq!(
+            config(&vars).unwrap().dedup_window,
+            Some(Duration::from_secs(604800))
+        );
+    }
+
+    #[test]
+    fn feed_insecure() {
+        let mut vars = REQUIRED.to_vec();
+        assert!(!config(&vars).unwrap().feed_insecure);
+        vars.push(("WIZARDS_BOT_FEED_INSECURE", "1"));
+        assert!(config(&vars).unwrap().feed_insecure);
+    }
+
+    #[test]
+    fn footer() {
+        let mut vars = REQUIRED.to_vec();
+        assert!(!config(&vars).unwrap().message_options.footer);
+        vars.push(("WIZARDS_BOT_FOOTER", "true"));
+        assert!(config(&vars).unwrap().message_options.footer);
+    }
+
+    #[test]
+    fn location_props() {
+        let mut vars = REQUIRED.to_vec();
+        assert!(!config(&vars).unwrap().message_options.location);
+        vars.push(("WIZARDS_BOT_LOCATION_PROPS", "true"));
+        assert!(config(&vars).unwrap().message_options.location);
+    }
+
+    #[test]
+    fn webhook_secret() {
+        let mut vars = REQUIRED.to_vec();
+        assert_eq!(config(&vars).unwrap().webhook_secret, None);
+        vars.push(("WIZARDS_BOT_WEBHOOK_SECRET", "hunter2"));
+        let config = self::config(&vars).unwrap();
+        assert_eq!(config.webhook_secret.as_deref(), Some("hunter2"));
+        assert!(!format!("{config:?}").contains("hunter2"));
+    }
+
+    #[test]
+    fn feed_prefilter() {
+        assert_eq!(config(&REQUIRED).unwrap().feed_prefilter, None);
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_PREFILTER_KM", "50"));
+        assert_eq!(config(&vars).unwrap().feed_prefilter, Some(50.));
+
+        for distance in ["5", "far"] {
+            let mut vars = REQUIRED.to_vec();
+            vars.push(("WIZARDS_BOT_PREFILTER_KM", distance));
+            assert_eq!(
+                config(&vars).err().unwrap().to_string(),
+                "Unable to parse WIZARDS_BOT_PREFILTER_KM, expected a distance of at least 20 km"
+            );
+        }
+    }
+
+    #[test]
+    fn feed_content_types() {
+        assert!(config(&REQUIRED).unwrap().feed_content_types.is_empty());
+        let mut vars = REQUIRED.to_vec();
+        vars.push((
+            "WIZARDS_BOT_FEED_CONTENT_TYPES",
+            "application/atom+xml, Text/Plain",
+        ));
+        assert_eq!(
+            config(&vars).unwrap().feed_content_types,
+            ["application/atom+xml", "text/plain"]
+        );
+    }
+
+    #[test]
+    fn max_rewrites() {
+        let mut vars = REQUIRED.to_vec();
+        assert_eq!(config(&vars).unwrap().substitutions.max_rewrites, None);
+        vars.push(("WIZARDS_BOT_MAX_REWRITES", "3"));
+        assert_eq!(config(&vars).unwrap().substitutions.max_rewrites, Some(3));
+    }
+
+    #[test]
+    fn error_webhook() {
+        assert_eq!(config(&REQUIRED).unwrap().error_webhook, None);
+        let mut vars = REQUIRED.to_vec();
+        vars.push((
+            "WIZARDS_BOT_ERROR_WEBHOOK",
+            "https://chat.example.com/hooks/ops",
+        ));
+        let config = self::config(&vars).unwrap();
+        assert_eq!(
+            config.error_webhook.as_deref(),
+            Some("https://chat.example.com/hooks/ops")
+        );
+        assert!(!format!("{config:?}").contains("/hooks/ops"));
+    }
+
+    #[test]
+    fn renotify_interval() {
+        let mut vars = REQUIRED.to_vec();
+        assert_eq!(
+            config(&vars).unwrap().renotify_interval,
+            DEFAULT_RENOTIFY_INTERVAL
+        );
+        vars.push(("WIZARDS_BOT_RENOTIFY_INTERVAL_MINS", "30"));
+        assert_eq!(
+            config(&vars).unwrap().renotify_interval,
+            Duration::from_secs(30 * 60)
+        );
+        vars.push(("WIZARDS_BOT_RENOTIFY_INTERVAL_MINS", "18446744073709551615"));
+        assert_eq!(
+            config(&vars).unwrap().renotify_interval,
+            Duration::from_secs(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn error_reminder() {
+        let mut vars = REQUIRED.to_vec();
+        assert_eq!(
+            config(&vars).unwrap().error_reminder,
+            DEFAULT_ERROR_REMINDER
+        );
+        vars.push(("WIZARDS_BOT_ERROR_REMINDER_MINS", "18446744073709551615"));
+        assert_eq!(
+            config(&vars).unwrap().error_reminder,
+            Duration::from_secs(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn backups() {
+        let mut vars = REQUIRED.to_vec();
+        assert_eq!(config(&vars).unwrap().backups, None);
+        vars.push(("WIZARDS_BOT_BACKUP_DIR", "backups"));
+        assert_eq!(
+            config(&vars).unwrap().backups,
+            Some(Backups {
+                dir: PathBuf::from("backups"),
+                interval: DEFAULT_BACKUP_INTERVAL,
+                keep: DEFAULT_BACKUP_KEEP,
+            })
+        );
+        vars.push(("WIZARDS_BOT_BACKUP_INTERVAL_HOURS", "6"));
+        vars.push(("WIZARDS_BOT_BACKUP_KEEP", "10"));
+        assert_eq!(
+            config(&vars).unwrap().backups,
+            Some(Backups {
+                dir: PathBuf::from("backups"),
+                interval: Duration::from_secs(6 * 60 * 60),
+                keep: 10,
+            })
+        );
+        vars.push(("WIZARDS_BOT_BACKUP_INTERVAL_HOURS", "18446744073709551615"));
+        assert_eq!(
+            config(&vars)
+                .unwrap()
+                .backups
+                .map(|backups| backups.interval),
+            Some(Duration::from_secs(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn tls_requires_cert_and_key() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_TLS_CERT", "cert.pem"));
+        let err = config(&vars).err().unwrap();
+        assert_eq!(err.to_string(), "WIZARDS_BOT_TLS_KEY is not set");
+
+        vars.push(("WIZARDS_BOT_TLS_KEY", "key.pem"));
+        assert_eq!(config(&vars).is_ok(), cfg!(feature = "tls"));
+    }
+
+    #[test]
+    fn bushfire_bbox() {
+        let mut vars = without_point();
+        vars.push(("WIZARDS_BOT_BUSHFIRE_BBOX", "-27.5,152.75,-27.25,153.25"));
+        let config = config(&vars).unwrap();
+        assert_eq!(config.bushfire_point, (-27.375, 153.0));
+        assert_eq!(
+            config.alert_shape,
+            AlertShape::Region(BoundingBox {
+                min: (-27.5, 152.75),
+                max: (-27.25, 153.25),
+            })
+        );
+
+        // Mutually exclusive with the point
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_BUSHFIRE_BBOX", "-27.5,152.75,-27.25,153.25"));
+        assert!(self::config(&vars).is_err());
+
+        let mut vars = without_point();
+        vars.push(("WIZARDS_BOT_BUSHFIRE_BBOX", "-27.6,152.9"));
+        assert!(self::config(&vars).is_err());
+    }
+
+    #[test]
+    fn numbered_bushfire_points() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_BUSHFIRE_POINT_1", "-27.5,153.0"));
+        vars.push(("WIZARDS_BOT_BUSHFIRE_POINT_2", "-26.75,152.5"));
+        // Not read after the gap
+        vars.push(("WIZARDS_BOT_BUSHFIRE_POINT_4", "-26.0,152.0"));
+        let config = config(&vars).unwrap();
+        assert_eq!(config.bushfire_point, (-27.46844, 153.02334));
+        assert_eq!(
+            config.bushfire_points,
+            [(-27.46844, 153.02334), (-27.5, 153.0), (-26.75, 152.5)]
+        );
+
+        // Numbered points alone are enough, the first is the reference
+        let mut vars = without_point();
+        vars.push(("WIZARDS_BOT_BUSHFIRE_POINT_1", "-27.5,153.0"));
+        vars.push(("WIZARDS_BOT_BUSHFIRE_POINT_2", "-26.75,152.5"));
+        let config = self::config(&vars).unwrap();
+        assert_eq!(config.bushfire_point, (-27.5, 153.0));
+        assert_eq!(config.bushfire_points, [(-27.5, 153.0), (-26.75, 152.5)]);
+
+        vars.push(("WIZARDS_BOT_BUSHFIRE_POINT_3", "somewhere"));
+        let err = self::config(&vars).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Unable to parse WIZARDS_BOT_BUSHFIRE_POINT_3"
+        );
+    }
+
+    #[test]
+    fn grace_poll_needs_daemon() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_GRACE_POLL", "1"));
+        assert!(config(&vars).unwrap().grace_poll);
+
+        vars.push(("WIZARDS_BOT_RUN_MODE", "once"));
+        let err = config(&vars).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "WIZARDS_BOT_GRACE_POLL can't be used with WIZARDS_BOT_RUN_MODE=once"
+        );
+    }
+
+    #[test]
+    fn clear_after_needs_daemon() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_CLEAR_AFTER_POLLS", "2"));
+        assert_eq!(config(&vars).unwrap().clear_after, Some(2));
+
+        vars.push(("WIZARDS_BOT_RUN_MODE", "once"));
+        let err = config(&vars).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "WIZARDS_BOT_CLEAR_AFTER_POLLS can't be used with WIZARDS_BOT_RUN_MODE=once"
+        );
+    }
+
+    #[test]
+    fn run_mode() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_RUN_MODE", "once"));
+        assert_eq!(config(&vars).unwrap().run_mode, RunMode::Once);
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_RUN_MODE", "sometimes"));
+        assert!(config(&vars).is_err());
+    }
+
+    #[test]
+    fn print_config() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("MM_SLASH_TOKEN", "hunter2"));
+        vars.push((
+            "MM_BUSHFIRE_WEBHOOK",
+            "https://chat.example.com/hooks/abc123",
+        ));
+        vars.push(("WIZARDS_BOT_RUN_MODE", "print-config"));
+        vars.push(("WIZARDS_BOT_PORT", "9000"));
+        let config = config(&vars).unwrap();
+        assert_eq!(config.run_mode, RunMode::PrintConfig);
+
+        let printed = format!("{config:#?}");
+        assert!(printed.contains("run_mode: PrintConfig,"));
+        assert!(printed.contains("port: 9000,"));
+        assert!(printed.contains("bushfire_point: (\n        -27.46844,"));
+        assert!(printed.contains("mattermost_token: \"<redacted>\","));
+        assert!(!printed.contains("hunter2"));
+        assert!(!printed.contains("abc123"));
+    }
+
+    #[test]
+    fn dedupe_urls() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_DEDUPE_URLS", "true"));
+        assert!(config(&vars).unwrap().substitutions.dedupe);
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WIZARDS_BOT_DEDUPE_URLS", "maybe"));
+        assert!(config(&vars).is_err());
+    }
+}
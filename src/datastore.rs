@@ -1,56 +1,578 @@
-use std::collections::HashSet;
-use std::fs::{File, OpenOptions};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Write};
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use time::format_description::well_known::Rfc3339;
+use time::format_description::BorrowedFormatItem;
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
 
 use crate::bushfire::EntryId;
 
+/// Entries that have been notified, stored one per line.
+///
+/// Each line is the entry id, optionally followed by tab separated `updated` time, thread root
+/// post id, notified time, content hash, category and title. Later lines for the same id replace
+/// earlier ones.
 pub struct Datastore {
-    path: PathBuf,
+    /// File the records are stored in, records are only kept in memory when not set
+    path: Option<PathBuf>,
     records: Records,
+    /// There was no datastore file when it was opened
+    new: bool,
+}
+
+pub type Records = HashMap<EntryId, Record>;
+
+/// What is known about a notified entry.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Record {
+    /// The `updated` time of the entry when it was last notified.
+    pub updated: Option<OffsetDateTime>,
+    /// Id of the post that started the thread for this entry.
+    pub root_id: Option<String>,
+    /// When the entry was last notified.
+    pub notified: Option<OffsetDateTime>,
+    /// Hash of the entry's content when it was last notified.
+    pub content_hash: Option<u64>,
+    /// The entry's category when it was last notified.
+    pub category: Option<String>,
+    /// The entry's title when it was last notified.
+    pub title: Option<String>,
 }
 
-pub type Records = HashSet<EntryId>;
+/// A way of identifying a notified entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key<'a> {
+    Id(&'a EntryId),
+    ContentHash(u64),
+}
 
 impl Datastore {
     pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self, io::Error> {
         let path = path.into();
         match Self::load(&path) {
-            Ok(records) => Ok(Datastore { path, records }),
+            Ok(records) => Ok(Datastore {
+                path: Some(path),
+                records,
+                new: false,
+            }),
             Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Datastore {
-                path,
-                records: HashSet::new(),
+                path: Some(path),
+                records: HashMap::new(),
+                new: true,
             }),
             Err(err) => Err(err),
         }
     }
 
+    /// A datastore holding `records` in memory, without a file.
+    #[cfg(test)]
+    pub fn with_records(records: Records) -> Self {
+        Datastore {
+            path: None,
+            records,
+            new: false,
+        }
+    }
+
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Records, io::Error> {
         let path = path.as_ref();
         // Read the existing records
         let file = BufReader::new(File::open(path)?);
-        let mut records = HashSet::new();
+        let mut records = HashMap::new();
         for line in file.lines() {
             let line = line?;
-            if !line.is_empty() {
-                records.insert(EntryId(line));
+            // Files edited on Windows may have CRLF line endings
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
             }
+            let mut fields = line.split('\t');
+            // NOTE(unwrap): split always yields at least one item
+            let id = EntryId(fields.next().unwrap().to_owned());
+            let updated = fields
+                .next()
+                .and_then(|updated| OffsetDateTime::parse(updated, &Rfc3339).ok());
+            let root_id = fields
+                .next()
+                .filter(|root_id| !root_id.is_empty())
+                .map(ToOwned::to_owned);
+            let notified = fields
+                .next()
+                .and_then(|notified| OffsetDateTime::parse(notified, &Rfc3339).ok());
+            let content_hash = fields
+                .next()
+                .and_then(|hash| u64::from_str_radix(hash, 16).ok());
+            let mut text = || {
+                fields
+                    .next()
+                    .filter(|text| !text.is_empty())
+                    .map(ToOwned::to_owned)
+            };
+            let (category, title) = (text(), text());
+            records.insert(
+                id,
+                Record {
+                    updated,
+                    root_id,
+                    notified,
+                    content_hash,
+                    category,
+                    title,
+                },
+            );
         }
         Ok(records)
     }
 
-    pub fn append(&mut self, record: EntryId) -> Result<(), io::Error> {
-        let mut file = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&self.path)?;
-        writeln!(file, "{}", record.0)?;
-        self.records.insert(record);
+    /// Append `id` to the file and record it.
+    ///
+    /// The record is kept in memory even if writing it fails.
+    pub fn append(&mut self, id: EntryId, record: Record) -> Result<(), io::Error> {
+        let result = self.write(&id, &record);
+        self.records.insert(id, record);
+        result
+    }
+
+    fn write(&self, id: &EntryId, record: &Record) -> Result<(), io::Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        if *record == Record::default() {
+            writeln!(file, "{}", id.0)?;
+        } else {
+            let format = |time: Option<OffsetDateTime>| {
+                time.and_then(|time| time.format(&Rfc3339).ok())
+                    .unwrap_or_default()
+            };
+            let root_id = record.root_id.as_deref().unwrap_or_default();
+            let content_hash = record
+                .content_hash
+                .map(|hash| format!("{hash:016x}"))
+                .unwrap_or_default();
+            // Tabs and newlines would split the line
+            let text =
+                |text: Option<&str>| text.unwrap_or_default().replace(['\t', '\r', '\n'], " ");
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                id.0,
+                format(record.updated),
+                root_id,
+                format(record.notified),
+                content_hash,
+                text(record.category.as_deref()),
+                text(record.title.as_deref())
+            )?;
+        }
         Ok(())
     }
 
-    pub fn contains(&self, entry: &EntryId) -> bool {
-        self.records.contains(entry)
+    /// Determine if the datastore file didn't exist when it was opened, as on a fresh deployment.
+    pub fn is_new(&self) -> bool {
+        self.new
+    }
+
+    pub fn get(&self, entry: &EntryId) -> Option<&Record> {
+        self.records.get(entry)
+    }
+
+    /// Determine if any of `keys` match a notified entry.
+    pub fn contains_any(&self, keys: &[Key<'_>]) -> bool {
+        keys.iter().any(|key| match key {
+            Key::Id(id) => self.records.contains_key(*id),
+            Key::ContentHash(hash) => self
+                .records
+                .values()
+                .any(|record| record.content_hash == Some(*hash)),
+        })
+    }
+}
+
+/// How often the datastore is backed up unless configured otherwise.
+pub const DEFAULT_BACKUP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Number of datastore backups kept unless configured otherwise.
+pub const DEFAULT_BACKUP_KEEP: usize = 7;
+
+/// Backups are named with the time they were made in this format, after the datastore file name.
+const BACKUP_TIMESTAMP: &[BorrowedFormatItem<'_>] =
+    format_description!("[year][month][day]T[hour][minute][second]Z");
+
+/// Periodic copies of the datastore file, named with the time they were made.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backups {
+    /// Directory the copies are written to.
+    pub dir: PathBuf,
+    /// Time between copies.
+    pub interval: Duration,
+    /// Number of copies kept, older ones are removed.
+    pub keep: usize,
+}
+
+impl Backups {
+    /// Determine if the interval has passed since the `last` backup.
+    pub fn is_due(&self, last: OffsetDateTime, now: OffsetDateTime) -> bool {
+        now - last >= self.interval
+    }
+
+    /// Copy the datastore at `path` to a backup named with the time `now`, then remove all but
+    /// the newest `keep` backups.
+    ///
+    /// Nothing is copied if there is no datastore file yet.
+    pub fn backup(&self, path: &Path, now: OffsetDateTime) -> Result<Option<PathBuf>, io::Error> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        // NOTE(unwrap): The datastore path names a file
+        let prefix = format!("{}.", path.file_name().unwrap().to_string_lossy());
+        // NOTE(unwrap): The format only has numeric components so can't fail
+        let timestamp = now.format(BACKUP_TIMESTAMP).unwrap();
+        fs::create_dir_all(&self.dir)?;
+        let backup = self.dir.join(format!("{prefix}{timestamp}"));
+        fs::copy(path, &backup)?;
+
+        // Timestamps sort chronologically so the oldest backups come first
+        let mut backups = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                // Only backups, not other files next to the datastore such as the retry queue
+                path.file_name().is_some_and(|name| {
+                    name.to_string_lossy()
+                        .strip_prefix(&prefix)
+                        .is_some_and(|timestamp| {
+                            PrimitiveDateTime::parse(timestamp, BACKUP_TIMESTAMP).is_ok()
+                        })
+                })
+            })
+            .collect::<Vec<_>>();
+        backups.sort();
+        let excess = backups.len().saturating_sub(self.keep);
+        for old in &backups[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(Some(backup))
+    }
+}
+
+/// `path` with `.{suffix}` appended to the file name, so `data` becomes `data.retry` rather than
+/// replacing any extension it already has.
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{suffix}"));
+    PathBuf::from(path)
+}
+
+/// Where the retry queue for the datastore at `data_path` is kept.
+pub fn retry_path(data_path: &Path) -> PathBuf {
+    sibling_path(data_path, "retry")
+}
+
+/// Where notifications deferred during quiet hours are kept for the datastore at `data_path`.
+pub fn deferred_path(data_path: &Path) -> PathBuf {
+    sibling_path(data_path, "deferred")
+}
+
+/// A notification that failed to post and should be retried.
+#[derive(Debug, PartialEq)]
+pub struct Retry {
+    pub id: EntryId,
+    pub message: String,
+    /// The `updated` time of the entry the notification is for.
+    pub updated: Option<OffsetDateTime>,
+    /// Post to reply to, for follow-up notifications.
+    pub root_id: Option<String>,
+}
+
+/// A bounded queue of notifications to retry, persisted as JSON.
+pub struct RetryQueue {
+    path: PathBuf,
+    capacity: usize,
+    queue: VecDeque<Retry>,
+}
+
+impl RetryQueue {
+    pub fn new<P: Into<PathBuf>>(path: P, capacity: usize) -> Result<Self, io::Error> {
+        let path = path.into();
+        let queue = match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(RetryQueue {
+            path,
+            capacity,
+            queue,
+        })
+    }
+
+    fn parse(contents: &str) -> Result<VecDeque<Retry>, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid retry queue");
+        let value = json::parse(contents).map_err(|_| invalid())?;
+        value
+            .members()
+            .map(
+                |item| match (item["id"].as_str(), item["message"].as_str()) {
+                    (Some(id), Some(message)) => Ok(Retry {
+                        id: EntryId(id.to_owned()),
+                        message: message.to_owned(),
+                        updated: item["updated"]
+                            .as_str()
+                            .and_then(|updated| OffsetDateTime::parse(updated, &Rfc3339).ok()),
+                        root_id: item["root_id"].as_str().map(ToOwned::to_owned),
+                    }),
+                    _ => Err(invalid()),
+                },
+            )
+            .collect()
+    }
+
+    /// Add a notification to the back of the queue.
+    ///
+    /// If the queue is full the oldest notification is dropped and returned.
+    pub fn push(&mut self, retry: Retry) -> Result<Option<Retry>, io::Error> {
+        let dropped = if self.queue.len() >= self.capacity {
+            self.queue.pop_front()
+        } else {
+            None
+        };
+        self.queue.push_back(retry);
+        self.save()?;
+        Ok(dropped)
+    }
+
+    pub fn front(&self) -> Option<&Retry> {
+        self.queue.front()
+    }
+
+    pub fn pop_front(&mut self) -> Result<Option<Retry>, io::Error> {
+        let retry = self.queue.pop_front();
+        if retry.is_some() {
+            self.save()?;
+        }
+        Ok(retry)
+    }
+
+    /// Remove and return every queued notification, oldest first.
+    pub fn drain(&mut self) -> Result<Vec<Retry>, io::Error> {
+        let retries = self.queue.drain(..).collect::<Vec<_>>();
+        if !retries.is_empty() {
+            self.save()?;
+        }
+        Ok(retries)
+    }
+
+    pub fn contains(&self, id: &EntryId) -> bool {
+        self.queue.iter().any(|retry| retry.id == *id)
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let items = self
+            .queue
+            .iter()
+            .map(|retry| {
+                json::object! {
+                    id: retry.id.0.as_str(),
+                    message: retry.message.as_str(),
+                    updated: retry.updated.and_then(|updated| updated.format(&Rfc3339).ok()),
+                    root_id: retry.root_id.as_deref(),
+                }
+            })
+            .collect::<Vec<_>>();
+        // Write to a temporary file and rename so the queue isn't left truncated on failure
+        let tmp = sibling_path(&self.path, "tmp");
+        fs::write(&tmp, json::stringify(items))?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn sibling_paths() {
+        assert_eq!(retry_path(Path::new("data")), Path::new("data.retry"));
+        // An existing extension is kept so the files of `data.db` and `data.log` don't collide
+        assert_eq!(retry_path(Path::new("data.db")), Path::new("data.db.retry"));
+        assert_eq!(
+            sibling_path(&retry_path(Path::new("data")), "tmp"),
+            Path::new("data.retry.tmp")
+        );
+    }
+
+    #[test]
+    fn contains_any() {
+        let path =
+            std::env::temp_dir().join(format!("wizards-bot-contains-any-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut datastore = Datastore::new(&path).unwrap();
+        let id = EntryId("IF1".to_string());
+        let record = Record {
+            notified: Some(datetime!(2023-09-08 12:00 +10)),
+            content_hash: Some(0xfeed),
+            ..Default::default()
+        };
+        datastore
+            .append(EntryId("IF1".to_string()), record)
+            .unwrap();
+
+        let other = EntryId("IF2".to_string());
+        assert!(datastore.contains_any(&[Key::Id(&other), Key::Id(&id)]));
+        assert!(datastore.contains_any(&[Key::Id(&other), Key::ContentHash(0xfeed)]));
+        assert!(!datastore.contains_any(&[Key::Id(&other), Key::ContentHash(0xbeef)]));
+        assert!(!datastore.contains_any(&[]));
+
+        // The hash is persisted
+        let datastore = Datastore::new(&path).unwrap();
+        assert!(datastore.contains_any(&[Key::ContentHash(0xfeed)]));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn category_and_title_persisted() {
+        let path =
+            std::env::temp_dir().join(format!("wizards-bot-category-title-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut datastore = Datastore::new(&path).unwrap();
+        let record = Record {
+            category: Some("Advice".to_string()),
+            title: Some("Fire near\tKumbarilla".to_string()),
+            ..Default::default()
+        };
+        datastore
+            .append(EntryId("IF1".to_string()), record)
+            .unwrap();
+
+        let datastore = Datastore::new(&path).unwrap();
+        let record = datastore.get(&EntryId("IF1".to_string())).unwrap();
+        assert_eq!(record.category.as_deref(), Some("Advice"));
+        assert_eq!(record.title.as_deref(), Some("Fire near Kumbarilla"));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let path = std::env::temp_dir().join(format!("wizards-bot-crlf-{}", std::process::id()));
+        fs::write(
+            &path,
+            "IF1\r\nIF2\t2023-09-08T12:00:00Z\t\t\t\tAdvice\tFire near Kumbarilla \r\n\r\n",
+        )
+        .unwrap();
+        let datastore = Datastore::new(&path).unwrap();
+        let id = |id: &str| EntryId(id.to_string());
+        assert!(datastore.contains_any(&[Key::Id(&id("IF1"))]));
+        let record = datastore.get(&id("IF2")).unwrap();
+        assert_eq!(record.updated, Some(datetime!(2023-09-08 12:00 UTC)));
+        assert_eq!(record.title.as_deref(), Some("Fire near Kumbarilla"));
+        assert_eq!(datastore.records.len(), 2);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn with_records() {
+        let id = |id: &str| EntryId(id.to_string());
+        let record = Record {
+            content_hash: Some(0xfeed),
+            ..Default::default()
+        };
+        let mut datastore = Datastore::with_records(Records::from([(id("IF1"), record)]));
+        assert!(datastore.contains_any(&[Key::Id(&id("IF1"))]));
+        assert!(datastore.contains_any(&[Key::ContentHash(0xfeed)]));
+        assert!(!datastore.contains_any(&[Key::Id(&id("IF2"))]));
+        assert!(!datastore.is_new());
+
+        // Appended records are only kept in memory
+        datastore.append(id("IF2"), Record::default()).unwrap();
+        assert!(datastore.contains_any(&[Key::Id(&id("IF2"))]));
+    }
+
+    #[test]
+    fn new_datastore() {
+        let path =
+            std::env::temp_dir().join(format!("wizards-bot-new-datastore-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut datastore = Datastore::new(&path).unwrap();
+        assert!(datastore.is_new());
+        datastore
+            .append(EntryId("IF1".to_string()), Record::default())
+            .unwrap();
+
+        assert!(!Datastore::new(&path).unwrap().is_new());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn backups_pruned() {
+        let tmp = std::env::temp_dir();
+        let path = tmp.join(format!(
+            "wizards-bot-backup-datastore-{}",
+            std::process::id()
+        ));
+        let dir = tmp.join(format!("wizards-bot-backups-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let backups = Backups {
+            dir: dir.clone(),
+            interval: DEFAULT_BACKUP_INTERVAL,
+            keep: 3,
+        };
+        let _ = fs::remove_file(&path);
+        let now = datetime!(2024-01-02 03:04:05 UTC);
+        assert_eq!(backups.backup(&path, now).unwrap(), None);
+
+        // Other files named after the datastore are left alone
+        fs::create_dir_all(&dir).unwrap();
+        let retry = retry_path(&dir.join(path.file_name().unwrap()));
+        fs::write(&retry, "[]").unwrap();
+
+        let mut datastore = Datastore::new(&path).unwrap();
+        for day in 0..5 {
+            datastore
+                .append(EntryId(format!("IF{day}")), Record::default())
+                .unwrap();
+            backups
+                .backup(&path, now + time::Duration::days(day))
+                .unwrap();
+        }
+        let mut names = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        let prefix = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(
+            names,
+            [
+                format!("{prefix}.20240104T030405Z"),
+                format!("{prefix}.20240105T030405Z"),
+                format!("{prefix}.20240106T030405Z"),
+                format!("{prefix}.retry"),
+            ]
+        );
+        // The newest backup has every record
+        let records = Datastore::load(dir.join(&names[2])).unwrap();
+        assert_eq!(records.len(), 5);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn backup_due_after_interval() {
+        let backups = Backups {
+            dir: PathBuf::from("backups"),
+            interval: Duration::from_secs(60 * 60),
+            keep: 3,
+        };
+        let last = datetime!(2024-01-02 03:04:05 UTC);
+        assert!(!backups.is_due(last, last + time::Duration::minutes(59)));
+        assert!(backups.is_due(last, last + time::Duration::hours(1)));
     }
 }
@@ -0,0 +1,85 @@
+//! Fixed wording used in notifications, by locale.
+
+use std::str::FromStr;
+
+/// Language that notifications are worded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    French,
+}
+
+/// The fixed strings in notifications for a locale.
+pub struct Catalog {
+    pub unknown_category: &'static str,
+    pub untitled: &'static str,
+    pub no_content: &'static str,
+    /// Shown when the published time isn't known
+    pub unknown: &'static str,
+    pub category: &'static str,
+    pub published: &'static str,
+    pub link: &'static str,
+    pub distance: &'static str,
+    pub map: &'static str,
+    /// Starts the footer, followed by the poll time
+    pub footer: &'static str,
+    /// Labels a changed title in follow-ups
+    pub title: &'static str,
+    /// Heads follow-ups describing what changed in an entry
+    pub updated: &'static str,
+}
+
+const ENGLISH: Catalog = Catalog {
+    unknown_category: "Unknown Category",
+    untitled: "Untitled",
+    no_content: "No content",
+    unknown: "unknown",
+    category: "Category",
+    published: "Published",
+    link: "Link",
+    distance: "Distance",
+    map: "Map",
+    footer: "— via Wizards Bot at",
+    title: "Title",
+    updated: "Updated",
+};
+
+const FRENCH: Catalog = Catalog {
+    unknown_category: "Catégorie inconnue",
+    untitled: "Sans titre",
+    no_content: "Aucun contenu",
+    unknown: "inconnue",
+    category: "Catégorie",
+    published: "Publié",
+    link: "Lien",
+    distance: "Distance",
+    map: "Carte",
+    footer: "— via Wizards Bot à",
+    title: "Titre",
+    updated: "Mis à jour",
+};
+
+impl Locale {
+    /// The strings for this locale.
+    pub fn catalog(self) -> &'static Catalog {
+        match self {
+            Locale::English => &ENGLISH,
+            Locale::French => &FRENCH,
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Parse a language tag such as `en` or `fr-FR`, only the language is considered.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['-', '_']).next().unwrap_or_default();
+        match language.to_ascii_lowercase().as_str() {
+            "en" => Ok(Locale::English),
+            "fr" => Ok(Locale::French),
+            _ => Err(format!("unsupported locale '{s}', expected en or fr")),
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Minimal logging to stdout/stderr in either human readable or JSON lines format.
+//!
+//! Fields are only included in JSON output, the human readable format is just the message.
+//! Debug and trace events are only written when enabled with [set_max_level]. Events can be
+//! written to a file instead with [set_file], which is reopened by [reopen] after rotation.
+//!
+//! ```ignore
+//! info!("polled bushfire feed");
+//! info!(incident = entry.id.0; "notify of incident {}", entry.id.0);
+//! ```
+
+use std::fmt::{self, Display};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use json::JsonValue;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+static FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Text as u8);
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
+#[cfg(test)]
+thread_local! {
+    /// Events captured by [capture] on this thread, and the level they're captured at
+    static CAPTURED: std::cell::RefCell<Option<(Level, Vec<String>)>> = const {
+        std::cell::RefCell::new(None)
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+    Trace = 4,
+}
+
+/// A file that events are appended to.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `LEVEL: message` lines
+    #[default]
+    Text = 0,
+    /// One JSON object per line
+    Json = 1,
+}
+
+/// Set the format used for all subsequent log output.
+pub fn set_format(format: LogFormat) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// Only write events at `level` or more severe.
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Append all subsequent log output to the file at `path` instead of stdout/stderr.
+pub fn set_file(path: &Path) -> io::Result<()> {
+    let file = LogFile::open(path)?;
+    // NOTE(unwrap): the lock is only poisoned if writing a log line panicked
+    *FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Reopen the log file, if there is one, so output goes to a new file after the old one was
+/// moved aside by log rotation.
+pub fn reopen() -> io::Result<()> {
+    // NOTE(unwrap): the lock is only poisoned if writing a log line panicked
+    match FILE.lock().unwrap().as_mut() {
+        Some(file) => file.reopen(),
+        None => Ok(()),
+    }
+}
+
+/// Determine if events at `level` are written.
+pub fn enabled(level: Level) -> bool {
+    #[cfg(test)]
+    if let Some(max_level) = CAPTURED.with_borrow(|captured| captured.as_ref().map(|c| c.0)) {
+        return level <= max_level;
+    }
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Collect the events logged on this thread at `level` or more severe while running `f`.
+#[cfg(test)]
+pub fn capture<F: FnOnce()>(level: Level, f: F) -> Vec<String> {
+    CAPTURED.set(Some((level, Vec::new())));
+    f();
+    CAPTURED.take().map(|(_, lines)| lines).unwrap_or_default()
+}
+
+fn format() -> LogFormat {
+    match FORMAT.load(Ordering::Relaxed) {
+        1 => LogFormat::Json,
+        _ => LogFormat::Text,
+    }
+}
+
+/// Write a log event. Use the `error!`, `warn!`, `info!`, `debug!` and `trace!` macros rather than
+/// calling this directly.
+pub fn log(level: Level, message: fmt::Arguments<'_>, fields: &[(&str, &dyn Display)]) {
+    if !enabled(level) {
+        return;
+    }
+    let line = match format() {
+        LogFormat::Text => format!("{}: {message}", level.label()),
+        LogFormat::Json => format_json(level, OffsetDateTime::now_utc(), message, fields),
+    };
+    #[cfg(test)]
+    let line = match CAPTURED.with_borrow_mut(|captured| match captured {
+        Some((_, lines)) => {
+            lines.push(line);
+            None
+        }
+        None => Some(line),
+    }) {
+        Some(line) => line,
+        None => return,
+    };
+    // NOTE(unwrap): the lock is only poisoned if writing a log line panicked
+    if let Some(file) = FILE.lock().unwrap().as_mut() {
+        // There's nowhere left to report a failure to write the log
+        let _ = writeln!(file.file, "{line}");
+        return;
+    }
+    match level {
+        Level::Error | Level::Warn => eprintln!("{line}"),
+        Level::Info | Level::Debug | Level::Trace => println!("{line}"),
+    }
+}
+
+fn format_json(
+    level: Level,
+    timestamp: OffsetDateTime,
+    message: fmt::Arguments<'_>,
+    fields: &[(&str, &dyn Display)],
+) -> String {
+    let mut object = json::object! {
+        timestamp: timestamp.format(&Rfc3339).ok(),
+        level: level.label().to_ascii_lowercase(),
+        message: message.to_string(),
+    };
+    if !fields.is_empty() {
+        let mut values = JsonValue::new_object();
+        for (key, value) in fields {
+            values[*key] = value.to_string().into();
+        }
+        object["fields"] = values;
+    }
+    json::stringify(object)
+}
+
+impl LogFile {
+    fn open(path: &Path) -> io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        *self = LogFile::open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Level {
+    fn label(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(format!(
+                "unknown log level '{s}', expected error, warn, info, debug or trace"
+            )),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format '{s}', expected text or json")),
+        }
+    }
+}
+
+macro_rules! log_event {
+    ($level:expr, $($key:ident = $value:expr),+ ; $($arg:tt)+) => {
+        $crate::logging::log(
+            $level,
+            format_args!($($arg)+),
+            &[$((stringify!($key), &$value as &dyn std::fmt::Display)),+],
+        )
+    };
+    ($level:expr, $($arg:tt)+) => {
+        $crate::logging::log($level, format_args!($($arg)+), &[])
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { log_event!($crate::logging::Level::Info, $($arg)+) };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => { log_event!($crate::logging::Level::Error, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => { log_event!($crate::logging::Level::Warn, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { log_event!($crate::logging::Level::Debug, $($arg)+) };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => { log_event!($crate::logging::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn json_event() {
+        let id = "IF39-1919322";
+        let line = format_json(
+            Level::Info,
+            datetime!(2023-09-08 17:12:08 +10),
+            format_args!("notify of incident {id}"),
+            &[("incident", &id)],
+        );
+        let event = json::parse(&line).unwrap();
+        assert_eq!(event["timestamp"], "2023-09-08T17:12:08+10:00");
+        assert_eq!(event["level"], "info");
+        assert_eq!(event["message"], "notify of incident IF39-1919322");
+        assert_eq!(event["fields"]["incident"], "IF39-1919322");
+    }
+
+    #[test]
+    fn trace_only_when_enabled() {
+        let lines = capture(Level::Info, || {
+            info!("polled bushfire feed");
+            trace!("raw entry");
+        });
+        assert_eq!(lines, vec!["INFO: polled bushfire feed"]);
+
+        let lines = capture(Level::Trace, || trace!("raw entry"));
+        assert_eq!(lines, vec!["TRACE: raw entry"]);
+    }
+
+    #[test]
+    fn reopen_after_rotation() {
+        let path = std::env::temp_dir().join(format!("wizards-bot-log-{}", std::process::id()));
+        let mut rotated = path.clone().into_os_string();
+        rotated.push(".1");
+        let _ = std::fs::remove_file(&path);
+
+        let mut log = LogFile::open(&path).unwrap();
+        writeln!(log.file, "before").unwrap();
+        std::fs::rename(&path, &rotated).unwrap();
+        // Writes follow the file to its new name until it is reopened
+        writeln!(log.file, "rotating").unwrap();
+        log.reopen().unwrap();
+        writeln!(log.file, "after").unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&rotated).unwrap(),
+            "before\nrotating\n"
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "after\n");
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rotated).unwrap();
+    }
+}
@@ -1,23 +1,47 @@
+#[macro_use]
+mod logging;
 mod bushfire;
+mod client;
+mod clock;
+mod config;
 mod datastore;
+mod locale;
+mod metrics;
+mod monitor;
+mod notify;
+mod status;
+mod substitute;
 
-use std::borrow::Cow;
 use std::error::Error;
-use std::net::ToSocketAddrs;
-use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use std::{env, io, process, thread};
+use std::fmt;
+use std::io::Read;
+use std::net::{Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::{fs, io, process, thread};
 
 use json::{object, JsonValue};
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
-use time::format_description::well_known::Rfc2822;
-use tiny_http::{Header, HeaderField, Method, Request, Response, StatusCode};
-use url::Url;
+use socket2::{Domain, Protocol, Socket, Type};
+use time::format_description::well_known::Rfc3339;
+use time::{OffsetDateTime, UtcOffset};
+use tiny_http::{
+    ConfigListenAddr, Header, HeaderField, Method, Request, Response, ServerConfig, SslConfig,
+    StatusCode,
+};
 
-use crate::bushfire::Entry;
+use crate::bushfire::{Feed, QfesFeed};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Config, RunMode, TlsConfig};
+use crate::datastore::{Backups, RetryQueue};
+use crate::monitor::{Jitter, Monitor};
+use crate::notify::{LogNotifier, Notifier, Webhook};
+use crate::status::Status;
+use crate::substitute::{Shortlinks, Substitutions};
 
 const HTML: &str = include_str!("home.html");
 const CSS: &str = include_str!("style.css");
@@ -25,400 +49,2398 @@ const NOT_FOUND: &str = include_str!("not_found.html");
 const ONE_SECOND: Duration = Duration::from_secs(1);
 /// Poll the bushfire feed every 5 minutes
 const POLL_BUSHFIRE_FEED: u32 = 5 * 60;
-const BUSHFIRE_PAGE: &str = "https://www.qfes.qld.gov.au/Current-Incidents";
+/// Maximum number of failed notifications held for retry
+const RETRY_QUEUE_CAPACITY: usize = 50;
+/// Number of threads handling HTTP requests
+const DEFAULT_HTTP_WORKERS: usize = 4;
+/// Maximum size of a slash command request body
+const DEFAULT_MAX_BODY_SIZE: u64 = 64 * 1024;
+/// Time allowed to read a request
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum length of a slash command response, the Mattermost post size limit
+const DEFAULT_MAX_MESSAGE_LENGTH: usize = 16383;
+/// Time allowed for threads to finish on shutdown before exiting anyway
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 // NOTE(unwrap): These are known valid
 static AUTHORIZATION: Lazy<HeaderField> = Lazy::new(|| "Authorization".parse().unwrap());
+static ACCEPT: Lazy<HeaderField> = Lazy::new(|| "Accept".parse().unwrap());
 static CONTENT_TYPE: Lazy<HeaderField> = Lazy::new(|| "Content-Type".parse().unwrap());
 static JSON_CONTENT_TYPE: Lazy<Header> = Lazy::new(|| {
     "Content-type: application/json; charset=utf-8"
         .parse()
         .unwrap()
 });
+static TEXT_CONTENT_TYPE: Lazy<Header> =
+    Lazy::new(|| "Content-type: text/plain; charset=utf-8".parse().unwrap());
 static HTML_CONTENT_TYPE: Lazy<Header> =
     Lazy::new(|| "Content-type: text/html; charset=utf-8".parse().unwrap());
+static METRICS_CONTENT_TYPE: Lazy<Header> = Lazy::new(|| {
+    "Content-type: text/plain; version=0.0.4; charset=utf-8"
+        .parse()
+        .unwrap()
+});
 static CSS_CONTENT_TYPE: Lazy<Header> =
     Lazy::new(|| "Content-type: text/css; charset=utf-8".parse().unwrap());
-static HOME_HTML: Lazy<String> = Lazy::new(|| {
-    let git_rev = env::var("WIZARDS_BOT_REVISION").unwrap_or_else(|_| String::from("dev"));
-    HTML.replace("$rev$", &git_rev)
-});
 
 fn main() -> Result<(), io::Error> {
+    let config = Config::from_env()?;
+    logging::set_format(config.log_format);
+    logging::set_max_level(config.log_level);
+    if let Some(path) = &config.log_file {
+        logging::set_file(path).map_err(|err| {
+            io::Error::other(format!("unable to open log file {}: {err}", path.display()))
+        })?;
+    }
+    if config.run_mode == RunMode::PrintConfig {
+        println!("{config:#?}");
+        return Ok(());
+    }
+
     let term = Arc::new(AtomicBool::new(false));
     signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&term))?;
     signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&term))?;
+    let reopen_log = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&reopen_log))?;
 
     let mut threads = Vec::new();
 
-    let mm_token = env::var_os("MM_SLASH_TOKEN");
-    let mm_token = mm_token
-        .as_ref()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "MM_SLASH_TOKEN is not set"))
-        .and_then(|token| {
-            token.to_str().ok_or_else(|| {
-                io::Error::new(io::ErrorKind::Other, "MM_SLASH_TOKEN is not valid UTF-8")
-            })
-        })?;
-    let mm_webhook = env::var_os("MM_BUSHFIRE_WEBHOOK");
-    let mm_webhook = mm_webhook
-        .as_ref()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "MM_BUSHFIRE_WEBHOOK is not set"))
-        .and_then(|webhook| {
-            webhook.to_str().ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::Other,
-                    "MM_BUSHFIRE_WEBHOOK is not valid UTF-8",
-                )
-            })
-        })?;
-
-    let data_path = env::var_os("WIZARDS_BOT_DATA_PATH");
-    let data_path = data_path
-        .as_ref()
-        .map(Path::new)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "WIZARDS_BOT_DATA_PATH is not set"))?;
+    let agent = client::agent(&config.user_agent);
+    let webhook = notifier(&config, &agent);
+    if config.feed_insecure {
+        warn!("TLS certificates aren't verified when fetching the feed");
+    }
+    if (config.notify_updates || config.clear_after.is_some()) && !config.dry_run {
+        warn!(
+            "follow-ups are only threaded if the bushfire webhook responds with the created post, \
+             Mattermost incoming webhooks don't so they're posted to the channel instead"
+        );
+    }
+    let feed = QfesFeed::new(client::feed_agent(&config.user_agent, config.feed_insecure))
+        .with_url(&config.feed_url)
+        .with_alert_shape(config.alert_shape)
+        .with_max_size(config.max_feed_size)
+        .with_prefilter(config.feed_prefilter)
+        .with_content_types(config.feed_content_types.clone())
+        .with_extra_points(config.bushfire_points[1..].to_vec());
+    let bushfire_point = config.bushfire_point;
+    let precision = config
+        .message_options
+        .coordinate_precision
+        .unwrap_or(notify::DEFAULT_COORDINATE_PRECISION);
+    for point in &config.bushfire_points {
+        info!(
+            "monitoring for bushfire events at {:.*}, {:.*}",
+            precision, point.0, precision, point.1
+        );
+    }
 
-    let bushfire_point = env::var_os("WIZARDS_BOT_BUSHFIRE_POINT");
-    let bushfire_point = bushfire_point
-        .as_ref()
-        .ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                "WIZARDS_BOT_BUSHFIRE_POINT is not set",
-            )
-        })
-        .and_then(|webhook| {
-            webhook
-                .to_str()
-                .and_then(|s| s.split_once(','))
-                .and_then(|(lat, long)| match (lat.parse(), long.parse()) {
-                    (Ok(lat), Ok(long)) => Some((lat, long)),
-                    _ => None,
-                })
-                .ok_or_else(|| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        "Unable to parse WIZARDS_BOT_BUSHFIRE_POINT",
-                    )
-                })
+    let datastore = datastore::Datastore::new(&config.data_path).map_err(|err| {
+        io::Error::other(format!(
+            "unable to open datastore at {}: {err}",
+            config.data_path.display()
+        ))
+    })?;
+    let retry_path = datastore::retry_path(&config.data_path);
+    let retries = RetryQueue::new(&retry_path, RETRY_QUEUE_CAPACITY).map_err(|err| {
+        io::Error::other(format!(
+            "unable to open retry queue at {}: {err}",
+            retry_path.display()
+        ))
+    })?;
+    let status = Arc::new(Status::default());
+    let mut monitor = Monitor::new(datastore, retries);
+    if config.quiet_hours.is_some() {
+        let deferred_path = datastore::deferred_path(&config.data_path);
+        let deferred = RetryQueue::new(&deferred_path, RETRY_QUEUE_CAPACITY).map_err(|err| {
+            io::Error::other(format!(
+                "unable to open deferred queue at {}: {err}",
+                deferred_path.display()
+            ))
         })?;
-    println!(
-        "INFO: monitoring for bushfire events at {}, {}",
-        bushfire_point.0, bushfire_point.1
-    );
+        monitor = monitor.with_deferred_queue(deferred);
+    }
+    let mut monitor = monitor
+        .with_quiet_hours(config.quiet_hours)
+        .with_message_options(config.message_options.clone())
+        .with_seed(config.seed_on_start)
+        .with_notify_updates(config.notify_updates)
+        .with_renotify_interval(config.renotify_interval)
+        .with_dedup_window(config.dedup_window)
+        .with_error_reminder(config.error_reminder)
+        .with_content_dedup(config.dedupe_content)
+        .with_grace_poll(config.grace_poll)
+        .with_clear_after(config.clear_after)
+        .with_announce_new_datastore(config.announce_new_datastore)
+        .with_error_notifier(error_notifier(&config, &agent))
+        .with_status(Arc::clone(&status));
 
-    let datastore = datastore::Datastore::new(data_path)
-        .map(|store| Arc::new(Mutex::new(store)))
-        .map_err(|err| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("unable to open datastore at {}: {err}", data_path.display()),
-            )
-        })?;
+    if config.run_mode == RunMode::Once {
+        return run_once(&mut monitor, &feed, &config, webhook.as_ref(), &SystemClock);
+    }
+    let poller = Arc::new(Poller::new(
+        monitor,
+        Box::new(feed),
+        bushfire_point,
+        config.utc_offset,
+        webhook,
+    ));
 
-    let server_addr = (
-        env::var("WIZARDS_BOT_ADDRESS").unwrap_or_else(|_| String::from("0.0.0.0")),
-        env::var("WIZARDS_BOT_PORT")
-            .ok()
-            .and_then(|port| port.parse::<u16>().ok())
-            .unwrap_or(8888),
-    );
-    let server = match Server::new(server_addr.clone(), mm_token) {
+    let scheme = if config.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let builder = ServerBuilder::new(&config.mattermost_token)
+        .limits(config.limits)
+        .substitutions(config.substitutions.clone())
+        .shortlinks(config.max_shortlink_lookups.map(|max_lookups| Shortlinks {
+            resolver: Box::new(client::resolver_agent(&config.user_agent)),
+            max_lookups,
+        }))
+        .rate_limit(config.nit_rate_limit)
+        .max_message_length(config.max_message_length)
+        .status(status)
+        .tls(config.tls.clone())
+        .revision(config.revision.as_deref().unwrap_or("dev"))
+        .home_page(config.home_page.clone())
+        .poller(Arc::clone(&poller))
+        .base_path(&config.base_path)
+        .monitoring(Monitoring {
+            point: bushfire_point,
+            shape: config.alert_shape,
+            precision,
+        })
+        .test_notify(TestNotify {
+            options: config.message_options.clone(),
+            notifier: notifier(&config, &agent),
+        });
+    #[cfg(feature = "test-endpoints")]
+    let builder = builder.test_feed(TestFeed {
+        point: bushfire_point,
+        shape: config.alert_shape,
+        options: config.message_options.clone(),
+        notifier: notifier(&config, &agent),
+    });
+    let (server_addr, server) = if config.dual_stack {
+        let server = builder.dual_stack(config.port);
+        ((String::from("[::]"), config.port), server)
+    } else {
+        let server_addr = (config.address.clone(), config.port);
+        let server = builder.bind(server_addr.clone());
+        (server_addr, server)
+    };
+    let server = match server {
         Ok(server) => Arc::new(server),
         Err(err) => {
-            eprintln!(
-                "ERROR: Unable to start http server on {}:{}: {}",
-                server_addr.0, server_addr.1, err
+            error!(
+                "Unable to start {} server on {}:{}: {}",
+                scheme, server_addr.0, server_addr.1, err
             );
             process::exit(1);
         }
     };
-    println!(
-        "INFO: http server running on http://{}:{}",
-        server_addr.0, server_addr.1
+    info!(
+        "{} server running on {}://{}:{}{}",
+        scheme, scheme, server_addr.0, server_addr.1, server.base_path
     );
 
     // Handle HTTP requests
-    {
-        let server = Arc::clone(&server);
-        let thread = thread::spawn(move || {
-            server.handle_requests();
-            println!("INFO: server thread exiting");
-        });
-        threads.push(thread);
-    }
+    threads.extend(server.spawn_workers(config.http_workers));
 
-    // Set to the trigger value to cause an initial check on startup
-    let mut bushfire_wait = POLL_BUSHFIRE_FEED;
+    let mut jitter = Jitter::from_entropy(config.poll_jitter);
+    let mut bushfire_wait = 0;
+    let clock = SystemClock;
+    let mut last_backup = clock.now();
+    // Zero causes an initial check on startup
+    let mut next_poll = 0;
 
     // Wait for signals to exit
     while !term.load(Ordering::Relaxed) {
         thread::sleep(ONE_SECOND);
+        reopen_log_if_requested(&reopen_log);
         bushfire_wait += 1;
-        if bushfire_wait >= POLL_BUSHFIRE_FEED {
+        if bushfire_wait >= next_poll {
             bushfire_wait = 0;
-            let entries = match bushfire::check(bushfire_point) {
-                Ok(entries) => {
-                    println!("INFO: polled bushfire feed");
-                    entries
-                }
-                Err(err) => {
-                    let _ =
-                        post_webhook(&format!("unable to poll bushfire feed: {err}"), mm_webhook);
-                    continue;
-                }
-            };
-            if !entries.is_empty() {
-                let mut datastore = datastore.lock().unwrap();
-                for entry in entries {
-                    if !datastore.contains(&entry.id) {
-                        // notify about this entry
-                        println!("INFO: notify of incident {}", entry.id.0);
-                        match notify_entry(&entry, mm_webhook) {
-                            Ok(()) => {
-                                match datastore.append(entry.id) {
-                                    Ok(()) => (),
-                                    Err(err) => {
-                                        if let Err(notify_err) = post_webhook(
-                                            &format!("Unable to append entry to bushfire datastore: {err}"),
-                                            mm_webhook,
-                                        ) {
-                                            eprintln!("ERROR: Unable to append entry to bushfire datastore: {err}, error posting notification about that error: {notify_err}")
-                                        }
-                                        continue;
-                                    }
-                                }
-                            }
-                            Err(err) => {
-                                eprintln!(
-                                    "ERROR: Unable to post notification: {}: {}",
-                                    err.error, err.notification
-                                )
-                            }
-                        }
-                    }
-                }
-            }
+            poller.poll();
+            next_poll = jitter.apply(poller.poll_interval(POLL_BUSHFIRE_FEED));
+        }
+        if let Some(backups) = &config.backups {
+            backup_if_due(backups, &config.data_path, &mut last_backup, &clock);
         }
     }
     server.shutdown();
 
+    let unfinished = join_threads(threads, SHUTDOWN_TIMEOUT);
+    if unfinished > 0 {
+        error!("{unfinished} threads did not finish within {SHUTDOWN_TIMEOUT:?}, exiting anyway");
+    }
+
+    Ok(())
+}
+
+/// Back up the datastore at `path` if the interval has passed since `last_backup`, which is
+/// updated when it has. Elapsed time is used so slow polls don't delay backups.
+fn backup_if_due(
+    backups: &Backups,
+    path: &Path,
+    last_backup: &mut OffsetDateTime,
+    clock: &dyn Clock,
+) -> Option<PathBuf> {
+    let now = clock.now();
+    if !backups.is_due(*last_backup, now) {
+        return None;
+    }
+    *last_backup = now;
+    match backups.backup(path, now) {
+        Ok(Some(backup)) => {
+            info!("backed up datastore to {}", backup.display());
+            Some(backup)
+        }
+        Ok(None) => None,
+        Err(err) => {
+            error!("unable to back up datastore: {err}");
+            None
+        }
+    }
+}
+
+/// Reopen the log file if SIGHUP was received since the last check, returning true if it was.
+fn reopen_log_if_requested(requested: &AtomicBool) -> bool {
+    if !requested.swap(false, Ordering::Relaxed) {
+        return false;
+    }
+    match logging::reopen() {
+        Ok(()) => info!("reopened log file"),
+        Err(err) => error!("unable to reopen log file: {err}"),
+    }
+    true
+}
+
+/// Wait up to `timeout` for `threads` to finish, returning the number that are still running.
+///
+/// Threads that are still running are left detached, a request stuck on a slow webhook
+/// shouldn't stop the process exiting.
+fn join_threads(threads: Vec<JoinHandle<()>>, timeout: Duration) -> usize {
+    let deadline = Instant::now() + timeout;
+    while threads.iter().any(|thread| !thread.is_finished()) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut unfinished = 0;
     for thread in threads {
-        let _ = thread.join();
+        if thread.is_finished() {
+            let _ = thread.join();
+        } else {
+            unfinished += 1;
+        }
+    }
+    unfinished
+}
+
+/// The notifier for the bushfire webhook, which only logs notifications in a dry run.
+fn notifier(config: &Config, agent: &ureq::Agent) -> Box<dyn Notifier + Send + Sync> {
+    if config.dry_run {
+        Box::new(LogNotifier)
+    } else {
+        Box::new(
+            Webhook::new(config.bushfire_webhook.clone(), agent.clone())
+                .with_secret(config.webhook_secret.clone()),
+        )
     }
+}
+
+/// Where poll errors are posted when they don't go to the alert webhook.
+fn error_notifier(config: &Config, agent: &ureq::Agent) -> Option<Box<dyn Notifier + Send + Sync>> {
+    let url = config.error_webhook.as_ref().filter(|_| !config.dry_run)?;
+    Some(Box::new(
+        Webhook::new(url.clone(), agent.clone()).with_secret(config.webhook_secret.clone()),
+    ))
+}
 
+/// Perform a single poll of the feed, for cron-style invocation.
+///
+/// Fails if the feed couldn't be polled so the scheduler sees a non-zero exit status. Errors are
+/// posted on every failed run, as the error reminder interval only applies within one process.
+fn run_once(
+    monitor: &mut Monitor,
+    feed: &dyn Feed,
+    config: &Config,
+    notifier: &dyn Notifier,
+    clock: &dyn Clock,
+) -> Result<(), io::Error> {
+    let now = clock.now().to_offset(config.utc_offset);
+    monitor.poll(feed, config.bushfire_point, now, notifier);
+    if monitor.is_failing() {
+        return Err(io::Error::other("unable to poll bushfire feed"));
+    }
     Ok(())
 }
 
 pub struct Server {
     server: tiny_http::Server,
     mattermost_token: String,
+    limits: RequestLimits,
+    substitutions: Substitutions,
+    shortlinks: Option<Shortlinks>,
+    rate_limiter: Option<RateLimiter>,
+    /// Maximum characters in a slash command response
+    max_message_length: usize,
+    status: Arc<Status>,
+    home_html: String,
+    /// Prefix of all routes, without a trailing slash
+    base_path: String,
+    test_notify: Option<TestNotify>,
+    monitoring: Option<Monitoring>,
+    poller: Option<Arc<Poller>>,
+    #[cfg(feature = "test-endpoints")]
+    test_feed: Option<TestFeed>,
+    workers: AtomicUsize,
 }
 
-impl Server {
-    pub fn new<A>(addr: A, token: &str) -> Result<Server, Box<dyn Error + Send + Sync + 'static>>
-    where
-        A: ToSocketAddrs,
-    {
-        let mattermost_token = format!("Token {}", token);
-        tiny_http::Server::http(addr).map(|server| Server {
-            server,
-            mattermost_token,
+/// An endpoint served by [Server], listed at /api.
+///
+/// `GET` routes also respond to `HEAD` requests.
+struct Route {
+    method: Method,
+    /// Answer any method, as these paths did before routes were matched on method
+    any_method: bool,
+    path: &'static str,
+    description: &'static str,
+    handler: fn(&Server, &mut Request) -> HttpResponse,
+}
+
+type HttpResponse = Response<io::Cursor<Vec<u8>>>;
+
+const ROUTES: &[Route] = &[
+    Route {
+        method: Method::Get,
+        any_method: true,
+        path: "/",
+        description: "Home page",
+        handler: Server::home,
+    },
+    Route {
+        method: Method::Get,
+        any_method: false,
+        path: "/api",
+        description: "List of endpoints",
+        handler: Server::api,
+    },
+    Route {
+        method: Method::Get,
+        any_method: true,
+        path: "/health",
+        description: "Liveness check",
+        handler: Server::health,
+    },
+    Route {
+        method: Method::Get,
+        any_method: false,
+        path: "/ready",
+        description: "Readiness check, available once the feed has been polled",
+        handler: Server::ready,
+    },
+    Route {
+        method: Method::Post,
+        any_method: false,
+        path: "/nit",
+        description: "Substitute URLs in text, as a slash command or plain text",
+        handler: Server::nit,
+    },
+    Route {
+        method: Method::Get,
+        any_method: false,
+        path: "/history",
+        description: "Recent notifications and whether they were delivered",
+        handler: Server::get_history,
+    },
+    Route {
+        method: Method::Get,
+        any_method: false,
+        path: "/metrics",
+        description: "Prometheus metrics",
+        handler: Server::metrics,
+    },
+    Route {
+        method: Method::Get,
+        any_method: true,
+        path: "/style.css",
+        description: "Home page stylesheet",
+        handler: Server::stylesheet,
+    },
+];
+
+/// Reports what is being monitored when configured.
+const BUSHFIRE_CONFIG_ROUTE: Route = Route {
+    method: Method::Post,
+    any_method: false,
+    path: "/bushfire-config",
+    description: "Describe the monitored point and alert distance, as a slash command",
+    handler: Server::bushfire_config,
+};
+
+/// Polls the feed immediately when a poller is configured.
+const POLL_ROUTE: Route = Route {
+    method: Method::Post,
+    any_method: false,
+    path: "/poll",
+    description: "Poll the bushfire feed now, reporting the number of entries notified",
+    handler: Server::post_poll,
+};
+
+/// Sends a canned alert when enabled.
+const TEST_NOTIFY_ROUTE: Route = Route {
+    method: Method::Post,
+    any_method: false,
+    path: "/admin/test-notify",
+    description: "Send a test alert to the notification channel",
+    handler: Server::post_test_notify,
+};
+
+/// Where synthetic feeds are posted when enabled.
+#[cfg(feature = "test-endpoints")]
+const TEST_FEED_ROUTE: Route = Route {
+    method: Method::Post,
+    any_method: false,
+    path: "/test/feed",
+    description: "Notify about entries in a posted feed",
+    handler: Server::post_test_feed,
+};
+
+/// Configuration for a [Server].
+pub struct ServerBuilder {
+    token: String,
+    limits: RequestLimits,
+    substitutions: Substitutions,
+    shortlinks: Option<Shortlinks>,
+    rate_limit: Option<RateLimit>,
+    max_message_length: usize,
+    status: Arc<Status>,
+    tls: Option<TlsConfig>,
+    revision: String,
+    home_page: HomePage,
+    base_path: String,
+    test_notify: Option<TestNotify>,
+    monitoring: Option<Monitoring>,
+    poller: Option<Arc<Poller>>,
+    #[cfg(feature = "test-endpoints")]
+    test_feed: Option<TestFeed>,
+}
+
+/// Where entries posted to /test/feed are checked against and notified.
+#[cfg(feature = "test-endpoints")]
+pub struct TestFeed {
+    pub point: bushfire::LatLong,
+    pub shape: bushfire::AlertShape,
+    pub options: notify::MessageOptions,
+    pub notifier: Box<dyn Notifier + Send + Sync>,
+}
+
+/// Polls the bushfire feed, shared by the timer and /poll so that polls never overlap.
+pub struct Poller {
+    monitor: Mutex<Monitor>,
+    feed: Box<dyn Feed + Send + Sync>,
+    point: bushfire::LatLong,
+    utc_offset: UtcOffset,
+    notifier: Box<dyn Notifier + Send + Sync>,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+/// Operator supplied details shown on the home page.
+#[derive(Debug, Clone, Default)]
+pub struct HomePage {
+    /// Replaces the default title, "Wizards Bot"
+    pub title: Option<String>,
+    /// How to reach whoever runs the instance
+    pub contact: Option<String>,
+    /// Description of the area monitored for bushfires
+    pub area: Option<String>,
+}
+
+/// What is being monitored, reported by /bushfire-config.
+pub struct Monitoring {
+    pub point: bushfire::LatLong,
+    pub shape: bushfire::AlertShape,
+    /// Decimal places the coordinates are shown to
+    pub precision: usize,
+}
+
+/// Where canned alerts posted to /admin/test-notify are sent.
+pub struct TestNotify {
+    pub options: notify::MessageOptions,
+    pub notifier: Box<dyn Notifier + Send + Sync>,
+}
+
+/// Limits applied when reading request bodies.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    /// Maximum size of a request body in bytes.
+    pub max_body_size: u64,
+    /// Maximum time allowed for reading a request body, checked between reads.
+    pub read_timeout: Duration,
+}
+
+/// Why a request couldn't be handled, which determines the response status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandlerError {
+    /// The request was malformed, with a description of the problem
+    BadRequest(String),
+    Unauthorized,
+    NotFound,
+    RequestTimeout,
+    PayloadTooLarge,
+    TooManyRequests,
+    /// A notification couldn't be posted, with the reason
+    BadGateway(String),
+    Internal,
+}
+
+/// Maximum number of requests accepted in each period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+/// Counts requests in fixed windows of the rate limit period.
+struct RateLimiter {
+    limit: RateLimit,
+    /// Start of the current window and the number of requests in it
+    window: Mutex<(Instant, u32)>,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        }
+    }
+}
+
+impl RateLimit {
+    pub fn parse(value: &str) -> Option<RateLimit> {
+        let (requests, seconds) = value.split_once('/')?;
+        let requests = requests.trim().parse().ok()?;
+        let seconds = seconds.trim().parse().ok().filter(|&seconds| seconds > 0)?;
+        Some(RateLimit {
+            requests,
+            period: Duration::from_secs(seconds),
         })
     }
+}
+
+impl RateLimiter {
+    fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
 
-    pub fn handle_requests(&self) {
-        for mut request in self.server.incoming_requests() {
-            let response = match request.url() {
-                "/" => Response::from_string(&*HOME_HTML).with_header(HTML_CONTENT_TYPE.clone()),
-                "/nit" => {
-                    if request.method() == &Method::Post {
-                        let (obj, status) = self.nit_slash_command(&mut request);
-                        let body = json::stringify_pretty(obj, 2);
-                        Response::from_string(body)
-                            .with_header(JSON_CONTENT_TYPE.clone())
-                            .with_status_code(status)
-                    } else {
-                        Response::from_string(NOT_FOUND)
-                            .with_header(HTML_CONTENT_TYPE.clone())
-                            .with_status_code(404)
-                    }
-                }
-                "/style.css" => Response::from_string(CSS).with_header(CSS_CONTENT_TYPE.clone()),
-                _ => Response::from_string(NOT_FOUND)
-                    .with_header(HTML_CONTENT_TYPE.clone())
-                    .with_status_code(404),
-            };
+    /// Count a request, returning false if it exceeds the limit.
+    fn allow(&self) -> bool {
+        // NOTE(unwrap): The lock is never held across a panic
+        let mut window = self.window.lock().unwrap();
+        let now = Instant::now();
+        if now.duration_since(window.0) >= self.limit.period {
+            *window = (now, 0);
+        }
+        if window.1 >= self.limit.requests {
+            return false;
+        }
+        window.1 += 1;
+        true
+    }
+}
 
-            // Ignoring I/O errors that occur here so that we don't take down the process if there
-            // is an issue sending the response.
-            let _ = request.respond(response);
+impl Poller {
+    pub fn new(
+        monitor: Monitor,
+        feed: Box<dyn Feed + Send + Sync>,
+        point: bushfire::LatLong,
+        utc_offset: UtcOffset,
+        notifier: Box<dyn Notifier + Send + Sync>,
+    ) -> Self {
+        Poller {
+            monitor: Mutex::new(monitor),
+            feed,
+            point,
+            utc_offset,
+            notifier,
+            clock: Arc::new(SystemClock),
         }
     }
 
-    fn nit_slash_command(&self, request: &mut Request) -> (JsonValue, StatusCode) {
-        let (content_type, authorization) = match Self::validate_request(request) {
-            Ok(headers) => headers,
-            Err((message, status)) => {
-                return (object! {error: message}, status);
-            }
+    /// Read the current time from `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock + Send + Sync>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Poll the feed now, returning the number of entries notified.
+    pub fn poll(&self) -> usize {
+        // A poll that panicked leaves the monitor usable, so carry on rather than poisoning every
+        // later poll
+        let mut monitor = self.monitor.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = self.clock.now().to_offset(self.utc_offset);
+        monitor.poll(&*self.feed, self.point, now, &*self.notifier)
+    }
+
+    /// The number of seconds to wait before the next poll, see [Monitor::poll_interval].
+    pub fn poll_interval(&self, interval: u32) -> u32 {
+        self.monitor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .poll_interval(interval)
+    }
+}
+
+impl HomePage {
+    /// Fill in the placeholders in the home page template, falling back to the defaults for
+    /// anything that isn't configured. Links are made absolute under `base_path`.
+    fn render(&self, revision: &str, base_path: &str) -> String {
+        let title = escape_html(self.title.as_deref().unwrap_or("Wizards Bot"));
+        let paragraph = |text: Option<&str>, prefix: &str| {
+            text.map(|text| format!("<p>{prefix}{}</p>", escape_html(text)))
+                .unwrap_or_default()
         };
+        let area = paragraph(self.area.as_deref(), "Watching for bushfires near ");
+        let contact = paragraph(self.contact.as_deref(), "Contact: ");
+        // NOTE(unwrap): the pattern is valid
+        let placeholder = Regex::new(r"\$([a-z]+)\$").unwrap();
+        placeholder
+            .replace_all(HTML, |captures: &Captures<'_>| match &captures[1] {
+                "title" => title.clone(),
+                "area" => area.clone(),
+                "contact" => contact.clone(),
+                "rev" => revision.to_string(),
+                "base" => escape_html(base_path),
+                _ => captures[0].to_string(),
+            })
+            .into_owned()
+    }
+}
 
-        if content_type.value != "application/x-www-form-urlencoded" {
-            return (object! {error: "Bad request"}, StatusCode::from(400));
+/// Escape `text` for inclusion in HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl ServerBuilder {
+    /// Start building a server that accepts requests with the Mattermost `token`.
+    pub fn new(token: &str) -> Self {
+        ServerBuilder {
+            token: token.to_string(),
+            limits: RequestLimits::default(),
+            substitutions: Substitutions::default(),
+            shortlinks: None,
+            rate_limit: None,
+            max_message_length: DEFAULT_MAX_MESSAGE_LENGTH,
+            status: Arc::default(),
+            tls: None,
+            revision: String::from("dev"),
+            home_page: HomePage::default(),
+            base_path: String::new(),
+            test_notify: None,
+            monitoring: None,
+            poller: None,
+            #[cfg(feature = "test-endpoints")]
+            test_feed: None,
         }
+    }
 
-        if !self.verify_token(authorization.value.as_str()) {
-            return (object! {error: "Not authorised"}, StatusCode::from(401));
+    /// Limits applied when reading request bodies.
+    pub fn limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Maximum size of a request body in bytes.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.limits.max_body_size = max_body_size;
+        self
+    }
+
+    /// Configure how URLs in /nit messages are substituted.
+    pub fn substitutions(mut self, substitutions: Substitutions) -> Self {
+        self.substitutions = substitutions;
+        self
+    }
+
+    /// Expand shortened links in /nit text before substituting them.
+    pub fn shortlinks(mut self, shortlinks: Option<Shortlinks>) -> Self {
+        self.shortlinks = shortlinks;
+        self
+    }
+
+    /// Limit the rate of /nit requests, unlimited if `None`.
+    pub fn rate_limit(mut self, rate_limit: Option<RateLimit>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Truncate /nit slash command responses to `max_message_length` characters.
+    pub fn max_message_length(mut self, max_message_length: usize) -> Self {
+        self.max_message_length = max_message_length;
+        self
+    }
+
+    /// Report readiness on /ready from `status`.
+    pub fn status(mut self, status: Arc<Status>) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Serve HTTPS if `tls` is present.
+    pub fn tls(mut self, tls: Option<TlsConfig>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Revision shown on the home page.
+    pub fn revision(mut self, revision: &str) -> Self {
+        self.revision = revision.to_string();
+        self
+    }
+
+    /// Customise the home page with `home_page`.
+    pub fn home_page(mut self, home_page: HomePage) -> Self {
+        self.home_page = home_page;
+        self
+    }
+
+    /// Serve all routes under `base_path`, for when a reverse proxy doesn't strip the prefix.
+    pub fn base_path(mut self, base_path: &str) -> Self {
+        let base_path = base_path.trim_matches('/');
+        self.base_path = if base_path.is_empty() {
+            String::new()
+        } else {
+            format!("/{base_path}")
+        };
+        self
+    }
+
+    /// Report the monitoring point and alert region with the /bushfire-config slash command.
+    pub fn monitoring(mut self, monitoring: Monitoring) -> Self {
+        self.monitoring = Some(monitoring);
+        self
+    }
+
+    /// Poll the feed with `poller` when /poll is posted to.
+    pub fn poller(mut self, poller: Arc<Poller>) -> Self {
+        self.poller = Some(poller);
+        self
+    }
+
+    /// Send a canned alert when /admin/test-notify is posted to.
+    pub fn test_notify(mut self, test_notify: TestNotify) -> Self {
+        self.test_notify = Some(test_notify);
+        self
+    }
+
+    /// Accept synthetic feeds on /test/feed, notifying about entries near the point in `feed`.
+    #[cfg(feature = "test-endpoints")]
+    pub fn test_feed(mut self, feed: TestFeed) -> Self {
+        self.test_feed = Some(feed);
+        self
+    }
+
+    /// Create a server listening on `addr`.
+    pub fn bind<A>(self, addr: A) -> Result<Server, Box<dyn Error + Send + Sync + 'static>>
+    where
+        A: ToSocketAddrs,
+    {
+        let config = ServerConfig {
+            addr: ConfigListenAddr::from_socket_addrs(addr)?,
+            ssl: self.tls.as_ref().map(ssl_config).transpose()?,
+        };
+        tiny_http::Server::new(config).map(|server| self.build(server))
+    }
+
+    /// Create a server listening on `port` on all IPv6 and IPv4 addresses.
+    pub fn dual_stack(self, port: u16) -> Result<Server, Box<dyn Error + Send + Sync + 'static>> {
+        let listener = dual_stack_listener(port)?;
+        let ssl = self.tls.as_ref().map(ssl_config).transpose()?;
+        tiny_http::Server::from_listener(listener, ssl).map(|server| self.build(server))
+    }
+
+    fn build(self, server: tiny_http::Server) -> Server {
+        Server {
+            server,
+            mattermost_token: format!("Token {}", self.token),
+            limits: self.limits,
+            substitutions: self.substitutions,
+            shortlinks: self.shortlinks,
+            rate_limiter: self.rate_limit.map(RateLimiter::new),
+            max_message_length: self.max_message_length,
+            status: self.status,
+            home_html: self.home_page.render(&self.revision, &self.base_path),
+            base_path: self.base_path,
+            test_notify: self.test_notify,
+            monitoring: self.monitoring,
+            poller: self.poller,
+            #[cfg(feature = "test-endpoints")]
+            test_feed: self.test_feed,
+            workers: AtomicUsize::new(0),
         }
+    }
+}
 
-        // Get the text field of the form data
-        let mut body = Vec::new();
-        if request.as_reader().read_to_end(&mut body).is_err() {
-            return (
-                object! {error: "Internal server error"},
-                StatusCode::from(500),
-            );
+impl Server {
+    /// Spawn `count` threads that handle requests until the server is shutdown.
+    pub fn spawn_workers(self: &Arc<Self>, count: usize) -> Vec<JoinHandle<()>> {
+        self.workers.fetch_add(count, Ordering::SeqCst);
+        (0..count)
+            .map(|_| {
+                let server = Arc::clone(self);
+                thread::spawn(move || {
+                    server.handle_requests();
+                    info!("server thread exiting");
+                })
+            })
+            .collect()
+    }
+
+    /// The route for `url` with the base path removed, `None` if it's outside the base path.
+    fn route<'a>(&self, url: &'a str) -> Option<&'a str> {
+        match url.strip_prefix(&self.base_path)? {
+            "" => Some("/"),
+            path if path.starts_with('/') => Some(path),
+            _ => None,
         }
-        match form_urlencoded::parse(&body).find(|(key, _value)| key == "text") {
-            Some((_key, text)) if !is_blank(&text) => (
-                object! {
-                  "response_type": "in_channel",
-                  "text": &*substitute_urls(&text),
-                },
-                StatusCode::from(200),
-            ),
-            Some(_) | None => (
+    }
+
+    /// The endpoints this server handles.
+    fn routes(&self) -> impl Iterator<Item = &Route> {
+        #[cfg(feature = "test-endpoints")]
+        let test_feed = self.test_feed.as_ref().map(|_| &TEST_FEED_ROUTE);
+        #[cfg(not(feature = "test-endpoints"))]
+        let test_feed = None;
+        let monitoring = self.monitoring.as_ref().map(|_| &BUSHFIRE_CONFIG_ROUTE);
+        let poller = self.poller.as_ref().map(|_| &POLL_ROUTE);
+        let test_notify = self.test_notify.as_ref().map(|_| &TEST_NOTIFY_ROUTE);
+        ROUTES
+            .iter()
+            .chain(monitoring)
+            .chain(poller)
+            .chain(test_notify)
+            .chain(test_feed)
+    }
+
+    /// List the endpoints and their methods.
+    fn api_index(&self) -> JsonValue {
+        let routes: Vec<JsonValue> = self
+            .routes()
+            .map(|route| {
                 object! {
-                    "response_type": "ephemeral",
-                    "text": "You need to supply some text",
-                },
-                StatusCode::from(200),
-            ),
+                    method: route.method.as_str(),
+                    path: format!("{}{}", self.base_path, route.path),
+                    description: route.description,
+                }
+            })
+            .collect();
+        object! { routes: routes }
+    }
+
+    pub fn handle_requests(&self) {
+        for mut request in self.server.incoming_requests() {
+            let response = self.handle_one(&mut request);
+
+            // Ignoring I/O errors that occur here so that we don't take down the process if there
+            // is an issue sending the response.
+            let _ = request.respond(response);
         }
     }
 
-    fn validate_request(request: &Request) -> Result<(&Header, &Header), (String, StatusCode)> {
-        const BAD_REQUEST: u16 = 400;
+    /// Respond to `request` with the handler of the matching route, or 404 if there isn't one.
+    pub fn handle_one(&self, request: &mut Request) -> HttpResponse {
+        let route = self.route(request.url()).and_then(|path| {
+            let method = request.method();
+            self.routes().find(|route| {
+                route.path == path
+                    && (route.any_method
+                        || route.method == *method
+                        || (route.method == Method::Get && *method == Method::Head))
+            })
+        });
+        match route {
+            Some(route) => (route.handler)(self, request),
+            None => Response::from_string(NOT_FOUND)
+                .with_header(HTML_CONTENT_TYPE.clone())
+                .with_status_code(404),
+        }
+    }
 
-        // Extract required headers
-        let content_type = request
-            .headers()
-            .iter()
-            .find(|&header| header.field == *CONTENT_TYPE)
-            .ok_or_else(|| {
-                (
-                    String::from("Content-Type header not found"),
-                    StatusCode::from(BAD_REQUEST),
-                )
-            })?;
-        let authorization = request
-            .headers()
-            .iter()
-            .find(|&header| header.field == *AUTHORIZATION)
-            .ok_or_else(|| {
-                (
-                    String::from("Authorization header not found"),
-                    StatusCode::from(BAD_REQUEST),
-                )
-            })?;
-        Ok((content_type, authorization))
+    fn home(&self, _request: &mut Request) -> HttpResponse {
+        Response::from_string(&self.home_html).with_header(HTML_CONTENT_TYPE.clone())
     }
 
-    fn verify_token(&self, token: &str) -> bool {
-        token == self.mattermost_token
+    fn api(&self, _request: &mut Request) -> HttpResponse {
+        Response::from_string(json::stringify_pretty(self.api_index(), 2))
+            .with_header(JSON_CONTENT_TYPE.clone())
     }
 
-    pub fn shutdown(&self) {
-        self.server.unblock();
+    fn health(&self, _request: &mut Request) -> HttpResponse {
+        Response::from_string("OK").with_header(TEXT_CONTENT_TYPE.clone())
     }
-}
 
-struct NotifyError {
-    notification: String,
-    error: ureq::Error,
-}
+    fn ready(&self, _request: &mut Request) -> HttpResponse {
+        if self.status.is_ready() {
+            Response::from_string("OK").with_header(TEXT_CONTENT_TYPE.clone())
+        } else {
+            Response::from_string("Not ready")
+                .with_header(TEXT_CONTENT_TYPE.clone())
+                .with_status_code(503)
+        }
+    }
 
-fn notify_entry(entry: &Entry, webhook: &str) -> Result<(), NotifyError> {
-    let location_url = entry.point.map(|(lat, lon)| {
-        format!(
-            "https://duckduckgo.com/?t=h_&q={}%2C{}&ia=web&iaxm=maps",
-            lat, lon
-        )
-    });
-    let message = format!(
-        "#### ⚠️ {category}\n\n[**{title}**]({map_link})\n\n{content}\n\n**Published:** {published}\n**Link:** {link}",
-        category = entry.category.as_deref().unwrap_or("Unknown Category"),
-        title = entry.title.as_deref().unwrap_or("Untitled"),
-        content = entry.content.as_deref().unwrap_or("No content"),
-        published = entry
-            .published
-            .and_then(|published| published.format(&Rfc2822).ok())
-            .as_deref()
-            .unwrap_or("unknown"),
-        link = BUSHFIRE_PAGE,
-        map_link = location_url.as_deref().unwrap_or(BUSHFIRE_PAGE),
-    );
-    post_webhook(&message, webhook).map_err(|error| NotifyError {
-        notification: message,
-        error,
-    })
-}
+    fn nit(&self, request: &mut Request) -> HttpResponse {
+        if Self::wants_plain_text(request) {
+            text_response(self.nit_plain_text(request))
+        } else {
+            json_response(self.nit_slash_command(request))
+        }
+    }
 
-fn post_webhook(message: &str, webhook: &str) -> Result<(), ureq::Error> {
-    let body = object! {
-        text: message
-    };
+    fn metrics(&self, _request: &mut Request) -> HttpResponse {
+        Response::from_string(metrics::render()).with_header(METRICS_CONTENT_TYPE.clone())
+    }
 
-    ureq::post(webhook)
-        .set("Content-Type", "application/json")
-        .send_string(&json::stringify(body))
-        .map(drop)
-}
+    fn stylesheet(&self, _request: &mut Request) -> HttpResponse {
+        Response::from_string(CSS).with_header(CSS_CONTENT_TYPE.clone())
+    }
 
-fn is_blank(text: &str) -> bool {
-    text.chars().all(|ch| ch.is_whitespace())
-}
+    fn bushfire_config(&self, request: &mut Request) -> HttpResponse {
+        json_response(self.bushfire_config_command(request))
+    }
 
-static URL_REGEX: Lazy<Regex> = Lazy::new(||
-    // https://www.regextester.com/94502
-    Regex::new(r"https?://[[:word:].-]+(?:\.[[:word:].-]+)+[[:word:]\-._~:/?#\[\]@!$&'()*+,;=]+").unwrap());
+    fn get_history(&self, request: &mut Request) -> HttpResponse {
+        json_response(self.history(request))
+    }
 
-fn substitute_urls(text: &str) -> Cow<'_, str> {
-    URL_REGEX.replace_all(text, maybe_replace_url)
-}
+    fn post_poll(&self, request: &mut Request) -> HttpResponse {
+        json_response(self.poll_now(request))
+    }
 
-fn maybe_replace_url(captures: &Captures<'_>) -> String {
-    // NOTE(unwrap): captures 0 should always be present and it should be parseable as a URL due
-    // to matching the regex.
-    let url0 = captures.get(0).unwrap().as_str();
-    let mut url: Url = url0.parse().unwrap();
+    fn post_test_notify(&self, request: &mut Request) -> HttpResponse {
+        text_response(self.test_notify(request))
+    }
 
-    if url.host_str().map_or(false, |host| {
-        host == "x.com" || host.ends_with("twitter.com")
-    }) {
-        let _ = url.set_host(Some("nitter.net"));
-        // Nitter doesn't like Twitter's new tracking params so strip query string and hope for the
-        // best.
-        url.set_query(None);
-        format!("{} ([source]({}))", url, url0)
-    } else if url
-        .host_str()
-        .map_or(false, |host| host.ends_with("medium.com"))
-    {
-        let _ = url.set_host(Some("scribe.rip"));
-        format!("{} ([source]({}))", url, url0)
-    } else {
-        // Return original url
-        url0.to_string()
+    #[cfg(feature = "test-endpoints")]
+    fn post_test_feed(&self, request: &mut Request) -> HttpResponse {
+        text_response(self.test_feed(request))
     }
-}
+
+    fn nit_slash_command(&self, request: &mut Request) -> Result<JsonValue, HandlerError> {
+        match self.nit_text(request)? {
+            Some(text) => Ok(object! {
+              "response_type": "in_channel",
+              "text": substitute::truncate(&text, self.max_message_length).as_ref(),
+            }),
+            None => Ok(object! {
+                "response_type": "ephemeral",
+                "text": "You need to supply some text",
+            }),
+        }
+    }
+
+    fn bushfire_config_command(&self, request: &Request) -> Result<JsonValue, HandlerError> {
+        self.authorise_slash_command(request)?;
+        Ok(object! {
+            "response_type": "ephemeral",
+            "text": self.monitoring_description(),
+        })
+    }
+
+    /// Handle /nit for non-Mattermost callers, responding with just the substituted text.
+    fn nit_plain_text(&self, request: &mut Request) -> Result<String, HandlerError> {
+        self.nit_text(request)?
+            .ok_or_else(|| HandlerError::BadRequest(String::from("You need to supply some text")))
+    }
+
+    /// Authenticate the request and substitute URLs in the supplied text.
+    ///
+    /// Returns `None` if the text field is missing or blank.
+    fn nit_text(&self, request: &mut Request) -> Result<Option<String>, HandlerError> {
+        self.authorise_slash_command(request)?;
+
+        if self
+            .rate_limiter
+            .as_ref()
+            .is_some_and(|limiter| !limiter.allow())
+        {
+            return Err(HandlerError::TooManyRequests);
+        }
+
+        if request
+            .body_length()
+            .is_some_and(|len| len as u64 > self.limits.max_body_size)
+        {
+            return Err(HandlerError::PayloadTooLarge);
+        }
+
+        // Get the text field of the form data
+        let body = read_body(request.as_reader(), self.limits)?;
+        match form_urlencoded::parse(&body).find(|(key, _value)| key == "text") {
+            Some((_key, text)) if !is_blank(&text) => {
+                let (text, expand) = match text.strip_prefix("expand ") {
+                    Some(text) => (text, true),
+                    None => (&*text, self.substitutions.expand),
+                };
+                let text = match &self.shortlinks {
+                    Some(shortlinks) => shortlinks.expand(text),
+                    None => text.into(),
+                };
+                let substitutions = Substitutions {
+                    expand,
+                    ..self.substitutions.clone()
+                };
+                Ok(Some(substitutions.apply(&text).into_owned()))
+            }
+            Some(_) | None => Ok(None),
+        }
+    }
+
+    /// Check a slash command request is form data with the expected token.
+    fn authorise_slash_command(&self, request: &Request) -> Result<(), HandlerError> {
+        let (content_type, authorization) = Self::validate_request(request)?;
+
+        if content_type.value != "application/x-www-form-urlencoded" {
+            return Err(HandlerError::BadRequest(String::from("Bad request")));
+        }
+
+        if !self.verify_token(authorization.value.as_str()) {
+            return Err(HandlerError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    /// Describe the monitored point and the region alerts are sent for.
+    fn monitoring_description(&self) -> String {
+        let Some(monitoring) = &self.monitoring else {
+            return String::from("Bushfire monitoring is not configured");
+        };
+        let precision = monitoring.precision;
+        let (lat, lon) = monitoring.point;
+        let distance = bushfire::ALERT_DISTANCE;
+        let region = match monitoring.shape {
+            bushfire::AlertShape::Circle => format!("within {distance} km of"),
+            bushfire::AlertShape::Box => {
+                format!("within a square extending {distance} km either side of")
+            }
+            bushfire::AlertShape::Polygon => {
+                format!("with an incident area containing, or otherwise within {distance} km of,")
+            }
+            bushfire::AlertShape::Region(region) => {
+                let ((min_lat, min_lon), (max_lat, max_lon)) = (region.min, region.max);
+                return format!(
+                    "Monitoring for bushfire incidents between {min_lat:.precision$}, {min_lon:.precision$} and {max_lat:.precision$}, {max_lon:.precision$}"
+                );
+            }
+        };
+        format!("Monitoring for bushfire incidents {region} {lat:.precision$}, {lon:.precision$}")
+    }
+
+    /// Poll the feed, waiting for any poll already in progress to finish first.
+    fn poll_now(&self, request: &Request) -> Result<JsonValue, HandlerError> {
+        let poller = self.poller.as_ref().ok_or(HandlerError::NotFound)?;
+        if !self.authorised(request) {
+            return Err(HandlerError::Unauthorized);
+        }
+        info!("polling bushfire feed on request");
+        let notified = poller.poll();
+        Ok(object! { notified: notified })
+    }
+
+    /// List recent notifications, newest first.
+    fn history(&self, request: &Request) -> Result<JsonValue, HandlerError> {
+        if !self.authorised(request) {
+            return Err(HandlerError::Unauthorized);
+        }
+        let notifications: Vec<JsonValue> = self
+            .status
+            .history()
+            .into_iter()
+            .map(|notification| {
+                object! {
+                    id: notification.id,
+                    title: notification.title,
+                    severity: notification.severity.name(),
+                    time: notification.time.format(&Rfc3339).ok(),
+                    delivered: notification.delivered,
+                }
+            })
+            .collect();
+        Ok(object! { notifications: notifications })
+    }
+
+    /// Determine if `request` has an Authorization header with the Mattermost token.
+    fn authorised(&self, request: &Request) -> bool {
+        request
+            .headers()
+            .iter()
+            .find(|&header| header.field == *AUTHORIZATION)
+            .is_some_and(|authorization| self.verify_token(authorization.value.as_str()))
+    }
+
+    /// Post a canned alert, to check notifications are delivered and how they look.
+    fn test_notify(&self, request: &Request) -> Result<String, HandlerError> {
+        let test_notify = self.test_notify.as_ref().ok_or(HandlerError::NotFound)?;
+        if !self.authorised(request) {
+            return Err(HandlerError::Unauthorized);
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let entry = bushfire::Entry {
+            id: bushfire::EntryId(String::from("wizards-bot-test")),
+            categories: vec![String::from("Advice")],
+            title: Some(String::from("Test alert from wizards-bot")),
+            content: Some(String::from(
+                "This is a test of bushfire notifications, no action is required.",
+            )),
+            published: Some(now),
+            point: test_notify.options.reference,
+            ..Default::default()
+        };
+        info!("sending test notification");
+        notify::notify_entry(
+            &entry,
+            &test_notify.options,
+            now,
+            None,
+            &*test_notify.notifier,
+        )
+        .map_err(|err| HandlerError::BadGateway(err.error.to_string()))?;
+        Ok(String::from("Notification posted"))
+    }
+
+    /// Parse the feed XML in the body and notify about entries near the test feed point.
+    #[cfg(feature = "test-endpoints")]
+    fn test_feed(&self, request: &mut Request) -> Result<String, HandlerError> {
+        let test_feed = self.test_feed.as_ref().ok_or(HandlerError::NotFound)?;
+        let (_content_type, authorization) = Self::validate_request(request)?;
+        if !self.verify_token(authorization.value.as_str()) {
+            return Err(HandlerError::Unauthorized);
+        }
+
+        let body = read_body(request.as_reader(), self.limits)?;
+        let entries = bushfire::parse_feed(
+            &String::from_utf8_lossy(&body),
+            test_feed.point,
+            test_feed.shape,
+        )
+        .map_err(|err| HandlerError::BadRequest(err.to_string()))?;
+        let now = OffsetDateTime::now_utc();
+        for entry in &entries {
+            info!(incident = entry.id.0; "notify of test incident {}", entry.id.0);
+            notify::notify_entry(entry, &test_feed.options, now, None, &*test_feed.notifier)
+                .map_err(|err| HandlerError::BadGateway(err.error.to_string()))?;
+        }
+        Ok(format!("Notified {} entries", entries.len()))
+    }
+
+    /// Determine if the client asked for a plain text response.
+    fn wants_plain_text(request: &Request) -> bool {
+        request
+            .headers()
+            .iter()
+            .find(|&header| header.field == *ACCEPT)
+            .is_some_and(|header| header.value.as_str().contains("text/plain"))
+    }
+
+    fn validate_request(request: &Request) -> Result<(&Header, &Header), HandlerError> {
+        // Extract required headers
+        let content_type = request
+            .headers()
+            .iter()
+            .find(|&header| header.field == *CONTENT_TYPE)
+            .ok_or_else(|| {
+                HandlerError::BadRequest(String::from("Content-Type header not found"))
+            })?;
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|&header| header.field == *AUTHORIZATION)
+            .ok_or_else(|| {
+                HandlerError::BadRequest(String::from("Authorization header not found"))
+            })?;
+        Ok((content_type, authorization))
+    }
+
+    fn verify_token(&self, token: &str) -> bool {
+        token == self.mattermost_token
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.server.server_addr().to_ip().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "server is not listening on an IP address",
+            )
+        })
+    }
+
+    pub fn shutdown(&self) {
+        // Each call to unblock releases one thread
+        for _ in 0..self.workers.load(Ordering::SeqCst).max(1) {
+            self.server.unblock();
+        }
+    }
+}
+
+impl HandlerError {
+    /// The status of the response for this error.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from(match self {
+            HandlerError::BadRequest(_) => 400,
+            HandlerError::Unauthorized => 401,
+            HandlerError::NotFound => 404,
+            HandlerError::RequestTimeout => 408,
+            HandlerError::PayloadTooLarge => 413,
+            HandlerError::TooManyRequests => 429,
+            HandlerError::Internal => 500,
+            HandlerError::BadGateway(_) => 502,
+        })
+    }
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandlerError::BadRequest(message) => f.write_str(message),
+            HandlerError::Unauthorized => f.write_str("Not authorised"),
+            HandlerError::NotFound => f.write_str("Not found"),
+            HandlerError::RequestTimeout => f.write_str("Request timeout"),
+            HandlerError::PayloadTooLarge => f.write_str("Payload too large"),
+            HandlerError::TooManyRequests => f.write_str("Too many requests"),
+            HandlerError::BadGateway(reason) => write!(f, "Unable to post notification: {reason}"),
+            HandlerError::Internal => f.write_str("Internal server error"),
+        }
+    }
+}
+
+impl Error for HandlerError {}
+
+/// Respond with `result` as JSON, errors are an object with an `error` message.
+fn json_response(result: Result<JsonValue, HandlerError>) -> HttpResponse {
+    let (obj, status) = match result {
+        Ok(obj) => (obj, StatusCode::from(200)),
+        Err(err) => (object! { error: err.to_string() }, err.status()),
+    };
+    Response::from_string(json::stringify_pretty(obj, 2))
+        .with_header(JSON_CONTENT_TYPE.clone())
+        .with_status_code(status)
+}
+
+/// Respond with `result` as plain text.
+fn text_response(result: Result<String, HandlerError>) -> HttpResponse {
+    let (text, status) = match result {
+        Ok(text) => (text, StatusCode::from(200)),
+        Err(err) => (err.to_string(), err.status()),
+    };
+    Response::from_string(text)
+        .with_header(TEXT_CONTENT_TYPE.clone())
+        .with_status_code(status)
+}
+
+/// Read the certificate and private key files.
+fn ssl_config(tls: &TlsConfig) -> io::Result<SslConfig> {
+    Ok(SslConfig {
+        certificate: fs::read(&tls.certificate)?,
+        private_key: fs::read(&tls.private_key)?,
+    })
+}
+
+/// Bind `[::]:port` with IPv4-mapped addresses enabled so both stacks are served.
+fn dual_stack_listener(port: u16) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(false)?;
+    socket.set_reuse_address(true)?;
+    let addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+    socket.bind(&addr.into())?;
+    socket.listen(128)?;
+    Ok(socket.into())
+}
+
+/// Read a request body, enforcing the size and time limits.
+fn read_body<R: Read>(reader: R, limits: RequestLimits) -> Result<Vec<u8>, HandlerError> {
+    let mut reader = DeadlineReader {
+        inner: reader,
+        deadline: Instant::now() + limits.read_timeout,
+    }
+    .take(limits.max_body_size + 1);
+    let mut body = Vec::new();
+    match reader.read_to_end(&mut body) {
+        Ok(_) if body.len() as u64 > limits.max_body_size => Err(HandlerError::PayloadTooLarge),
+        Ok(_) => Ok(body),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            ) =>
+        {
+            Err(HandlerError::RequestTimeout)
+        }
+        Err(_) => Err(HandlerError::Internal),
+    }
+}
+
+/// A reader that fails with `TimedOut` once the deadline has passed.
+///
+/// tiny_http doesn't expose the underlying socket so the deadline is checked between reads. This
+/// stops a client trickling bytes from holding the connection open indefinitely. A client that
+/// stops sending part way through a body still holds the worker until it disconnects, as tiny_http
+/// reads the rest of the body before the connection is released.
+struct DeadlineReader<R> {
+    inner: R,
+    deadline: Instant,
+}
+
+impl<R: Read> Read for DeadlineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if Instant::now() >= self.deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out reading request body",
+            ));
+        }
+        self.inner.read(buf)
+    }
+}
+
+fn is_blank(text: &str) -> bool {
+    text.chars().all(|ch| ch.is_whitespace())
+}
 
 #[cfg(test)]
 mod tests {
+    use std::borrow::Cow;
+    use std::env;
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    use tiny_http::TestRequest;
+
+    use crate::bushfire::{BushfireError, Entry, EntryId, LatLong};
+    use crate::clock::tests::MockClock;
+    use crate::datastore::Datastore;
+    use crate::notify::tests::RecordingNotifier;
+
     use super::*;
 
+    /// The files backing a test's datastore and retry queue, removed when dropped.
+    struct TempData(PathBuf);
+
+    impl TempData {
+        fn new(name: &str) -> Self {
+            let data =
+                TempData(env::temp_dir().join(format!("wizards-bot-{name}-{}", process::id())));
+            data.remove();
+            data
+        }
+
+        fn remove(&self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(datastore::retry_path(&self.0));
+        }
+    }
+
+    impl Drop for TempData {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    /// A feed of entries with the given ids and categories, which fails while `failing` is set.
+    #[derive(Default)]
+    struct StubFeed {
+        entries: Vec<(&'static str, &'static str)>,
+        failing: Arc<AtomicBool>,
+        checks: Arc<AtomicUsize>,
+    }
+
+    impl Feed for StubFeed {
+        fn check(&self, _: LatLong) -> Result<Vec<Entry>, BushfireError> {
+            self.checks.fetch_add(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(BushfireError::Io(io::Error::other("connection refused")));
+            }
+            let entries = self.entries.iter().map(|&(id, category)| Entry {
+                id: EntryId(id.to_string()),
+                categories: vec![category.to_string()],
+                title: Some(format!("Incident {id}")),
+                ..Default::default()
+            });
+            Ok(entries.collect())
+        }
+    }
+
+    /// A monitor with a temporary datastore and retry queue, and a feed of `entries`.
+    fn fixture(
+        name: &str,
+        entries: &[(&'static str, &'static str)],
+    ) -> (TempData, Monitor, StubFeed) {
+        let data = TempData::new(name);
+        let datastore = Datastore::new(&data.0).unwrap();
+        let retries = RetryQueue::new(datastore::retry_path(&data.0), 1).unwrap();
+        let feed = StubFeed {
+            entries: entries.to_vec(),
+            ..Default::default()
+        };
+        (data, Monitor::new(datastore, retries), feed)
+    }
+
+    /// A notifier that can be shared with a server, recording the messages posted.
+    struct SharedNotifier(Arc<Mutex<Vec<String>>>);
+
+    impl Notifier for SharedNotifier {
+        fn post_thread(
+            &self,
+            message: &str,
+            _root_id: Option<&str>,
+        ) -> Result<Option<String>, Box<ureq::Error>> {
+            self.0.lock().unwrap().push(message.to_string());
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn run_once_checks_feed_once() {
+        let (data, mut monitor, feed) = fixture("run-once", &[]);
+        let config = Config {
+            run_mode: RunMode::Once,
+            ..Config::test(data.0.clone())
+        };
+
+        run_once(
+            &mut monitor,
+            &feed,
+            &config,
+            &RecordingNotifier::default(),
+            &SystemClock,
+        )
+        .unwrap();
+        assert_eq!(feed.checks.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn run_once_fails_when_feed_fails() {
+        let (data, mut monitor, feed) = fixture("once-failing", &[]);
+        feed.failing.store(true, Ordering::SeqCst);
+        let config = Config {
+            run_mode: RunMode::Once,
+            ..Config::test(data.0.clone())
+        };
+        let notifier = RecordingNotifier::default();
+
+        let result = run_once(&mut monitor, &feed, &config, &notifier, &SystemClock);
+        assert!(result.is_err());
+        assert_eq!(
+            *notifier.messages.borrow(),
+            ["unable to poll bushfire feed: I/O error: connection refused"]
+        );
+    }
+
+    #[test]
+    fn backups_follow_the_clock() {
+        let data = TempData::new("backup-clock");
+        let path = &data.0;
+        let dir = env::temp_dir().join(format!("wizards-bot-backup-clock-dir-{}", process::id()));
+        fs::write(path, "").unwrap();
+        let backups = Backups {
+            dir: dir.clone(),
+            interval: Duration::from_secs(60 * 60),
+            keep: 2,
+        };
+        let clock = MockClock::new(OffsetDateTime::UNIX_EPOCH);
+        let mut last_backup = clock.now();
+
+        clock.advance(Duration::from_secs(59 * 60));
+        assert_eq!(
+            backup_if_due(&backups, path, &mut last_backup, &clock),
+            None
+        );
+        clock.advance(Duration::from_secs(60));
+        assert!(backup_if_due(&backups, path, &mut last_backup, &clock).is_some());
+        assert_eq!(
+            backup_if_due(&backups, path, &mut last_backup, &clock),
+            None
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn dry_run_logs_notifications() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let data = TempData::new("dry-run");
+        let mut config = Config::test(data.0.clone());
+        config.bushfire_webhook = format!("http://{}/hook", listener.local_addr().unwrap());
+        config.dry_run = true;
+
+        let notifier = notifier(&config, &client::agent("test"));
+        let lines = logging::capture(logging::Level::Info, || {
+            notifier.post("unable to poll bushfire feed").unwrap();
+        });
+        assert_eq!(
+            lines,
+            ["INFO: dry run, not posting: unable to poll bushfire feed"]
+        );
+        // Nothing connected to the webhook
+        let err = listener.accept().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    /// Send a raw HTTP request to the server and return the response.
+    fn http_request(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn trickling_clients_time_out() {
+        let limits = RequestLimits {
+            max_body_size: 4096,
+            read_timeout: Duration::from_millis(200),
+        };
+        let server = ServerBuilder::new("test")
+            .limits(limits)
+            .bind("127.0.0.1:0");
+        let server = Arc::new(server.unwrap());
+        let addr = server.local_addr().unwrap();
+        let workers = server.spawn_workers(1);
+
+        // Half the headers then nothing, this is left to tiny_http and doesn't reach a worker
+        let mut headers = TcpStream::connect(addr).unwrap();
+        headers.write_all(b"POST /nit HTTP/1.1\r\nHost: ").unwrap();
+
+        // A body sent a byte at a time, the worker gives up once the deadline passes. Bodies
+        // under 1024 bytes are read by tiny_http before the request reaches a worker.
+        let started = Instant::now();
+        let body = "POST /nit HTTP/1.1\r\nAuthorization: Token test\r\n\
+            Content-Type: application/x-www-form-urlencoded\r\nContent-Length: 2000\r\n\r\ntext=";
+        let mut partial = TcpStream::connect(addr).unwrap();
+        partial
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        partial.write_all(body.as_bytes()).unwrap();
+        let mut response = Vec::new();
+        while started.elapsed() < Duration::from_secs(5) {
+            let mut buf = [0; 1024];
+            match partial.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    response.extend_from_slice(&buf[..len]);
+                    break;
+                }
+                Err(_) => {
+                    // Writes fail once the server has responded and closed the connection
+                    let _ = partial.write_all(b"a");
+                }
+            }
+        }
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // tiny_http reads the rest of the body before the worker is free, so it's only free for
+        // other requests once the client gives up
+        drop(partial);
+        let response = http_request(addr, "GET /health HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        drop(headers);
+        server.shutdown();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn ready_after_first_poll() {
+        let status = Arc::new(Status::default());
+        let server = ServerBuilder::new("test")
+            .status(Arc::clone(&status))
+            .bind("127.0.0.1:0");
+        let server = Arc::new(server.unwrap());
+        let addr = server.local_addr().unwrap();
+        let workers = server.spawn_workers(1);
+        let ready = || http_request(addr, "GET /ready HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+        assert!(ready().starts_with("HTTP/1.1 503"));
+
+        let (_data, monitor, feed) = fixture("ready", &[]);
+        let mut monitor = monitor.with_status(status);
+        let now = OffsetDateTime::now_utc();
+        monitor.poll(&feed, (0., 0.), now, &RecordingNotifier::default());
+
+        let response = ready();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("OK"));
+
+        server.shutdown();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn local_addr_health() {
+        let server = Arc::new(ServerBuilder::new("test").bind("127.0.0.1:0").unwrap());
+        let addr = server.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+        let workers = server.spawn_workers(1);
+
+        let response = http_request(addr, "GET /health HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("OK"));
+
+        server.shutdown();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn base_path_routes() {
+        let get = |addr, path| {
+            let response = http_request(
+                addr,
+                &format!("GET {path} HTTP/1.1\r\nConnection: close\r\n\r\n"),
+            );
+            response[9..12].to_string()
+        };
+
+        for (base_path, prefix) in [("", ""), ("/bots/wizards/", "/bots/wizards")] {
+            let server = ServerBuilder::new("test")
+                .base_path(base_path)
+                .bind("127.0.0.1:0");
+            let server = Arc::new(server.unwrap());
+            let addr = server.local_addr().unwrap();
+            let workers = server.spawn_workers(1);
+
+            assert_eq!(get(addr, format!("{prefix}/")), "200");
+            assert_eq!(get(addr, format!("{prefix}/health")), "200");
+            assert_eq!(get(addr, format!("{prefix}/style.css")), "200");
+            assert_eq!(get(addr, format!("{prefix}/missing")), "404");
+            if !prefix.is_empty() {
+                assert_eq!(get(addr, prefix.to_string()), "200");
+                assert_eq!(get(addr, String::from("/health")), "404");
+                assert_eq!(get(addr, format!("{prefix}health")), "404");
+            }
+
+            server.shutdown();
+            for worker in workers {
+                worker.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn poll_on_request() {
+        let (_data, monitor, feed) = fixture("poll", &[("IF50", "Advice"), ("IF51", "Advice")]);
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let poller = Poller::new(
+            monitor,
+            Box::new(feed),
+            (0., 0.),
+            UtcOffset::UTC,
+            Box::new(SharedNotifier(Arc::clone(&messages))),
+        );
+        let server = ServerBuilder::new("test")
+            .poller(Arc::new(poller))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let request = |authorization: &str| {
+            TestRequest::new()
+                .with_method(Method::Post)
+                .with_path("/poll")
+                .with_header(authorization.parse().unwrap())
+                .into()
+        };
+
+        let err = server.poll_now(&request("Authorization: Token wrong"));
+        assert_eq!(err, Err(HandlerError::Unauthorized));
+        assert!(messages.lock().unwrap().is_empty());
+
+        let obj = server
+            .poll_now(&request("Authorization: Token test"))
+            .unwrap();
+        assert_eq!(obj["notified"], 2);
+        assert_eq!(messages.lock().unwrap().len(), 2);
+
+        // Entries already notified aren't counted again
+        let obj = server
+            .poll_now(&request("Authorization: Token test"))
+            .unwrap();
+        assert_eq!(obj["notified"], 0);
+    }
+
+    #[test]
+    fn dedup_window_expires_with_clock() {
+        let (_data, monitor, feed) = fixture("clock", &[("IF52", "Advice")]);
+        let window = Duration::from_secs(60 * 60);
+        let monitor = monitor.with_dedup_window(Some(window));
+        let clock = Arc::new(MockClock::new(
+            time::macros::datetime!(2023-09-08 12:00 UTC),
+        ));
+        let poller = Poller::new(
+            monitor,
+            Box::new(feed),
+            (0., 0.),
+            UtcOffset::UTC,
+            Box::new(SharedNotifier(Arc::default())),
+        )
+        .with_clock(clock.clone());
+
+        assert_eq!(poller.poll(), 1);
+        clock.advance(window - Duration::from_secs(1));
+        assert_eq!(poller.poll(), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(poller.poll(), 1);
+    }
+
+    #[test]
+    fn poll_after_panic() {
+        struct PanickingFeed(AtomicBool);
+
+        impl Feed for PanickingFeed {
+            fn check(&self, _: LatLong) -> Result<Vec<Entry>, BushfireError> {
+                if !self.0.swap(true, Ordering::SeqCst) {
+                    panic!("feed panicked");
+                }
+                Ok(Vec::new())
+            }
+        }
+
+        let (_data, monitor, _) = fixture("poll-panic", &[]);
+        let poller = Poller::new(
+            monitor,
+            Box::new(PanickingFeed(AtomicBool::new(false))),
+            (0., 0.),
+            UtcOffset::UTC,
+            Box::new(SharedNotifier(Arc::default())),
+        );
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| poller.poll()));
+        assert!(result.is_err());
+        // Later polls still run
+        assert_eq!(poller.poll(), 0);
+        assert_eq!(poller.poll_interval(300), 300);
+    }
+
+    #[test]
+    fn errors_routed_to_error_webhook() {
+        let (_data, monitor, feed) = fixture("error-webhook", &[("IF53", "Advice")]);
+        feed.failing.store(true, Ordering::SeqCst);
+        let failing = Arc::clone(&feed.failing);
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let monitor =
+            monitor.with_error_notifier(Some(Box::new(SharedNotifier(Arc::clone(&errors)))));
+        let poller = Poller::new(
+            monitor,
+            Box::new(feed),
+            (0., 0.),
+            UtcOffset::UTC,
+            Box::new(SharedNotifier(Arc::clone(&alerts))),
+        );
+
+        assert_eq!(poller.poll(), 0);
+        failing.store(false, Ordering::SeqCst);
+        assert_eq!(poller.poll(), 1);
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("unable to poll bushfire feed: "));
+        assert!(errors[1].starts_with("bushfire feed available again"));
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].contains("Advice"));
+    }
+
+    #[test]
+    fn history_lists_notifications() {
+        let (_data, monitor, feed) = fixture("history", &[("IF54", "Watch and Act")]);
+        let status = Arc::new(Status::default());
+        let poller = Poller::new(
+            monitor.with_status(Arc::clone(&status)),
+            Box::new(feed),
+            (0., 0.),
+            UtcOffset::UTC,
+            Box::new(SharedNotifier(Arc::default())),
+        )
+        .with_clock(Arc::new(MockClock::new(
+            time::macros::datetime!(2023-09-08 12:00 UTC),
+        )));
+        let server = ServerBuilder::new("test")
+            .status(status)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let request = |authorization: &str| {
+            TestRequest::new()
+                .with_path("/history")
+                .with_header(authorization.parse().unwrap())
+                .into()
+        };
+
+        let err = server.history(&request("Authorization: Token wrong"));
+        assert_eq!(err, Err(HandlerError::Unauthorized));
+        let obj = server
+            .history(&request("Authorization: Token test"))
+            .unwrap();
+        assert!(obj["notifications"].is_empty());
+
+        assert_eq!(poller.poll(), 1);
+        let obj = server
+            .history(&request("Authorization: Token test"))
+            .unwrap();
+        assert_eq!(
+            obj["notifications"],
+            json::array![{
+                id: "IF54",
+                title: "Incident IF54",
+                severity: "watch_and_act",
+                time: "2023-09-08T12:00:00Z",
+                delivered: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn custom_home_page() {
+        let server = Arc::new(
+            ServerBuilder::new("test")
+                .revision("abc123")
+                .home_page(HomePage {
+                    title: Some(String::from("Bushfire <Bot>")),
+                    contact: Some(String::from("ops@example.com")),
+                    area: None,
+                })
+                .bind("127.0.0.1:0")
+                .unwrap(),
+        );
+        let addr = server.local_addr().unwrap();
+        let workers = server.spawn_workers(1);
+
+        let response = http_request(addr, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.contains("<title>Bushfire &lt;Bot&gt;</title>"));
+        assert!(response.contains("<h1>🤖 Bushfire &lt;Bot&gt;</h1>"));
+        assert!(response.contains("<p>Contact: ops@example.com</p>"));
+        assert!(response.contains("(abc123)"));
+        assert!(!response.contains("Watching for bushfires"));
+        assert!(!response.contains('$'));
+
+        server.shutdown();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        // The compiled in defaults are used when nothing is configured
+        let html = HomePage::default().render("dev", "");
+        assert!(html.contains("<title>Wizards Bot</title>"));
+        assert!(html.contains(r#"href="/style.css""#));
+
+        // The stylesheet is found whether or not the base path has a trailing slash
+        let html = HomePage::default().render("dev", "/bot");
+        assert!(html.contains(r#"href="/bot/style.css""#));
+    }
+
+    #[test]
+    fn api_index() {
+        let server = ServerBuilder::new("test").bind("127.0.0.1:0").unwrap();
+        let index = server.api_index();
+        let route = |path: &str| {
+            index["routes"]
+                .members()
+                .find(|route| route["path"] == path)
+                .map(|route| route["method"].to_string())
+        };
+        assert_eq!(route("/nit").as_deref(), Some("POST"));
+        assert_eq!(route("/health").as_deref(), Some("GET"));
+        assert_eq!(route("/test/feed"), None);
+    }
+
+    #[test]
+    fn dispatch_routes() {
+        let status = Arc::new(Status::default());
+        let server = ServerBuilder::new("test")
+            .status(Arc::clone(&status))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let dispatch = |method: Method, path: &str| {
+            let mut request = TestRequest::new()
+                .with_method(method)
+                .with_path(path)
+                .into();
+            server.handle_one(&mut request).status_code().0
+        };
+
+        assert_eq!(dispatch(Method::Get, "/"), 200);
+        assert_eq!(dispatch(Method::Get, "/api"), 200);
+        assert_eq!(dispatch(Method::Get, "/health"), 200);
+        assert_eq!(dispatch(Method::Head, "/health"), 200);
+        assert_eq!(dispatch(Method::Get, "/ready"), 503);
+        status.set_ready();
+        assert_eq!(dispatch(Method::Get, "/ready"), 200);
+        assert_eq!(dispatch(Method::Get, "/metrics"), 200);
+        assert_eq!(dispatch(Method::Get, "/style.css"), 200);
+        assert_eq!(dispatch(Method::Post, "/"), 200);
+        assert_eq!(dispatch(Method::Put, "/style.css"), 200);
+        assert_eq!(dispatch(Method::Post, "/metrics"), 404);
+        // Missing headers
+        assert_eq!(dispatch(Method::Post, "/nit"), 400);
+        assert_eq!(dispatch(Method::Get, "/nit"), 404);
+        assert_eq!(dispatch(Method::Post, "/test/feed"), 404);
+        assert_eq!(dispatch(Method::Get, "/missing"), 404);
+        assert_eq!(
+            server
+                .handle_one(&mut nit_request("text=hello"))
+                .status_code()
+                .0,
+            200
+        );
+    }
+
+    #[test]
+    fn handle_one_responses() {
+        let server = ServerBuilder::new("test")
+            .revision("abc123")
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let handle = |mut request: Request| {
+            let response = server.handle_one(&mut request);
+            let status = response.status_code().0;
+            let mut body = String::new();
+            response.into_reader().read_to_string(&mut body).unwrap();
+            (status, body)
+        };
+        let get = |path: &str| handle(TestRequest::new().with_path(path).into());
+
+        let (status, body) = get("/");
+        assert_eq!(status, 200);
+        assert!(body.contains("abc123"));
+        assert_eq!(get("/health"), (200, String::from("OK")));
+        assert_eq!(
+            handle(nit_request(
+                "text=https%3A%2F%2Fmedium.com%2F%40wezm%2Fpost"
+            )),
+            (
+                200,
+                String::from(
+                    "https://scribe.rip/@wezm/post ([source](https://medium.com/@wezm/post))"
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn dual_stack_health() {
+        let server = match ServerBuilder::new("test").dual_stack(0) {
+            Ok(server) => Arc::new(server),
+            // IPv6 isn't available
+            Err(_) => return,
+        };
+        let port = server.local_addr().unwrap().port();
+        let workers = server.spawn_workers(1);
+
+        for ip in ["127.0.0.1", "::1"] {
+            let addr = SocketAddr::new(ip.parse().unwrap(), port);
+            let response = http_request(addr, "GET /health HTTP/1.1\r\nConnection: close\r\n\r\n");
+            assert!(response.starts_with("HTTP/1.1 200"), "{ip}: {response}");
+        }
+
+        server.shutdown();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn https_health() {
+        let testdata = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let tls = TlsConfig {
+            certificate: testdata.join("cert.pem"),
+            private_key: testdata.join("key.pem"),
+        };
+        let server = ServerBuilder::new("test")
+            .tls(Some(tls))
+            .bind("127.0.0.1:0");
+        let server = Arc::new(server.unwrap());
+        let port = server.local_addr().unwrap().port();
+        let workers = server.spawn_workers(1);
+
+        // Trust only the self-signed test certificate
+        let mut roots = rustls::RootCertStore::empty();
+        let cert = fs::read(testdata.join("cert.der")).unwrap();
+        roots.add(cert.into()).unwrap();
+        let tls_config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let agent = ureq::AgentBuilder::new()
+            .tls_config(Arc::new(tls_config))
+            .build();
+        let response = agent
+            .get(&format!("https://localhost:{port}/health"))
+            .call()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.into_string().unwrap(), "OK");
+
+        server.shutdown();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn slow_request_does_not_block_health() {
+        let limits = RequestLimits {
+            max_body_size: 4096,
+            read_timeout: Duration::from_secs(30),
+        };
+        let server = ServerBuilder::new("test")
+            .limits(limits)
+            .bind("127.0.0.1:0");
+        let server = Arc::new(server.unwrap());
+        let addr = server.local_addr().unwrap();
+        let workers = server.spawn_workers(2);
+
+        // Send the headers of a /nit request but stall on the body, which is large enough that
+        // tiny_http hands the request to a worker before reading it
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled
+            .write_all(
+                b"POST /nit HTTP/1.1\r\n\
+                Content-Type: application/x-www-form-urlencoded\r\n\
+                Authorization: Token test\r\n\
+                Content-Length: 2000\r\n\r\ntext=",
+            )
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        let start = Instant::now();
+        let response = http_request(addr, "GET /health HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("OK"));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        drop(stalled);
+        server.shutdown();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[cfg(feature = "test-endpoints")]
+    #[test]
+    fn test_feed_notifies() {
+        let messages = Arc::default();
+        let server = ServerBuilder::new("test")
+            .test_feed(TestFeed {
+                point: (-27.584701903466, 151.06082028616),
+                shape: bushfire::AlertShape::Circle,
+                options: notify::MessageOptions::default(),
+                notifier: Box::new(SharedNotifier(Arc::clone(&messages))),
+            })
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let xml = r#"<feed xmlns:georss="http://www.georss.org/georss" xmlns="http://www.w3.org/2005/Atom">
+    <entry>
+        <id>IF39-1</id>
+        <category term="Watch and Act"/>
+        <title>Fire near Kumbarilla</title>
+        <georss:point>-27.584701903466 151.06082028616</georss:point>
+    </entry>
+    <entry>
+        <id>IF39-2</id>
+        <title>Fire near Noosa</title>
+        <georss:point>-26.400054 153.0223421</georss:point>
+    </entry>
+</feed>"#;
+        let mut request = TestRequest::new()
+            .with_method(Method::Post)
+            .with_path("/test/feed")
+            .with_header("Content-Type: application/atom+xml".parse().unwrap())
+            .with_header("Authorization: Token test".parse().unwrap())
+            .with_body(xml)
+            .into();
+
+        let text = server.test_feed(&mut request).unwrap();
+        assert_eq!(text, "Notified 1 entries");
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("#### ⚠️ Watch and Act\n\n[**Fire near Kumbarilla**]"));
+    }
+
+    #[test]
+    fn admin_test_notify() {
+        let messages = Arc::default();
+        let server = ServerBuilder::new("test")
+            .test_notify(TestNotify {
+                options: notify::MessageOptions::default(),
+                notifier: Box::new(SharedNotifier(Arc::clone(&messages))),
+            })
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let request = |authorization: &str| {
+            TestRequest::new()
+                .with_method(Method::Post)
+                .with_path("/admin/test-notify")
+                .with_header(authorization.parse().unwrap())
+                .into()
+        };
+
+        let response = server.handle_one(&mut request("Authorization: Token wrong"));
+        assert_eq!(response.status_code().0, 401);
+        assert!(messages.lock().unwrap().is_empty());
+
+        let response = server.handle_one(&mut request("Authorization: Token test"));
+        assert_eq!(response.status_code().0, 200);
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("[**Test alert from wizards-bot**]"));
+    }
+
+    #[test]
+    fn bushfire_config_command() {
+        let server = ServerBuilder::new("test")
+            .monitoring(Monitoring {
+                point: (-27.46844, 153.02334),
+                shape: bushfire::AlertShape::Circle,
+                precision: 4,
+            })
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let obj = server
+            .bushfire_config_command(&nit_request("command=%2Fbushfire-config"))
+            .unwrap();
+        assert_eq!(obj["response_type"], "ephemeral");
+        assert_eq!(
+            obj["text"],
+            "Monitoring for bushfire incidents within 10 km of -27.4684, 153.0233"
+        );
+    }
+
+    #[test]
+    fn shutdown_with_hung_notification() {
+        struct HungNotifier;
+
+        impl Notifier for HungNotifier {
+            fn post_thread(
+                &self,
+                _message: &str,
+                _root_id: Option<&str>,
+            ) -> Result<Option<String>, Box<ureq::Error>> {
+                thread::sleep(Duration::from_secs(30));
+                Ok(None)
+            }
+        }
+
+        let server = Arc::new(
+            ServerBuilder::new("test")
+                .test_notify(TestNotify {
+                    options: notify::MessageOptions::default(),
+                    notifier: Box::new(HungNotifier),
+                })
+                .bind("127.0.0.1:0")
+                .unwrap(),
+        );
+        let threads = server.spawn_workers(1);
+        let addr = server.local_addr().unwrap();
+        // The worker blocks posting the notification, so there is no response to wait for
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"POST /admin/test-notify HTTP/1.1\r\nAuthorization: Token test\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        let start = Instant::now();
+        server.shutdown();
+        assert_eq!(join_threads(threads, Duration::from_millis(200)), 1);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn sighup_reopens_log() {
+        let requested = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&requested)).unwrap();
+        assert!(!reopen_log_if_requested(&requested));
+
+        signal_hook::low_level::raise(signal_hook::consts::SIGHUP).unwrap();
+        let lines = logging::capture(logging::Level::Info, || {
+            assert!(reopen_log_if_requested(&requested));
+        });
+        assert_eq!(lines, ["INFO: reopened log file"]);
+        // Only reopened once per signal
+        assert!(!reopen_log_if_requested(&requested));
+    }
+
+    /// A /nit request for a plain text response with `body`.
+    fn nit_request(body: &'static str) -> Request {
+        TestRequest::new()
+            .with_method(Method::Post)
+            .with_path("/nit")
+            .with_header("Accept: text/plain".parse().unwrap())
+            .with_header(
+                "Content-Type: application/x-www-form-urlencoded"
+                    .parse()
+                    .unwrap(),
+            )
+            .with_header("Authorization: Token test".parse().unwrap())
+            .with_body(body)
+            .into()
+    }
+
+    #[test]
+    fn builder_body_cap() {
+        let server = ServerBuilder::new("test")
+            .max_body_size(16)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        assert!(server
+            .nit_plain_text(&mut nit_request("text=short"))
+            .is_ok());
+        let result =
+            server.nit_plain_text(&mut nit_request("text=https%3A%2F%2Ftwitter.com%2Fwezm"));
+        assert_eq!(result, Err(HandlerError::PayloadTooLarge));
+    }
+
+    #[test]
+    fn nit_max_message_length() {
+        let server = ServerBuilder::new("test")
+            .max_message_length(40)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let obj = server
+            .nit_slash_command(&mut nit_request(
+                "text=see+https%3A%2F%2Ftwitter.com%2Fwezm+for+details",
+            ))
+            .unwrap();
+        assert_eq!(obj["text"], "see https://nitter.net/wezm… (truncated)");
+    }
+
+    #[test]
+    fn nit_expand_keyword() {
+        let server = ServerBuilder::new("test").bind("127.0.0.1:0").unwrap();
+        let text = server
+            .nit_plain_text(&mut nit_request(
+                "text=expand+https%3A%2F%2Ftwitter.com%2Fwezm",
+            ))
+            .unwrap();
+        assert_eq!(
+            text,
+            "https://twitter.com/wezm (clean: https://nitter.net/wezm)"
+        );
+    }
+
+    #[test]
+    fn nit_rate_limit() {
+        let server = ServerBuilder::new("test")
+            .rate_limit(RateLimit::parse("1/60"))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        assert!(server
+            .nit_plain_text(&mut nit_request("text=hello"))
+            .is_ok());
+        let result = server.nit_plain_text(&mut nit_request("text=hello"));
+        assert_eq!(result, Err(HandlerError::TooManyRequests));
+
+        assert_eq!(RateLimit::parse("10/0"), None);
+        assert_eq!(RateLimit::parse("ten"), None);
+    }
+
+    #[test]
+    fn nit_plain_text_response() {
+        let server = ServerBuilder::new("test").bind("127.0.0.1:0").unwrap();
+        let mut request = nit_request("text=https%3A%2F%2Ftwitter.com%2Fwezm");
+
+        assert!(Server::wants_plain_text(&request));
+        let text = server.nit_plain_text(&mut request).unwrap();
+        assert_eq!(
+            text,
+            "https://nitter.net/wezm ([source](https://twitter.com/wezm))"
+        );
+    }
+
+    /// A reader that stalls before returning each chunk of data.
+    struct StalledReader {
+        delay: Duration,
+    }
+
+    impl Read for StalledReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(self.delay);
+            buf[0] = b'a';
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn read_body_stalled_reader_times_out() {
+        let limits = RequestLimits {
+            max_body_size: 1024,
+            read_timeout: Duration::from_millis(50),
+        };
+        let reader = StalledReader {
+            delay: Duration::from_millis(20),
+        };
+        let err = read_body(reader, limits).unwrap_err();
+        assert_eq!(err, HandlerError::RequestTimeout);
+    }
+
+    #[test]
+    fn read_body_too_large() {
+        let limits = RequestLimits {
+            max_body_size: 8,
+            read_timeout: Duration::from_secs(1),
+        };
+        let err = read_body(&b"text=0123456789"[..], limits).unwrap_err();
+        assert_eq!(err, HandlerError::PayloadTooLarge);
+
+        let body = read_body(&b"text=abc"[..], limits).unwrap();
+        assert_eq!(body, b"text=abc");
+    }
+
+    #[test]
+    fn handler_error_status() {
+        for (err, status) in [
+            (HandlerError::BadRequest(String::from("Bad request")), 400),
+            (HandlerError::Unauthorized, 401),
+            (HandlerError::NotFound, 404),
+            (HandlerError::RequestTimeout, 408),
+            (HandlerError::PayloadTooLarge, 413),
+            (HandlerError::TooManyRequests, 429),
+            (HandlerError::Internal, 500),
+            (HandlerError::BadGateway(String::from("503")), 502),
+        ] {
+            assert_eq!(err.status(), StatusCode::from(status), "{err}");
+        }
+
+        let response = json_response(Err(HandlerError::Unauthorized));
+        assert_eq!(response.status_code(), StatusCode::from(401));
+        let mut body = String::new();
+        response.into_reader().read_to_string(&mut body).unwrap();
+        assert_eq!(json::parse(&body).unwrap()["error"], "Not authorised");
+    }
+
+    fn substitute_urls(text: &str) -> Cow<'_, str> {
+        Substitutions::default().apply(text)
+    }
+
     #[test]
     fn twitter_to_nitter_desktop() {
         let val = substitute_urls("https://twitter.com/wezm");
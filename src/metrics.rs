@@ -0,0 +1,72 @@
+//! Counters exposed at /metrics in the Prometheus text format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// URLs rewritten by /nit, by the name of the rule that matched.
+pub static SUBSTITUTIONS: CounterVec = CounterVec::new(
+    "substitutions_total",
+    "URLs rewritten by substitution rules",
+    "host",
+);
+
+/// A counter with a single label.
+pub struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl CounterVec {
+    const fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        CounterVec {
+            name,
+            help,
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Increment the counter for `value`.
+    pub fn inc(&self, value: &str) {
+        // NOTE(unwrap): the lock is never held across a panic
+        let mut values = self.values.lock().unwrap();
+        match values.get_mut(value) {
+            Some(count) => *count += 1,
+            None => {
+                values.insert(value.to_owned(), 1);
+            }
+        }
+    }
+
+    /// The count for `value`.
+    #[cfg(test)]
+    pub fn get(&self, value: &str) -> u64 {
+        // NOTE(unwrap): the lock is never held across a panic
+        self.values.lock().unwrap().get(value).copied().unwrap_or(0)
+    }
+
+    fn render(&self, output: &mut String) {
+        // NOTE(unwrap): writing to a String does not fail
+        writeln!(output, "# HELP {} {}", self.name, self.help).unwrap();
+        writeln!(output, "# TYPE {} counter", self.name).unwrap();
+        // NOTE(unwrap): the lock is never held across a panic
+        for (value, count) in self.values.lock().unwrap().iter() {
+            writeln!(
+                output,
+                "{}{{{}=\"{}\"}} {}",
+                self.name, self.label, value, count
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Render all metrics in the Prometheus text format.
+pub fn render() -> String {
+    let mut output = String::new();
+    SUBSTITUTIONS.render(&mut output);
+    output
+}
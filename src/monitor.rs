@@ -0,0 +1,1478 @@
+//! Decide which bushfire entries to notify about and keep track of those already sent.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use time::{format_description, OffsetDateTime, Time};
+
+use crate::bushfire::{self, Entry, EntryId, Feed, LatLong};
+use crate::datastore::{Datastore, Key, Record, Retry, RetryQueue};
+use crate::notify::{self, MessageOptions, Notifier};
+use crate::status::{Notification, Status};
+
+/// Polls back off to at most `2^MAX_BACKOFF_SHIFT` times the normal interval.
+const MAX_BACKOFF_SHIFT: u32 = 2;
+/// While the feed keeps failing with the same error a reminder is posted this often, unless
+/// configured otherwise.
+pub const DEFAULT_ERROR_REMINDER: Duration = Duration::from_secs(30 * 60);
+/// Updates to a notified entry are posted at most this often, unless configured otherwise.
+pub const DEFAULT_RENOTIFY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+pub struct Monitor {
+    datastore: Datastore,
+    /// Notifications that failed to post
+    retries: RetryQueue,
+    quiet_hours: Option<QuietHours>,
+    message_options: MessageOptions,
+    /// How to treat the first poll, cleared once it has been processed
+    seed: SeedMode,
+    /// Entries held back until quiet hours end
+    deferred: Vec<Entry>,
+    /// The text of deferred notifications, kept so they survive a restart
+    deferred_queue: Option<RetryQueue>,
+    /// Post a follow-up when a notified entry is updated
+    notify_updates: bool,
+    /// Minimum time between notifications about updates to the same entry
+    renotify_interval: Duration,
+    status: Arc<Status>,
+    /// Number of consecutive failed polls
+    failures: u32,
+    /// The last poll error posted, cleared once a poll succeeds
+    posted_error: Option<PostedError>,
+    /// How often a poll error that keeps recurring is posted again
+    error_reminder: Duration,
+    /// Where errors are posted instead of with the notifications, if set
+    error_notifier: Option<Box<dyn Notifier + Send + Sync>>,
+    /// Set when writing to the datastore has failed, until a write succeeds
+    datastore_unwritable: bool,
+    /// Notify about an entry again once this long has passed since it was last notified
+    dedup_window: Option<Duration>,
+    /// Skip entries with the same content as a notified entry
+    dedupe_content: bool,
+    /// Wait for new entries to still be in the feed on the next poll before notifying
+    grace_poll: bool,
+    /// New entries seen in the last poll, held until the next
+    held: HashSet<EntryId>,
+    /// Post a cleared message once a notified entry has been absent for this many polls
+    clear_after: Option<u32>,
+    /// Entries notified since startup that are still in the feed, by id
+    active: HashMap<String, Active>,
+    /// Post a note after the first poll when the datastore was newly created
+    announce_new_datastore: bool,
+    /// Set until the first poll has been processed
+    first_poll: bool,
+    /// Number of entries recorded in the datastore since startup
+    recorded: usize,
+}
+
+/// A notified entry being tracked until it leaves the feed.
+struct Active {
+    /// How the entry is referred to in the cleared message
+    title: String,
+    /// Number of consecutive polls the entry has been absent from
+    missed: u32,
+}
+
+/// A poll error that was posted to the channel.
+struct PostedError {
+    message: String,
+    /// When the error, or the last reminder about it, was posted
+    posted: OffsetDateTime,
+}
+
+/// Randomly varies poll intervals so deployments don't all poll the feed at the same moment.
+pub struct Jitter {
+    /// Maximum seconds added to or removed from the interval
+    max: u32,
+    /// SplitMix64 state
+    state: u64,
+}
+
+/// How entries in the first poll after startup are handled when the datastore is new.
+///
+/// Seeding lets a fresh deployment record the entries already in the feed without flooding the
+/// channel with notifications about them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeedMode {
+    /// Notify as normal
+    #[default]
+    Off,
+    /// Record entries without notifying
+    Silent,
+    /// Only notify about emergencies, record the rest
+    Emergencies,
+}
+
+/// A daily window of local time, which may span midnight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuietHours {
+    start: Time,
+    end: Time,
+}
+
+#[derive(Debug)]
+pub struct QuietHoursParseError;
+
+impl Monitor {
+    pub fn new(datastore: Datastore, retries: RetryQueue) -> Self {
+        Monitor {
+            datastore,
+            retries,
+            quiet_hours: None,
+            message_options: MessageOptions::default(),
+            seed: SeedMode::Off,
+            deferred: Vec::new(),
+            deferred_queue: None,
+            notify_updates: false,
+            renotify_interval: DEFAULT_RENOTIFY_INTERVAL,
+            status: Arc::default(),
+            failures: 0,
+            posted_error: None,
+            error_reminder: DEFAULT_ERROR_REMINDER,
+            error_notifier: None,
+            datastore_unwritable: false,
+            dedup_window: None,
+            dedupe_content: false,
+            grace_poll: false,
+            held: HashSet::new(),
+            clear_after: None,
+            active: HashMap::new(),
+            announce_new_datastore: false,
+            first_poll: true,
+            recorded: 0,
+        }
+    }
+
+    /// Mark `status` ready after the first successful poll.
+    pub fn with_status(mut self, status: Arc<Status>) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Post follow-ups in the entry's thread when its `updated` time changes.
+    ///
+    /// Threading needs the notifier to return the id of the first post, otherwise follow-ups are
+    /// posted to the channel.
+    pub fn with_notify_updates(mut self, notify_updates: bool) -> Self {
+        self.notify_updates = notify_updates;
+        self
+    }
+
+    /// Only post a follow-up for an updated entry once `interval` has passed since it was last
+    /// notified, however often its `updated` time changes.
+    pub fn with_renotify_interval(mut self, interval: Duration) -> Self {
+        self.renotify_interval = interval;
+        self
+    }
+
+    /// Allow entries to be notified again once `window` has passed since they were notified.
+    ///
+    /// Entries recorded before notified times were stored are never notified again.
+    pub fn with_dedup_window(mut self, window: Option<Duration>) -> Self {
+        self.dedup_window = window;
+        self
+    }
+
+    /// Skip entries with the same title and content as a notified entry, even if the id differs.
+    pub fn with_content_dedup(mut self, dedupe_content: bool) -> Self {
+        self.dedupe_content = dedupe_content;
+        self
+    }
+
+    /// Hold new entries for one poll so ones that are quickly removed from the feed aren't
+    /// notified. Emergencies are notified straight away. Held entries are only kept in memory.
+    pub fn with_grace_poll(mut self, grace_poll: bool) -> Self {
+        self.grace_poll = grace_poll;
+        self
+    }
+
+    /// Post a message in the entry's thread when a notified entry has been absent from the feed
+    /// for `polls` consecutive polls. Notified entries are only tracked in memory, so no cleared
+    /// message is posted for entries notified before a restart.
+    pub fn with_clear_after(mut self, polls: Option<u32>) -> Self {
+        self.clear_after = polls.filter(|&polls| polls > 0);
+        self
+    }
+
+    /// Post a note with the number of entries recorded by the first poll when the datastore was
+    /// newly created.
+    pub fn with_announce_new_datastore(mut self, announce: bool) -> Self {
+        self.announce_new_datastore = announce;
+        self
+    }
+
+    /// Post a reminder every `interval` while polls keep failing with the same error.
+    ///
+    /// The last error posted is only kept in memory, so after a restart a recurring error is
+    /// posted again straight away.
+    pub fn with_error_reminder(mut self, interval: Duration) -> Self {
+        self.error_reminder = interval;
+        self
+    }
+
+    /// Post poll and datastore errors to `notifier` rather than where entries are notified.
+    pub fn with_error_notifier(
+        mut self,
+        notifier: Option<Box<dyn Notifier + Send + Sync>>,
+    ) -> Self {
+        self.error_notifier = notifier;
+        self
+    }
+
+    /// Treat the first poll according to `seed` if the datastore was newly created. Restarts with
+    /// an existing datastore notify as normal so incidents aren't missed.
+    pub fn with_seed(mut self, seed: SeedMode) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Defer non-emergency notifications during `quiet_hours`.
+    pub fn with_quiet_hours(mut self, quiet_hours: Option<QuietHours>) -> Self {
+        self.quiet_hours = quiet_hours;
+        self
+    }
+
+    /// Persist notifications deferred during quiet hours in `queue`.
+    ///
+    /// Notifications restored from the queue after a restart are posted as plain text.
+    pub fn with_deferred_queue(mut self, queue: RetryQueue) -> Self {
+        self.deferred_queue = Some(queue);
+        self
+    }
+
+    /// Format notification messages using `options`.
+    pub fn with_message_options(mut self, options: MessageOptions) -> Self {
+        self.message_options = options;
+        self
+    }
+
+    /// Check `feed` for entries near `point` and notify about any new ones, returning the number
+    /// notified.
+    pub fn poll(
+        &mut self,
+        feed: &dyn Feed,
+        point: LatLong,
+        now: OffsetDateTime,
+        notifier: &dyn Notifier,
+    ) -> usize {
+        let errors = self
+            .error_notifier
+            .as_deref()
+            .map_or(notifier, |errors| errors as _);
+        match feed.check(point) {
+            Ok(mut entries) => {
+                // Notify about the closest incidents first
+                bushfire::sort_by_distance(&mut entries, point);
+                if self.failures > 0 {
+                    info!(
+                        "bushfire feed available after {} failed polls",
+                        self.failures
+                    );
+                    if self.posted_error.take().is_some() {
+                        let _ = errors.post(&format!(
+                            "bushfire feed available again after {} failed polls",
+                            self.failures
+                        ));
+                    }
+                    self.failures = 0;
+                }
+                info!("polled bushfire feed");
+                let notified = self.process(entries, now, notifier);
+                self.status.set_ready();
+                notified
+            }
+            Err(err) => {
+                self.failures += 1;
+                error!("unable to poll bushfire feed: {err}");
+                // Only post an error when it changes and then periodic reminders to avoid flooding
+                // the channel during an outage
+                let message = err.to_string();
+                match &mut self.posted_error {
+                    Some(posted) if posted.message == message => {
+                        if now - posted.posted >= self.error_reminder {
+                            posted.posted = now;
+                            let _ = errors.post(&format!(
+                                "bushfire feed still unavailable after {} attempts: {err}",
+                                self.failures
+                            ));
+                        }
+                    }
+                    _ => {
+                        let _ = errors.post(&format!("unable to poll bushfire feed: {err}"));
+                        self.posted_error = Some(PostedError {
+                            message,
+                            posted: now,
+                        });
+                    }
+                }
+                self.retry_failed(now, notifier);
+                0
+            }
+        }
+    }
+
+    /// Determine if the last poll of the feed failed.
+    pub fn is_failing(&self) -> bool {
+        self.failures > 0
+    }
+
+    /// The number of seconds to wait before the next poll, given the normal `interval`.
+    ///
+    /// The interval doubles with each consecutive failure, up to a limit.
+    pub fn poll_interval(&self, interval: u32) -> u32 {
+        let shift = self.failures.min(MAX_BACKOFF_SHIFT);
+        interval.saturating_mul(1 << shift)
+    }
+
+    /// Notify about any entries that haven't been seen before, or that have been updated if
+    /// follow-ups are enabled.
+    ///
+    /// `now` is the current local time. During quiet hours only emergencies are notified, other
+    /// entries are deferred until the quiet period is over. Returns the number of entries notified.
+    pub fn process(
+        &mut self,
+        entries: Vec<Entry>,
+        now: OffsetDateTime,
+        notifier: &dyn Notifier,
+    ) -> usize {
+        self.retry_failed(now, notifier);
+
+        let quiet = self
+            .quiet_hours
+            .is_some_and(|quiet_hours| quiet_hours.contains(now.time()));
+
+        let mut notified = 0;
+        if !quiet {
+            let deferred = std::mem::take(&mut self.deferred);
+            // Notifications deferred before a restart only have their text
+            let restored = self
+                .take_deferred_queue()
+                .into_iter()
+                .filter(|retry| !deferred.iter().any(|entry| entry.id == retry.id))
+                .collect::<Vec<_>>();
+            if !deferred.is_empty() || !restored.is_empty() {
+                info!(
+                    "quiet hours over, sending {} deferred notifications",
+                    deferred.len() + restored.len()
+                );
+            }
+            for retry in restored {
+                if self.post_deferred(retry, now, notifier) {
+                    notified += 1;
+                }
+            }
+            for entry in deferred {
+                if self.notify(entry, now, notifier) {
+                    notified += 1;
+                }
+            }
+        }
+
+        self.clear_absent(&entries, notifier);
+
+        let first_poll = std::mem::replace(&mut self.first_poll, false);
+        let recorded = self.recorded;
+        let seed = std::mem::take(&mut self.seed);
+        let seed = if self.datastore.is_new() {
+            seed
+        } else {
+            SeedMode::Off
+        };
+        let mut seeded = 0;
+        let mut held = HashSet::new();
+        for entry in entries {
+            if self.retries.contains(&entry.id) {
+                continue;
+            }
+            let keys = [Key::Id(&entry.id), Key::ContentHash(entry.content_hash())];
+            let keys = if self.dedupe_content {
+                &keys[..]
+            } else {
+                &keys[..1]
+            };
+            if self.datastore.contains_any(keys) {
+                // An entry only matched by content is a notified entry re-issued under a new id
+                let Some(record) = self.datastore.get(&entry.id) else {
+                    info!(incident = entry.id.0; "skipping incident {} with notified content", entry.id.0);
+                    continue;
+                };
+                let updated = match (record.updated, entry.updated) {
+                    (Some(notified), Some(updated)) => updated > notified,
+                    _ => false,
+                };
+                // Updates are picked up on a later poll once the interval has passed
+                let throttled = record
+                    .notified
+                    .is_some_and(|notified| now - notified < self.renotify_interval);
+                let expired = match (self.dedup_window, record.notified) {
+                    (Some(window), Some(notified)) => now - notified >= window,
+                    _ => false,
+                };
+                if !expired && (!self.notify_updates || !updated || throttled) {
+                    continue;
+                }
+            }
+            let known = self.datastore.get(&entry.id).is_some();
+
+            let notify_seed = seed == SeedMode::Emergencies && entry.is_emergency();
+            if seed != SeedMode::Off && !notify_seed {
+                info!(incident = entry.id.0; "seeding incident {} without notifying", entry.id.0);
+                let record = entry_record(&entry, self.root_id(&entry.id), now);
+                if self.record(entry.id, record, notifier) {
+                    seeded += 1;
+                }
+                continue;
+            }
+
+            if self.grace_poll && !known && !entry.is_emergency() && !self.held.remove(&entry.id) {
+                info!(incident = entry.id.0; "holding incident {} until the next poll", entry.id.0);
+                held.insert(entry.id);
+                continue;
+            }
+
+            if quiet && !entry.is_emergency() {
+                if !self.deferred.iter().any(|deferred| deferred.id == entry.id) {
+                    info!(
+                        incident = entry.id.0;
+                        "deferring incident {} until quiet hours end",
+                        entry.id.0
+                    );
+                    self.persist_deferred(&entry, now);
+                    self.deferred.push(entry);
+                }
+                continue;
+            }
+
+            if self.notify(entry, now, notifier) {
+                notified += 1;
+            }
+        }
+        // Entries that have left the feed are forgotten
+        self.held = held;
+
+        if first_poll && self.datastore.is_new() {
+            let notified = self.recorded - recorded - seeded;
+            let message = match seed {
+                SeedMode::Off => {
+                    format!("Bushfire datastore was empty; notified {notified} entries on first poll")
+                }
+                SeedMode::Silent => {
+                    format!("Bushfire datastore was empty; seeded {seeded} entries on first poll")
+                }
+                SeedMode::Emergencies => format!(
+                    "Bushfire datastore was empty; seeded {seeded} entries and notified {notified} emergencies on first poll"
+                ),
+            };
+            info!("{message}");
+            if self.announce_new_datastore {
+                if let Err(err) = notifier.post(&message) {
+                    error!("Unable to post note about new datastore: {err}");
+                }
+            }
+        }
+        notified
+    }
+
+    /// Post a cleared message for active entries that have been absent from the feed for long
+    /// enough, and stop tracking them.
+    fn clear_absent(&mut self, entries: &[Entry], notifier: &dyn Notifier) {
+        let Some(clear_after) = self.clear_after else {
+            return;
+        };
+        let present: HashSet<&str> = entries.iter().map(|entry| entry.id.0.as_str()).collect();
+        let mut cleared = Vec::new();
+        for (id, active) in self.active.iter_mut() {
+            if present.contains(id.as_str()) {
+                active.missed = 0;
+                continue;
+            }
+            active.missed += 1;
+            if active.missed >= clear_after {
+                cleared.push(id.clone());
+            }
+        }
+
+        for id in cleared {
+            // NOTE(unwrap): only ids in the map were collected
+            let active = self.active.remove(&id).unwrap();
+            info!(incident = id; "incident {} has cleared", id);
+            let id = EntryId(id);
+            let root_id = self.root_id(&id);
+            let message = format!("{} appears to have cleared", active.title);
+            if let Err(err) = notifier.post_thread(&message, root_id.as_deref()) {
+                error!(
+                    "Unable to post cleared notification for incident {}: {err}",
+                    id.0
+                );
+            }
+        }
+    }
+
+    /// Post notifications that previously failed, stopping at the first that fails again.
+    ///
+    /// Queued notifications are retried regardless of whether the entry is still in the feed.
+    pub fn retry_failed(&mut self, now: OffsetDateTime, notifier: &dyn Notifier) {
+        while let Some(retry) = self.retries.front() {
+            let post_id = match notifier.post_thread(&retry.message, retry.root_id.as_deref()) {
+                Ok(post_id) => post_id,
+                Err(err) => {
+                    error!(
+                        "Unable to post queued notification for incident {}: {err}",
+                        retry.id.0
+                    );
+                    break;
+                }
+            };
+
+            info!("posted queued notification for incident {}", retry.id.0);
+            match self.retries.pop_front() {
+                Ok(Some(retry)) => self.record_retry(retry, post_id, now, notifier),
+                Ok(None) => {}
+                Err(err) => {
+                    error!("Unable to update bushfire retry queue: {err}");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Record that a queued notification was posted as `post_id`.
+    fn record_retry(
+        &mut self,
+        retry: Retry,
+        post_id: Option<String>,
+        now: OffsetDateTime,
+        notifier: &dyn Notifier,
+    ) {
+        // The content hash, category and title aren't kept in the retry queue
+        let record = Record {
+            updated: retry.updated,
+            root_id: retry.root_id.or(post_id),
+            notified: Some(now),
+            ..Default::default()
+        };
+        self.record(retry.id, record, notifier);
+    }
+
+    /// Save the text of a notification about `entry` to the deferred queue, if there is one.
+    fn persist_deferred(&mut self, entry: &Entry, now: OffsetDateTime) {
+        let root_id = self.root_id(&entry.id);
+        let Some(queue) = self.deferred_queue.as_mut() else {
+            return;
+        };
+        if queue.contains(&entry.id) {
+            return;
+        }
+        let retry = Retry {
+            id: EntryId(entry.id.0.clone()),
+            message: notify::entry_text(entry, &self.message_options, now),
+            updated: entry.updated,
+            root_id,
+        };
+        match queue.push(retry) {
+            Ok(Some(dropped)) => error!(
+                "bushfire deferred queue full, dropped notification for incident {}",
+                dropped.id.0
+            ),
+            Ok(None) => {}
+            Err(err) => error!("Unable to update bushfire deferred queue: {err}"),
+        }
+    }
+
+    fn take_deferred_queue(&mut self) -> Vec<Retry> {
+        let Some(queue) = self.deferred_queue.as_mut() else {
+            return Vec::new();
+        };
+        queue.drain().unwrap_or_else(|err| {
+            error!("Unable to update bushfire deferred queue: {err}");
+            Vec::new()
+        })
+    }
+
+    /// Post a deferred notification restored from the deferred queue, queueing it for retry if
+    /// that fails.
+    fn post_deferred(
+        &mut self,
+        retry: Retry,
+        now: OffsetDateTime,
+        notifier: &dyn Notifier,
+    ) -> bool {
+        info!(incident = retry.id.0; "notify of deferred incident {}", retry.id.0);
+        match notifier.post_thread(&retry.message, retry.root_id.as_deref()) {
+            Ok(post_id) => {
+                self.record_retry(retry, post_id, now, notifier);
+                true
+            }
+            Err(err) => {
+                error!("Unable to post notification, queueing for retry: {err}");
+                match self.retries.push(retry) {
+                    Ok(Some(dropped)) => error!(
+                        "bushfire retry queue full, dropped notification for incident {}",
+                        dropped.id.0
+                    ),
+                    Ok(None) => {}
+                    Err(err) => error!("Unable to update bushfire retry queue: {err}"),
+                }
+                false
+            }
+        }
+    }
+
+    /// Post a notification for `entry`, returning true if it was posted.
+    ///
+    /// Follow-ups for updated entries only describe what changed, when that is known.
+    fn notify(&mut self, entry: Entry, now: OffsetDateTime, notifier: &dyn Notifier) -> bool {
+        // notify about this entry, following up in the existing thread if there is one
+        let root_id = self.root_id(&entry.id);
+        info!(incident = entry.id.0; "notify of incident {}", entry.id.0);
+        let changes = self
+            .datastore
+            .get(&entry.id)
+            .filter(|record| match (record.updated, entry.updated) {
+                (Some(notified), Some(updated)) => updated > notified,
+                _ => false,
+            })
+            .and_then(|record| {
+                notify::changes(
+                    &entry,
+                    record.category.as_deref(),
+                    record.title.as_deref(),
+                    &self.message_options,
+                )
+            });
+        let result = match changes {
+            Some(changes) => notify::notify_changes(
+                &changes,
+                &self.message_options,
+                now,
+                root_id.as_deref(),
+                notifier,
+            ),
+            None => notify::notify_entry(
+                &entry,
+                &self.message_options,
+                now,
+                root_id.as_deref(),
+                notifier,
+            ),
+        };
+        self.status.record_notification(Notification {
+            id: entry.id.0.clone(),
+            title: entry.title.clone(),
+            severity: entry.severity(),
+            time: now,
+            delivered: result.is_ok(),
+        });
+        match result {
+            Ok(post_id) => {
+                if self.clear_after.is_some() {
+                    let title = match &entry.title {
+                        Some(title) => title.clone(),
+                        None => format!("Incident {}", entry.id.0),
+                    };
+                    self.active
+                        .insert(entry.id.0.clone(), Active { title, missed: 0 });
+                }
+                let record = entry_record(&entry, root_id.or(post_id), now);
+                self.record(entry.id, record, notifier);
+                true
+            }
+            Err(err) => {
+                error!(
+                    "Unable to post notification, queueing for retry: {}: {}",
+                    err.error, err.notification
+                );
+                let retry = Retry {
+                    id: entry.id,
+                    message: err.notification,
+                    updated: entry.updated,
+                    root_id,
+                };
+                match self.retries.push(retry) {
+                    Ok(Some(dropped)) => error!(
+                        "bushfire retry queue full, dropped notification for incident {}",
+                        dropped.id.0
+                    ),
+                    Ok(None) => {}
+                    Err(err) => error!("Unable to update bushfire retry queue: {err}"),
+                }
+                false
+            }
+        }
+    }
+
+    /// The thread root post for a previously notified entry.
+    fn root_id(&self, id: &EntryId) -> Option<String> {
+        self.datastore
+            .get(id)
+            .and_then(|record| record.root_id.clone())
+    }
+
+    /// Record that an entry was notified, returning whether it was written to the datastore.
+    ///
+    /// Only the first failure to write to the datastore is posted, entries are still remembered
+    /// in memory so they aren't notified again while the process is running.
+    fn record(&mut self, id: EntryId, record: Record, notifier: &dyn Notifier) -> bool {
+        match self.datastore.append(id, record) {
+            Ok(()) => {
+                self.recorded += 1;
+                if self.datastore_unwritable {
+                    info!("Bushfire datastore is writable again");
+                    self.datastore_unwritable = false;
+                }
+                return true;
+            }
+            Err(err) if self.datastore_unwritable => {
+                error!("Unable to append entry to bushfire datastore: {err}")
+            }
+            Err(err) => {
+                self.datastore_unwritable = true;
+                let errors = self
+                    .error_notifier
+                    .as_deref()
+                    .map_or(notifier, |errors| errors as _);
+                if let Err(notify_err) = errors.post(&format!(
+                    "Unable to append entry to bushfire datastore, seen entries will only be remembered until restart: {err}"
+                )) {
+                    error!("Unable to append entry to bushfire datastore: {err}, error posting notification about that error: {notify_err}")
+                }
+            }
+        }
+        false
+    }
+}
+
+/// What is recorded about `entry` when it is notified at `now`.
+fn entry_record(entry: &Entry, root_id: Option<String>, now: OffsetDateTime) -> Record {
+    Record {
+        updated: entry.updated,
+        root_id,
+        notified: Some(now),
+        content_hash: Some(entry.content_hash()),
+        category: entry.category().map(ToOwned::to_owned),
+        title: entry.title.clone(),
+    }
+}
+
+impl Jitter {
+    pub fn new(max: u32, seed: u64) -> Self {
+        Jitter { max, state: seed }
+    }
+
+    /// Seed from the current time and process id.
+    pub fn from_entropy(max: u32) -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Jitter::new(max, nanos ^ u64::from(std::process::id()).rotate_left(32))
+    }
+
+    /// Offset `interval` by a random amount of up to the maximum in either direction, never
+    /// going below one second.
+    pub fn apply(&mut self, interval: u32) -> u32 {
+        if self.max == 0 {
+            return interval;
+        }
+        let span = u64::from(self.max) * 2 + 1;
+        let offset = (self.next() % span) as i64 - i64::from(self.max);
+        (i64::from(interval) + offset).clamp(1, i64::from(u32::MAX)) as u32
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl QuietHours {
+    /// Determine if `time` falls within the quiet hours.
+    pub fn contains(&self, time: Time) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&time)
+        } else {
+            // Window spans midnight
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl FromStr for SeedMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" | "false" | "off" => Ok(SeedMode::Off),
+            "1" | "true" | "on" | "silent" => Ok(SeedMode::Silent),
+            "emergency" | "emergencies" => Ok(SeedMode::Emergencies),
+            _ => Err(format!(
+                "unknown seed mode '{s}', expected true, false, or emergency"
+            )),
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = QuietHoursParseError;
+
+    /// Parse quiet hours in the form `HH:MM-HH:MM`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // NOTE(unwrap): This is a known valid format description
+        let format = format_description::parse("[hour]:[minute]").unwrap();
+        let (start, end) = s.split_once('-').ok_or(QuietHoursParseError)?;
+        let start = Time::parse(start.trim(), &format).map_err(|_| QuietHoursParseError)?;
+        let end = Time::parse(end.trim(), &format).map_err(|_| QuietHoursParseError)?;
+        Ok(QuietHours { start, end })
+    }
+}
+
+impl fmt::Display for QuietHoursParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected HH:MM-HH:MM")
+    }
+}
+
+impl std::error::Error for QuietHoursParseError {}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::bushfire::BushfireError;
+    use crate::datastore::{deferred_path, retry_path};
+    use crate::notify::tests::RecordingNotifier;
+
+    fn entry(id: &str, category: &str) -> Entry {
+        Entry {
+            id: EntryId(id.to_string()),
+            categories: vec![category.to_string()],
+            title: Some(format!("Incident {id}")),
+            ..Default::default()
+        }
+    }
+
+    fn data_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wizards-bot-{name}-{}", std::process::id()))
+    }
+
+    fn monitor(name: &str, quiet_hours: Option<&str>) -> Monitor {
+        let path = data_path(name);
+        let retry_path = retry_path(&path);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&retry_path);
+        let datastore = Datastore::new(path).unwrap();
+        let retries = RetryQueue::new(retry_path, 10).unwrap();
+        Monitor::new(datastore, retries)
+            .with_quiet_hours(quiet_hours.map(|hours| hours.parse().unwrap()))
+    }
+
+    #[test]
+    fn quiet_hours_span_midnight() {
+        let quiet_hours: QuietHours = "22:00-07:00".parse().unwrap();
+        assert!(quiet_hours.contains(Time::from_hms(23, 0, 0).unwrap()));
+        assert!(quiet_hours.contains(Time::from_hms(3, 0, 0).unwrap()));
+        assert!(!quiet_hours.contains(Time::from_hms(7, 0, 0).unwrap()));
+        assert!(!quiet_hours.contains(Time::from_hms(12, 0, 0).unwrap()));
+        assert!("22:00".parse::<QuietHours>().is_err());
+    }
+
+    #[test]
+    fn notifies_new_entries_once() {
+        let mut monitor = monitor("once", Some("22:00-07:00"));
+        let notifier = RecordingNotifier::default();
+
+        let day = datetime!(2023-09-08 12:00 +10);
+        monitor.process(vec![entry("IF1", "Advice")], day, &notifier);
+        monitor.process(vec![entry("IF1", "Advice")], day, &notifier);
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Incident IF1"));
+    }
+
+    #[test]
+    fn advice_deferred_during_quiet_hours() {
+        let mut monitor = monitor("advice-deferred", Some("22:00-07:00"));
+        let notifier = RecordingNotifier::default();
+
+        let night = datetime!(2023-09-08 23:30 +10);
+        monitor.process(vec![entry("IF1", "Advice")], night, &notifier);
+        monitor.process(vec![entry("IF1", "Advice")], night, &notifier);
+        assert!(notifier.messages.borrow().is_empty());
+
+        // The deferred entry is sent once quiet hours are over, even if it's left the feed
+        let morning = datetime!(2023-09-09 07:05 +10);
+        monitor.process(Vec::new(), morning, &notifier);
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Incident IF1"));
+    }
+
+    #[test]
+    fn deferred_notifications_survive_restart() {
+        let path = data_path("deferred-restart");
+        let deferred_path = deferred_path(&path);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(retry_path(&path));
+        let _ = std::fs::remove_file(&deferred_path);
+        let restart = || {
+            Monitor::new(
+                Datastore::new(&path).unwrap(),
+                RetryQueue::new(retry_path(&path), 10).unwrap(),
+            )
+            .with_quiet_hours(Some("22:00-07:00".parse().unwrap()))
+            .with_deferred_queue(RetryQueue::new(&deferred_path, 10).unwrap())
+        };
+        let notifier = RecordingNotifier::default();
+
+        let night = datetime!(2023-09-08 23:30 +10);
+        restart().process(vec![entry("IF1", "Advice")], night, &notifier);
+        assert!(notifier.messages.borrow().is_empty());
+
+        // Sent once quiet hours are over, even though it's left the feed
+        let mut monitor = restart();
+        let morning = datetime!(2023-09-09 07:05 +10);
+        monitor.process(Vec::new(), morning, &notifier);
+        monitor.process(vec![entry("IF1", "Advice")], morning, &notifier);
+        {
+            let messages = notifier.messages.borrow();
+            assert_eq!(messages.len(), 1);
+            assert!(messages[0].contains("Incident IF1"));
+        }
+        assert!(restart().deferred_queue.unwrap().front().is_none());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(retry_path(&path));
+        let _ = std::fs::remove_file(&deferred_path);
+    }
+
+    #[test]
+    fn emergency_bypasses_quiet_hours() {
+        let mut monitor = monitor("emergency-bypass", Some("21:00-06:00"));
+        let notifier = RecordingNotifier::default();
+
+        let night = datetime!(2023-09-08 23:30 +10);
+        monitor.process(
+            vec![
+                entry("IF2", "Emergency Warning"),
+                entry("IF3", "Watch and Act"),
+            ],
+            night,
+            &notifier,
+        );
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Incident IF2"));
+    }
+
+    #[test]
+    fn failed_notification_delivered_on_later_tick() {
+        let mut monitor = monitor("retry", None);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        notifier.fail.set(true);
+        monitor.process(vec![entry("IF4", "Advice")], now, &notifier);
+        assert!(notifier.messages.borrow().is_empty());
+        assert!(monitor.datastore.get(&EntryId("IF4".to_string())).is_none());
+
+        // The queue is persisted
+        let retries = RetryQueue::new(retry_path(&data_path("retry")), 10).unwrap();
+        assert!(retries.contains(&EntryId("IF4".to_string())));
+
+        // Delivered even though the entry has left the feed
+        notifier.fail.set(false);
+        monitor.process(Vec::new(), now, &notifier);
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Incident IF4"));
+        assert!(monitor.datastore.get(&EntryId("IF4".to_string())).is_some());
+        assert!(!monitor.retries.contains(&EntryId("IF4".to_string())));
+    }
+
+    #[test]
+    fn seed_records_without_posting() {
+        let mut monitor = monitor("seed", None).with_seed(SeedMode::Silent);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        monitor.process(vec![entry("IF5", "Emergency Warning")], now, &notifier);
+        assert!(notifier.messages.borrow().is_empty());
+        assert!(monitor.datastore.get(&EntryId("IF5".to_string())).is_some());
+
+        // Only the first poll is a seed
+        monitor.process(vec![entry("IF6", "Advice")], now, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 1);
+    }
+
+    #[test]
+    fn seed_only_new_datastore() {
+        let path = data_path("seed-restart");
+        let retry_path = retry_path(&path);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&retry_path);
+        let start = || {
+            let datastore = Datastore::new(&path).unwrap();
+            let retries = RetryQueue::new(retry_path.clone(), 10).unwrap();
+            Monitor::new(datastore, retries).with_seed(SeedMode::Silent)
+        };
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        start().process(vec![entry("IF27", "Advice")], now, &notifier);
+        assert!(notifier.messages.borrow().is_empty());
+
+        // Restarting with the existing datastore doesn't swallow new incidents
+        start().process(
+            vec![entry("IF27", "Advice"), entry("IF28", "Advice")],
+            now,
+            &notifier,
+        );
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Incident IF28"));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&retry_path);
+    }
+
+    #[test]
+    fn seed_notifies_emergencies() {
+        let mut monitor = monitor("seed-emergencies", None).with_seed(SeedMode::Emergencies);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        monitor.process(
+            vec![entry("IF7", "Emergency Warning"), entry("IF8", "Advice")],
+            now,
+            &notifier,
+        );
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Incident IF7"));
+        assert!(monitor.datastore.get(&EntryId("IF8".to_string())).is_some());
+    }
+
+    #[test]
+    fn update_follows_up_in_thread() {
+        let mut monitor = monitor("follow-up", None).with_notify_updates(true);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        let mut incident = entry("IF9", "Advice");
+        incident.updated = Some(datetime!(2023-09-08 11:00 +10));
+        monitor.process(vec![incident], now, &notifier);
+
+        // Unchanged entries aren't notified again
+        let mut incident = entry("IF9", "Advice");
+        incident.updated = Some(datetime!(2023-09-08 11:00 +10));
+        monitor.process(vec![incident], now, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 1);
+
+        let mut escalated = entry("IF9", "Watch and Act");
+        escalated.updated = Some(datetime!(2023-09-08 11:30 +10));
+        let content_hash = Some(escalated.content_hash());
+        let later = datetime!(2023-09-08 12:30 +10);
+        monitor.process(vec![escalated], later, &notifier);
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 2);
+        // Only what changed is posted
+        assert_eq!(
+            messages[1],
+            "#### Updated: Incident IF9\n\n**Category:** Advice → Watch and Act"
+        );
+        assert_eq!(
+            *notifier.root_ids.borrow(),
+            vec![None, Some("post-1".to_string())]
+        );
+
+        // The thread is persisted with the entry
+        let datastore = Datastore::new(data_path("follow-up")).unwrap();
+        assert_eq!(
+            datastore.get(&EntryId("IF9".to_string())),
+            Some(&Record {
+                updated: Some(datetime!(2023-09-08 11:30 +10)),
+                root_id: Some("post-1".to_string()),
+                notified: Some(later),
+                content_hash,
+                category: Some("Watch and Act".to_string()),
+                title: Some("Incident IF9".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn updates_throttled() {
+        let mut monitor = monitor("renotify-interval", None).with_notify_updates(true);
+        let notifier = RecordingNotifier::default();
+
+        let mut incident = entry("IF23", "Advice");
+        incident.updated = Some(datetime!(2023-09-08 11:55 +10));
+        monitor.process(vec![incident], datetime!(2023-09-08 12:00 +10), &notifier);
+
+        let escalated = |updated| {
+            let mut entry = entry("IF23", "Watch and Act");
+            entry.updated = Some(updated);
+            vec![entry]
+        };
+        // Updates within the interval aren't posted
+        let first = datetime!(2023-09-08 12:03 +10);
+        monitor.process(escalated(first), first, &notifier);
+        let second = datetime!(2023-09-08 12:06 +10);
+        monitor.process(escalated(second), second, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 1);
+
+        // The latest update is posted once the interval has passed
+        let after = datetime!(2023-09-08 12:10 +10);
+        monitor.process(escalated(second), after, &notifier);
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].contains("Watch and Act"));
+    }
+
+    #[test]
+    fn updates_ignored_by_default() {
+        let mut monitor = monitor("no-follow-up", None);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        let mut incident = entry("IF10", "Advice");
+        incident.updated = Some(datetime!(2023-09-08 11:00 +10));
+        monitor.process(vec![incident], now, &notifier);
+        let mut escalated = entry("IF10", "Watch and Act");
+        escalated.updated = Some(datetime!(2023-09-08 11:30 +10));
+        monitor.process(vec![escalated], now, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 1);
+    }
+
+    #[test]
+    fn dedup_window() {
+        let window = Duration::from_secs(7 * 24 * 60 * 60);
+        let mut monitor = monitor("dedup-window", None).with_dedup_window(Some(window));
+        let notifier = RecordingNotifier::default();
+
+        let now = datetime!(2023-09-01 12:00 +10);
+        monitor.process(vec![entry("IF13", "Advice")], now, &notifier);
+        // Suppressed within the window
+        let later = datetime!(2023-09-08 11:59 +10);
+        monitor.process(vec![entry("IF13", "Advice")], later, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 1);
+
+        // Notified again once it has elapsed, which restarts the window
+        let after = datetime!(2023-09-08 12:00 +10);
+        monitor.process(vec![entry("IF13", "Advice")], after, &notifier);
+        monitor.process(vec![entry("IF13", "Advice")], after, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 2);
+
+        // The notified time survives a restart
+        let datastore = Datastore::new(data_path("dedup-window")).unwrap();
+        let record = datastore.get(&EntryId("IF13".to_string())).unwrap();
+        assert_eq!(record.notified, Some(after));
+    }
+
+    #[test]
+    fn content_dedup() {
+        let now = datetime!(2023-09-08 12:00 +10);
+        let reissued = || Entry {
+            title: Some("Incident IF14".to_string()),
+            ..entry("IF15", "Advice")
+        };
+
+        let mut deduped = monitor("content-dedup", None).with_content_dedup(true);
+        let notifier = RecordingNotifier::default();
+        deduped.process(vec![entry("IF14", "Advice")], now, &notifier);
+        deduped.process(vec![reissued()], now, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 1);
+
+        let mut monitor = monitor("no-content-dedup", None);
+        let notifier = RecordingNotifier::default();
+        monitor.process(vec![entry("IF14", "Advice")], now, &notifier);
+        monitor.process(vec![reissued()], now, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 2);
+    }
+
+    #[test]
+    fn grace_poll() {
+        let mut monitor = monitor("grace-poll", None).with_grace_poll(true);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        // Gone by the next poll
+        monitor.process(vec![entry("IF16", "Advice")], now, &notifier);
+        monitor.process(Vec::new(), now, &notifier);
+        monitor.process(vec![entry("IF17", "Advice")], now, &notifier);
+        assert!(notifier.messages.borrow().is_empty());
+
+        // Still there on the next poll
+        monitor.process(vec![entry("IF17", "Advice")], now, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 1);
+        assert!(notifier.messages.borrow()[0].contains("Incident IF17"));
+
+        // Emergencies aren't held
+        monitor.process(vec![entry("IF18", "Emergency Warning")], now, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 2);
+    }
+
+    #[test]
+    fn clear_after() {
+        let mut monitor = monitor("clear-after", None).with_clear_after(Some(2));
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        monitor.process(vec![entry("IF19", "Advice")], now, &notifier);
+        // Back before the limit is reached
+        monitor.process(Vec::new(), now, &notifier);
+        monitor.process(vec![entry("IF19", "Advice")], now, &notifier);
+        monitor.process(Vec::new(), now, &notifier);
+        assert_eq!(notifier.messages.borrow().len(), 1);
+
+        monitor.process(Vec::new(), now, &notifier);
+        monitor.process(Vec::new(), now, &notifier);
+        monitor.process(Vec::new(), now, &notifier);
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1], "Incident IF19 appears to have cleared");
+    }
+
+    #[test]
+    fn announce_new_datastore() {
+        let mut monitor = monitor("announce-new-datastore", None)
+            .with_seed(SeedMode::Silent)
+            .with_announce_new_datastore(true);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        monitor.process(
+            vec![entry("IF20", "Advice"), entry("IF21", "Advice")],
+            now,
+            &notifier,
+        );
+        monitor.process(vec![entry("IF22", "Advice")], now, &notifier);
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0],
+            "Bushfire datastore was empty; seeded 2 entries on first poll"
+        );
+        assert!(messages[1].contains("Incident IF22"));
+    }
+
+    #[test]
+    fn announce_new_datastore_wording() {
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        let mut off = monitor("announce-off", None).with_announce_new_datastore(true);
+        let notifier = RecordingNotifier::default();
+        off.process(vec![entry("IF23", "Advice")], now, &notifier);
+        assert_eq!(
+            notifier.messages.borrow()[1],
+            "Bushfire datastore was empty; notified 1 entries on first poll"
+        );
+
+        let mut emergencies = monitor("announce-emergencies", None)
+            .with_seed(SeedMode::Emergencies)
+            .with_announce_new_datastore(true);
+        let notifier = RecordingNotifier::default();
+        emergencies.process(
+            vec![entry("IF24", "Advice"), entry("IF25", "Emergency Warning")],
+            now,
+            &notifier,
+        );
+        assert_eq!(
+            notifier.messages.borrow()[1],
+            "Bushfire datastore was empty; seeded 1 entries and notified 1 emergencies on first poll"
+        );
+    }
+
+    #[test]
+    fn announce_counts_only_written_entries() {
+        // The parent directory doesn't exist so every append fails
+        let path = data_path("announce-unwritable").join("data");
+        let retry_path = data_path("announce-unwritable-retry");
+        let _ = std::fs::remove_file(&retry_path);
+        let datastore = Datastore::new(path).unwrap();
+        let retries = RetryQueue::new(retry_path.clone(), 10).unwrap();
+        let mut monitor = Monitor::new(datastore, retries)
+            .with_seed(SeedMode::Silent)
+            .with_announce_new_datastore(true);
+        let notifier = RecordingNotifier::default();
+
+        let now = datetime!(2023-09-08 12:00 +10);
+        monitor.process(vec![entry("IF26", "Advice")], now, &notifier);
+        let messages = notifier.messages.borrow();
+        assert_eq!(
+            messages.last().unwrap(),
+            "Bushfire datastore was empty; seeded 0 entries on first poll"
+        );
+        let _ = std::fs::remove_file(&retry_path);
+    }
+
+    #[test]
+    fn unwritable_datastore_remembers_entries() {
+        // The parent directory doesn't exist so every append fails
+        let path = data_path("unwritable").join("data");
+        let retry_path = data_path("unwritable-retry");
+        let _ = std::fs::remove_file(&retry_path);
+        let datastore = Datastore::new(path).unwrap();
+        let retries = RetryQueue::new(retry_path, 10).unwrap();
+        let mut monitor = Monitor::new(datastore, retries);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        monitor.process(vec![entry("IF11", "Advice")], now, &notifier);
+        monitor.process(vec![entry("IF11", "Advice")], now, &notifier);
+        monitor.process(
+            vec![entry("IF11", "Advice"), entry("IF12", "Advice")],
+            now,
+            &notifier,
+        );
+
+        let messages = notifier.messages.borrow();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].contains("Incident IF11"));
+        assert!(messages[1].contains("Unable to append entry to bushfire datastore"));
+        assert!(messages[2].contains("Incident IF12"));
+    }
+
+    #[test]
+    fn poll_failures_back_off() {
+        struct UnavailableFeed;
+
+        impl Feed for UnavailableFeed {
+            fn check(&self, _: LatLong) -> Result<Vec<Entry>, BushfireError> {
+                Err(BushfireError::Io(std::io::Error::other(
+                    "connection refused",
+                )))
+            }
+        }
+
+        struct EmptyFeed;
+
+        impl Feed for EmptyFeed {
+            fn check(&self, _: LatLong) -> Result<Vec<Entry>, BushfireError> {
+                Ok(Vec::new())
+            }
+        }
+
+        let mut monitor = monitor("backoff", None);
+        let notifier = RecordingNotifier::default();
+        let now = datetime!(2023-09-08 12:00 +10);
+
+        assert_eq!(monitor.poll_interval(300), 300);
+        for _ in 0..3 {
+            monitor.poll(&UnavailableFeed, (0., 0.), now, &notifier);
+        }
+        assert_eq!(notifier.messages.borrow().len(), 1);
+        assert_eq!(monitor.poll_interval(300), 1200);
+
+        monitor.poll(&EmptyFeed, (0., 0.), now, &notifier);
+        assert_eq!(monitor.poll_interval(300), 300);
+    }
+
+    #[test]
+    fn repeated_poll_errors() {
+        struct FailingFeed(&'static str);
+
+        impl Feed for FailingFeed {
+            fn check(&self, _: LatLong) -> Result<Vec<Entry>, BushfireError> {
+                Err(BushfireError::Io(std::io::Error::other(self.0)))
+            }
+        }
+
+        struct EmptyFeed;
+
+        impl Feed for EmptyFeed {
+            fn check(&self, _: LatLong) -> Result<Vec<Entry>, BushfireError> {
+                Ok(Vec::new())
+            }
+        }
+
+        let mut monitor =
+            monitor("repeated-errors", None).with_error_reminder(Duration::from_secs(30 * 60));
+        let notifier = RecordingNotifier::default();
+        let start = datetime!(2023-09-08 12:00 +10);
+        let refused = FailingFeed("connection refused");
+
+        // Identical errors within the reminder interval are only posted once
+        for minutes in [0, 5, 10, 29] {
+            monitor.poll(
+                &refused,
+                (0., 0.),
+                start + minutes * time::Duration::MINUTE,
+                &notifier,
+            );
+        }
+        assert_eq!(
+            *notifier.messages.borrow(),
+            ["unable to poll bushfire feed: I/O error: connection refused"]
+        );
+
+        monitor.poll(
+            &refused,
+            (0., 0.),
+            start + 30 * time::Duration::MINUTE,
+            &notifier,
+        );
+        monitor.poll(
+            &refused,
+            (0., 0.),
+            start + 35 * time::Duration::MINUTE,
+            &notifier,
+        );
+        // A different error is posted straight away
+        let timeout = FailingFeed("timed out");
+        monitor.poll(
+            &timeout,
+            (0., 0.),
+            start + 40 * time::Duration::MINUTE,
+            &notifier,
+        );
+        monitor.poll(
+            &EmptyFeed,
+            (0., 0.),
+            start + 45 * time::Duration::MINUTE,
+            &notifier,
+        );
+        assert_eq!(
+            notifier.messages.borrow()[1..],
+            [
+                "bushfire feed still unavailable after 5 attempts: I/O error: connection refused",
+                "unable to poll bushfire feed: I/O error: timed out",
+                "bushfire feed available again after 7 failed polls",
+            ]
+        );
+
+        // The next failure after recovering is posted again
+        monitor.poll(
+            &refused,
+            (0., 0.),
+            start + 50 * time::Duration::MINUTE,
+            &notifier,
+        );
+        assert_eq!(notifier.messages.borrow().len(), 5);
+    }
+
+    #[test]
+    fn poll_jitter() {
+        let mut jitter = Jitter::new(30, 42);
+        let intervals: Vec<u32> = (0..1000).map(|_| jitter.apply(300)).collect();
+        assert!(intervals
+            .iter()
+            .all(|interval| (270..=330).contains(interval)));
+        assert!(intervals.iter().any(|&interval| interval < 290));
+        assert!(intervals.iter().any(|&interval| interval > 310));
+
+        // The same seed gives the same intervals
+        let mut jitter = Jitter::new(30, 42);
+        assert_eq!(jitter.apply(300), intervals[0]);
+
+        assert_eq!(Jitter::new(0, 42).apply(300), 300);
+        assert!(Jitter::new(30, 42).apply(10) >= 1);
+    }
+}
@@ -0,0 +1,993 @@
+//! Post notifications to Mattermost.
+
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use hmac::{Hmac, Mac};
+use json::{array, object, JsonValue};
+use sha2::Sha256;
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+
+use crate::bushfire::{self, Entry, LatLong, Severity};
+use crate::locale::Locale;
+
+/// Header carrying the hex encoded HMAC-SHA256 of the request body when a secret is configured.
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+const BUSHFIRE_PAGE: &str = "https://www.qfes.qld.gov.au/Current-Incidents";
+
+/// Template used for notification messages unless one is configured.
+pub const DEFAULT_TEMPLATE: &str = "#### ⚠️ {category}\n\n[**{title}**]({map_link})\n\n{content}\n\n**{published_label}:** {published}\n**{link_label}:** {link}";
+
+/// A destination for notification messages.
+pub trait Notifier {
+    /// Post `message` to the channel, as a reply to `root_id` if present.
+    ///
+    /// Returns the id of the new post if it is known.
+    fn post_thread(
+        &self,
+        message: &str,
+        root_id: Option<&str>,
+    ) -> Result<Option<String>, Box<ureq::Error>>;
+
+    /// Post a message attachment, as a reply to `root_id` if present.
+    ///
+    /// Notifiers that don't support attachments post the `fallback` text instead.
+    fn post_attachment(
+        &self,
+        _attachment: &JsonValue,
+        fallback: &str,
+        root_id: Option<&str>,
+    ) -> Result<Option<String>, Box<ureq::Error>> {
+        self.post_thread(fallback, root_id)
+    }
+
+    /// Post `message` with `props` stored on the post, as a reply to `root_id` if present.
+    ///
+    /// Notifiers that don't support props post just the message.
+    fn post_props(
+        &self,
+        message: &str,
+        _props: &JsonValue,
+        root_id: Option<&str>,
+    ) -> Result<Option<String>, Box<ureq::Error>> {
+        self.post_thread(message, root_id)
+    }
+
+    /// Post `message` to the channel.
+    fn post(&self, message: &str) -> Result<(), Box<ureq::Error>> {
+        self.post_thread(message, None).map(drop)
+    }
+}
+
+/// Posts messages to a Mattermost incoming webhook.
+///
+/// Incoming webhooks don't return the id of the created post, so replies can only be threaded
+/// when the URL is an endpoint that does, such as a proxy to the posts API.
+pub struct Webhook {
+    url: String,
+    agent: ureq::Agent,
+    /// Shared secret used to sign request bodies
+    secret: Option<String>,
+}
+
+/// Logs messages instead of posting them, for dry runs.
+pub struct LogNotifier;
+
+/// Options controlling how notification messages are formatted.
+#[derive(Debug, Clone)]
+pub struct MessageOptions {
+    /// Map linked to at the end of messages, if set. Titles link to this or the default map.
+    pub map_provider: Option<MapProvider>,
+    /// Message template, [DEFAULT_TEMPLATE] if not set. Fields missing from an entry use fallback
+    /// wording.
+    pub template: Option<String>,
+    /// Point that `{distance}` is measured from.
+    pub reference: Option<LatLong>,
+    /// Post entries as message attachments instead of Markdown text.
+    pub attachments: bool,
+    /// Decimal places in map link coordinates, [DEFAULT_COORDINATE_PRECISION] if not set.
+    pub coordinate_precision: Option<usize>,
+    /// Mentions that start messages, by category prefix. [DEFAULT_MENTIONS] if not set.
+    pub mentions: Option<Vec<(String, String)>>,
+    /// Language of the fixed wording in messages.
+    pub locale: Locale,
+    /// End messages with a line naming the bot and the poll time.
+    pub footer: bool,
+    /// Include the entry's coordinates in the post props so clients can show a map pin.
+    pub location: bool,
+}
+
+/// Mentions added to messages by category prefix unless configured otherwise.
+pub const DEFAULT_MENTIONS: &[(&str, &str)] = &[("Emergency", "@channel")];
+
+/// Decimal places coordinates are formatted to, about 11 m.
+pub const DEFAULT_COORDINATE_PRECISION: usize = 4;
+
+/// Service used to link to an incident on a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapProvider {
+    #[default]
+    OpenStreetMap,
+    Google,
+}
+
+pub struct NotifyError {
+    pub notification: String,
+    pub error: Box<ureq::Error>,
+}
+
+impl Webhook {
+    pub fn new(url: String, agent: ureq::Agent) -> Self {
+        Webhook {
+            url,
+            agent,
+            secret: None,
+        }
+    }
+
+    /// Sign each request body with `secret` so the receiver can verify it came from the bot.
+    pub fn with_secret(mut self, secret: Option<String>) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    fn send(
+        &self,
+        mut body: JsonValue,
+        root_id: Option<&str>,
+    ) -> Result<Option<String>, Box<ureq::Error>> {
+        if let Some(root_id) = root_id {
+            body["root_id"] = root_id.into();
+        }
+
+        let body = json::stringify(body);
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.set(SIGNATURE_HEADER, &signature(secret, &body));
+        }
+        let response = request.send_string(&body).map_err(Box::new)?;
+
+        // Incoming webhooks respond with a plain "ok", the post id is only available when the
+        // endpoint responds with the created post.
+        let post_id = response
+            .into_string()
+            .ok()
+            .and_then(|body| json::parse(&body).ok())
+            .and_then(|post| post["id"].as_str().map(ToOwned::to_owned));
+        Ok(post_id)
+    }
+}
+
+/// The hex encoded HMAC-SHA256 of `body` keyed with `secret`.
+fn signature(secret: &str, body: &str) -> String {
+    // NOTE(unwrap): HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+impl Notifier for Webhook {
+    fn post_thread(
+        &self,
+        message: &str,
+        root_id: Option<&str>,
+    ) -> Result<Option<String>, Box<ureq::Error>> {
+        self.send(object! { text: message }, root_id)
+    }
+
+    fn post_attachment(
+        &self,
+        attachment: &JsonValue,
+        _fallback: &str,
+        root_id: Option<&str>,
+    ) -> Result<Option<String>, Box<ureq::Error>> {
+        self.send(object! { attachments: [attachment.clone()] }, root_id)
+    }
+
+    fn post_props(
+        &self,
+        message: &str,
+        props: &JsonValue,
+        root_id: Option<&str>,
+    ) -> Result<Option<String>, Box<ureq::Error>> {
+        self.send(object! { text: message, props: props.clone() }, root_id)
+    }
+}
+
+impl Notifier for LogNotifier {
+    fn post_thread(
+        &self,
+        message: &str,
+        root_id: Option<&str>,
+    ) -> Result<Option<String>, Box<ureq::Error>> {
+        match root_id {
+            Some(root_id) => {
+                info!(root_id = root_id; "dry run, not posting reply to {root_id}: {message}")
+            }
+            None => info!("dry run, not posting: {message}"),
+        }
+        Ok(None)
+    }
+}
+
+impl Default for MessageOptions {
+    fn default() -> Self {
+        MessageOptions {
+            map_provider: Some(MapProvider::default()),
+            template: None,
+            reference: None,
+            attachments: false,
+            coordinate_precision: None,
+            mentions: None,
+            locale: Locale::default(),
+            footer: false,
+            location: false,
+        }
+    }
+}
+
+impl MessageOptions {
+    fn coordinate_precision(&self) -> usize {
+        self.coordinate_precision
+            .unwrap_or(DEFAULT_COORDINATE_PRECISION)
+    }
+
+    /// The mention for entries in `category`, if any.
+    fn mention(&self, category: &str) -> Option<&str> {
+        match &self.mentions {
+            Some(mentions) => mentions
+                .iter()
+                .find(|(prefix, _)| category.starts_with(prefix.as_str()))
+                .map(|(_, mention)| mention.as_str()),
+            None => DEFAULT_MENTIONS
+                .iter()
+                .find(|(prefix, _)| category.starts_with(prefix))
+                .map(|(_, mention)| *mention),
+        }
+    }
+}
+
+impl MapProvider {
+    /// Build a link to `point` on this provider's map, with coordinates to `precision` places.
+    pub fn url(&self, (lat, lon): LatLong, precision: usize) -> String {
+        let lat = format!("{lat:.precision$}");
+        let lon = format!("{lon:.precision$}");
+        match self {
+            MapProvider::OpenStreetMap => {
+                format!("https://www.openstreetmap.org/?mlat={lat}&mlon={lon}#map=12/{lat}/{lon}")
+            }
+            MapProvider::Google => {
+                format!("https://www.google.com/maps/search/?api=1&query={lat}%2C{lon}")
+            }
+        }
+    }
+}
+
+impl FromStr for MapProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openstreetmap" | "osm" => Ok(MapProvider::OpenStreetMap),
+            "google" => Ok(MapProvider::Google),
+            _ => Err(format!(
+                "unknown map provider '{s}', expected openstreetmap or google"
+            )),
+        }
+    }
+}
+
+/// Post a notification about `entry`, as a reply to `root_id` if present.
+///
+/// Returns the id of the new post if it is known.
+pub fn notify_entry(
+    entry: &Entry,
+    options: &MessageOptions,
+    polled: OffsetDateTime,
+    root_id: Option<&str>,
+    notifier: &dyn Notifier,
+) -> Result<Option<String>, NotifyError> {
+    let message = entry_text(entry, options, polled);
+    let footer = options.footer.then(|| footer(options, polled));
+    let location = entry
+        .point
+        .filter(|_| options.location)
+        .map(|(lat, lon)| object! { latitude: lat, longitude: lon });
+    let attachment = options.attachments.then(|| {
+        let mut attachment = entry_attachment(entry, options);
+        if let Some(footer) = footer {
+            // Keep the map link added by entry_attachment
+            attachment["footer"] = match attachment["footer"].as_str() {
+                Some(map) => format!("{map} · {footer}").into(),
+                None => footer.into(),
+            };
+        }
+        attachment
+    });
+    let result = match (attachment, location) {
+        // Mattermost reads attachments from the props too
+        (Some(attachment), Some(location)) => {
+            let props = object! { attachments: [attachment], location: location };
+            notifier.post_props(&message, &props, root_id)
+        }
+        (Some(attachment), None) => notifier.post_attachment(&attachment, &message, root_id),
+        (None, Some(location)) => {
+            notifier.post_props(&message, &object! { location: location }, root_id)
+        }
+        (None, None) => notifier.post_thread(&message, root_id),
+    };
+    result.map_err(|error| NotifyError {
+        notification: message,
+        error,
+    })
+}
+
+/// The plain text of the notification about `entry`, without any attachment or location.
+pub fn entry_text(entry: &Entry, options: &MessageOptions, polled: OffsetDateTime) -> String {
+    let mut message = entry_message(entry, options);
+    if options.footer {
+        message.push_str(&format!("\n\n{}", footer(options, polled)));
+    }
+    message
+}
+
+/// Describe how `entry` has changed since it was notified with `category` and `title`.
+///
+/// Returns `None` if neither is known to have changed.
+pub fn changes(
+    entry: &Entry,
+    category: Option<&str>,
+    title: Option<&str>,
+    options: &MessageOptions,
+) -> Option<String> {
+    let catalog = options.locale.catalog();
+    let fields = [
+        (catalog.category, category, entry.category()),
+        (catalog.title, title, entry.title.as_deref()),
+    ];
+    let lines: Vec<_> = fields
+        .into_iter()
+        .filter_map(|(label, previous, current)| match (previous, current) {
+            (Some(previous), Some(current)) if previous != current => {
+                Some(format!("**{label}:** {previous} → {current}"))
+            }
+            _ => None,
+        })
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let title = entry.title.as_deref().unwrap_or(catalog.untitled);
+    let mut message = format!("#### {}: {title}\n\n{}", catalog.updated, lines.join("\n"));
+    if let Some(mention) = entry
+        .category()
+        .and_then(|category| options.mention(category))
+    {
+        message.insert_str(0, &format!("{mention}\n"));
+    }
+    Some(message)
+}
+
+/// Post `changes` from [changes] as a reply to `root_id`.
+///
+/// Follow-ups are always plain text, attachments and location props are only added to the
+/// original notification.
+pub fn notify_changes(
+    changes: &str,
+    options: &MessageOptions,
+    polled: OffsetDateTime,
+    root_id: Option<&str>,
+    notifier: &dyn Notifier,
+) -> Result<Option<String>, NotifyError> {
+    let mut message = changes.to_owned();
+    if options.footer {
+        message.push_str(&format!("\n\n{}", footer(options, polled)));
+    }
+    notifier
+        .post_thread(&message, root_id)
+        .map_err(|error| NotifyError {
+            notification: message,
+            error,
+        })
+}
+
+/// The attribution line added to messages, `polled` is in the configured time zone.
+fn footer(options: &MessageOptions, polled: OffsetDateTime) -> String {
+    let polled = polled
+        .format(&Rfc2822)
+        .unwrap_or_else(|_| options.locale.catalog().unknown.to_string());
+    format!("{} {polled}", options.locale.catalog().footer)
+}
+
+/// Format the notification message for an entry.
+fn entry_message(entry: &Entry, options: &MessageOptions) -> String {
+    let template = options.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+    let mut message = render(template, |name| placeholder(entry, options, name));
+    if let Some(mention) = entry
+        .category()
+        .and_then(|category| options.mention(category))
+    {
+        message.insert_str(0, &format!("{mention}\n"));
+    }
+    if let (Some(provider), Some(point)) = (options.map_provider, entry.point) {
+        message.push_str(&format!("\n**{}:** ", options.locale.catalog().map));
+        message.push_str(&provider.url(point, options.coordinate_precision()));
+    }
+    message
+}
+
+/// Build a message attachment for an entry, with the sidebar colour keyed to its alert level.
+fn entry_attachment(entry: &Entry, options: &MessageOptions) -> JsonValue {
+    let value = |name| placeholder(entry, options, name).map(Cow::into_owned);
+    let catalog = options.locale.catalog();
+    let color = match entry.severity() {
+        Severity::Emergency => "#d0021b",
+        Severity::WatchAndAct => "#f5a623",
+        Severity::Advice => "#f8e71c",
+        Severity::Unknown => "#9b9b9b",
+    };
+    let mut fields = array![
+        { short: true, title: catalog.category, value: value("category") },
+        { short: true, title: catalog.published, value: value("published") },
+    ];
+    if let Some(distance) = value("distance") {
+        // NOTE(unwrap): fields is an array
+        fields
+            .push(object! { short: true, title: catalog.distance, value: distance })
+            .unwrap();
+    }
+    let mut attachment = object! {
+        fallback: entry_message(entry, options),
+        color: color,
+        title: value("title"),
+        title_link: value("map_link"),
+        text: value("content"),
+        fields: fields,
+    };
+    if let Some(mention) = entry
+        .category()
+        .and_then(|category| options.mention(category))
+    {
+        attachment["pretext"] = mention.into();
+    }
+    if let (Some(provider), Some(point)) = (options.map_provider, entry.point) {
+        attachment["footer"] = format!(
+            "[{}]({})",
+            catalog.map,
+            provider.url(point, options.coordinate_precision())
+        )
+        .into();
+    }
+    attachment
+}
+
+/// The value of the `{name}` placeholder for an entry.
+///
+/// Fields missing from the entry use the locale's fallback wording, such as "Untitled". Returns
+/// `None` for unrecognised names, and for `{distance}` when the entry or reference point isn't
+/// known, so those are left in the message as they are.
+fn placeholder<'a>(entry: &'a Entry, options: &MessageOptions, name: &str) -> Option<Cow<'a, str>> {
+    let catalog = options.locale.catalog();
+    let value = match name {
+        "category" => entry.category().unwrap_or(catalog.unknown_category).into(),
+        "categories" if entry.categories.is_empty() => catalog.unknown_category.into(),
+        "categories" => entry.categories.join(", ").into(),
+        "title" => entry.title.as_deref().unwrap_or(catalog.untitled).into(),
+        "content" => entry
+            .content
+            .as_deref()
+            .or(entry.summary.as_deref())
+            .unwrap_or(catalog.no_content)
+            .into(),
+        "published" => entry
+            .published
+            .and_then(|published| published.format(&Rfc2822).ok())
+            .unwrap_or_else(|| String::from(catalog.unknown))
+            .into(),
+        "published_label" => catalog.published.into(),
+        "link_label" => catalog.link.into(),
+        "link" => entry.link().unwrap_or(BUSHFIRE_PAGE).into(),
+        "map_link" => match entry.point {
+            Some(point) => options
+                .map_provider
+                .unwrap_or_default()
+                .url(point, options.coordinate_precision())
+                .into(),
+            None => BUSHFIRE_PAGE.into(),
+        },
+        "distance" => {
+            let distance = bushfire::distance_km(options.reference?, entry.point?);
+            format!("{distance:.1} km").into()
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Replace `{name}` placeholders in `template` with the value returned by `lookup`.
+///
+/// Placeholders that `lookup` has no value for are left as they are.
+fn render<'a, F>(template: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<Cow<'a, str>>,
+{
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let value = placeholder
+            .find('}')
+            .and_then(|end| Some((end, lookup(&placeholder[1..end])?)));
+        match value {
+            Some((end, value)) => {
+                output.push_str(&value);
+                rest = &placeholder[end + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = &placeholder[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::bushfire::Link;
+
+    /// A notifier that records messages instead of posting them.
+    #[derive(Default)]
+    pub(crate) struct RecordingNotifier {
+        pub(crate) messages: RefCell<Vec<String>>,
+        /// The post each message was a reply to
+        pub(crate) root_ids: RefCell<Vec<Option<String>>>,
+        /// When set posting fails as if the webhook was unavailable
+        pub(crate) fail: Cell<bool>,
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn post_thread(
+            &self,
+            message: &str,
+            root_id: Option<&str>,
+        ) -> Result<Option<String>, Box<ureq::Error>> {
+            if self.fail.get() {
+                let response = ureq::Response::new(503, "Service Unavailable", "").unwrap();
+                return Err(Box::new(ureq::Error::Status(503, response)));
+            }
+            let mut messages = self.messages.borrow_mut();
+            messages.push(message.to_string());
+            self.root_ids
+                .borrow_mut()
+                .push(root_id.map(ToOwned::to_owned));
+            Ok(Some(format!("post-{}", messages.len())))
+        }
+    }
+
+    /// Post `message` through a webhook signed with `secret`, returning the signature header
+    /// received and the body.
+    fn signed_post(secret: Option<&str>, message: &str) -> (Option<String>, String) {
+        capture_post(secret, |webhook| {
+            webhook.post(message).unwrap();
+        })
+    }
+
+    /// Call `post` with a webhook signed with `secret`, returning the signature header and body
+    /// of the request it makes.
+    fn capture_post<F: FnOnce(&Webhook)>(
+        secret: Option<&str>,
+        post: F,
+    ) -> (Option<String>, String) {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let (mut signature, mut content_length) = (None, 0);
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case(SIGNATURE_HEADER) {
+                        signature = Some(value.trim().to_string());
+                    } else if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .unwrap();
+            (signature, String::from_utf8(body).unwrap())
+        });
+
+        let webhook = Webhook::new(url, crate::client::agent("test"))
+            .with_secret(secret.map(ToOwned::to_owned));
+        post(&webhook);
+        server.join().unwrap()
+    }
+
+    #[test]
+    fn webhook_signature() {
+        let (signature, body) = signed_post(Some("hunter2"), "Fire near Kumbarilla");
+        assert_eq!(body, r#"{"text":"Fire near Kumbarilla"}"#);
+        assert_eq!(
+            signature.as_deref(),
+            Some("a901af5599e45ac050201f356916ff5c87480a1569c3b4609322bd9322c804b5")
+        );
+
+        // Unsigned without a secret
+        let (signature, _) = signed_post(None, "Fire near Kumbarilla");
+        assert_eq!(signature, None);
+    }
+
+    #[test]
+    fn location_props() {
+        let entry = Entry {
+            categories: vec!["Advice".to_string()],
+            title: Some("Fire near Kumbarilla".to_string()),
+            point: Some((-27.1277, 152.879)),
+            ..Default::default()
+        };
+        let post = |options: &MessageOptions| {
+            let (_, body) = capture_post(None, |webhook| {
+                notify_entry(&entry, options, OffsetDateTime::UNIX_EPOCH, None, webhook)
+                    .ok()
+                    .unwrap();
+            });
+            json::parse(&body).unwrap()
+        };
+
+        let mut options = MessageOptions::default();
+        let body = post(&options);
+        assert!(body["props"].is_null());
+        assert_eq!(body["text"], entry_message(&entry, &options));
+
+        options.location = true;
+        let body = post(&options);
+        assert_eq!(
+            body["props"],
+            object! { location: { latitude: -27.1277, longitude: 152.879 } }
+        );
+        assert_eq!(body["text"], entry_message(&entry, &options));
+
+        options.attachments = true;
+        let body = post(&options);
+        assert_eq!(body["props"]["location"]["latitude"], -27.1277);
+        assert_eq!(
+            body["props"]["attachments"][0],
+            entry_attachment(&entry, &options)
+        );
+    }
+
+    #[test]
+    fn attachment_footer_keeps_map_link() {
+        let entry = Entry {
+            categories: vec!["Advice".to_string()],
+            point: Some((-27.1277, 152.879)),
+            ..Default::default()
+        };
+        let options = MessageOptions {
+            map_provider: Some(MapProvider::OpenStreetMap),
+            attachments: true,
+            footer: true,
+            ..Default::default()
+        };
+        let (_, body) = capture_post(None, |webhook| {
+            notify_entry(&entry, &options, OffsetDateTime::UNIX_EPOCH, None, webhook)
+                .ok()
+                .unwrap();
+        });
+        let body = json::parse(&body).unwrap();
+        assert_eq!(
+            body["attachments"][0]["footer"],
+            format!(
+                "[Map]({}) · {}",
+                MapProvider::OpenStreetMap.url((-27.1277, 152.879), 4),
+                super::footer(&options, OffsetDateTime::UNIX_EPOCH)
+            )
+        );
+    }
+
+    #[test]
+    fn entry_message_falls_back_to_summary() {
+        let mut entry = Entry {
+            summary: Some("Fire near Kumbarilla".to_string()),
+            ..Default::default()
+        };
+        assert!(entry_message(&entry, &MessageOptions::default())
+            .contains("\n\nFire near Kumbarilla\n\n"));
+
+        entry.content = Some("A large fire is burning".to_string());
+        let message = entry_message(&entry, &MessageOptions::default());
+        assert!(message.contains("\n\nA large fire is burning\n\n"));
+        assert!(!message.contains("Fire near Kumbarilla"));
+
+        assert!(entry_message(&Entry::default(), &MessageOptions::default())
+            .contains("\n\nNo content\n\n"));
+    }
+
+    #[test]
+    fn footer() {
+        let entry = Entry {
+            title: Some("Fire near Kumbarilla".to_string()),
+            ..Default::default()
+        };
+        let polled = time::macros::datetime!(2023-09-08 12:00 +10);
+        let post = |options: &MessageOptions| {
+            let notifier = RecordingNotifier::default();
+            notify_entry(&entry, options, polled, None, &notifier)
+                .ok()
+                .unwrap();
+            notifier.messages.take().remove(0)
+        };
+
+        let mut options = MessageOptions::default();
+        assert!(!post(&options).contains("via Wizards Bot"));
+
+        options.footer = true;
+        assert!(
+            post(&options).ends_with("\n\n— via Wizards Bot at Fri, 08 Sep 2023 12:00:00 +1000")
+        );
+    }
+
+    #[test]
+    fn changes_since_notified() {
+        let entry = Entry {
+            categories: vec!["Emergency Warning".to_string()],
+            title: Some("Fire near Kumbarilla".to_string()),
+            ..Default::default()
+        };
+        let options = MessageOptions::default();
+        assert_eq!(
+            changes(&entry, Some("Advice"), Some("Fire near Kumbarilla"), &options).as_deref(),
+            Some("@channel\n#### Updated: Fire near Kumbarilla\n\n**Category:** Advice → Emergency Warning")
+        );
+        // Nothing to describe when unchanged or not known
+        assert_eq!(
+            changes(&entry, Some("Emergency Warning"), None, &options),
+            None
+        );
+    }
+
+    #[test]
+    fn entry_link() {
+        let mut entry = Entry::default();
+        assert!(entry_message(&entry, &MessageOptions::default())
+            .ends_with("**Link:** https://www.qfes.qld.gov.au/Current-Incidents"));
+
+        entry.links = vec![
+            Link {
+                href: "https://example.com/IF39-1.xml".to_string(),
+                rel: Some("self".to_string()),
+                media_type: None,
+            },
+            Link {
+                href: "https://example.com/IF39-1".to_string(),
+                rel: Some("alternate".to_string()),
+                media_type: Some("text/html".to_string()),
+            },
+        ];
+        assert!(entry_message(&entry, &MessageOptions::default())
+            .ends_with("**Link:** https://example.com/IF39-1"));
+    }
+
+    #[test]
+    fn map_link() {
+        let point = (-27.584701903466, 151.06082028616);
+        assert_eq!(
+            MapProvider::OpenStreetMap.url(point, 12),
+            "https://www.openstreetmap.org/?mlat=-27.584701903466&mlon=151.060820286160#map=12/-27.584701903466/151.060820286160"
+        );
+        assert_eq!(
+            MapProvider::Google.url(point, 12),
+            "https://www.google.com/maps/search/?api=1&query=-27.584701903466%2C151.060820286160"
+        );
+
+        let entry = Entry {
+            point: Some(point),
+            ..Default::default()
+        };
+        let options = MessageOptions {
+            map_provider: Some(MapProvider::Google),
+            ..Default::default()
+        };
+        assert!(entry_message(&entry, &options).ends_with(
+            "\n**Map:** https://www.google.com/maps/search/?api=1&query=-27.5847%2C151.0608"
+        ));
+        // OpenStreetMap unless configured otherwise, the same as Config
+        assert!(entry_message(&entry, &MessageOptions::default()).ends_with(
+            "\n**Map:** https://www.openstreetmap.org/?mlat=-27.5847&mlon=151.0608#map=12/-27.5847/151.0608"
+        ));
+        let options = MessageOptions {
+            map_provider: None,
+            ..Default::default()
+        };
+        assert!(!entry_message(&entry, &options).contains("**Map:**"));
+    }
+
+    #[test]
+    fn coordinate_precision() {
+        let entry = Entry {
+            point: Some((-27.584701903466, 151.06082028616)),
+            ..Default::default()
+        };
+        let options = MessageOptions {
+            map_provider: Some(MapProvider::Google),
+            coordinate_precision: Some(2),
+            ..Default::default()
+        };
+        assert!(entry_message(&entry, &options).ends_with(
+            "\n**Map:** https://www.google.com/maps/search/?api=1&query=-27.58%2C151.06"
+        ));
+    }
+
+    #[test]
+    fn mention_prefix() {
+        let entry = |category: &str| Entry {
+            categories: vec![category.to_string()],
+            ..Default::default()
+        };
+        let options = MessageOptions::default();
+        assert!(entry_message(&entry("Emergency Warning"), &options).starts_with("@channel\n####"));
+        assert!(entry_message(&entry("Advice"), &options).starts_with("####"));
+
+        let options = MessageOptions {
+            mentions: Some(vec![("Watch and Act".to_string(), "@here".to_string())]),
+            ..Default::default()
+        };
+        assert!(entry_message(&entry("Watch and Act"), &options).starts_with("@here\n####"));
+        assert!(entry_message(&entry("Emergency Warning"), &options).starts_with("####"));
+
+        let options = MessageOptions {
+            mentions: Some(Vec::new()),
+            ..Default::default()
+        };
+        assert!(entry_message(&entry("Emergency Warning"), &options).starts_with("####"));
+    }
+
+    #[test]
+    fn default_template() {
+        let entry = Entry {
+            categories: vec!["Advice".to_string()],
+            title: Some("Fire near Kumbarilla".to_string()),
+            content: Some("A fire is burning".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            entry_message(&entry, &MessageOptions::default()),
+            "#### ⚠️ Advice\n\n[**Fire near Kumbarilla**](https://www.qfes.qld.gov.au/Current-Incidents)\n\nA fire is burning\n\n**Published:** unknown\n**Link:** https://www.qfes.qld.gov.au/Current-Incidents"
+        );
+    }
+
+    #[test]
+    fn locale_wording() {
+        let entry = Entry {
+            categories: vec!["Advice".to_string()],
+            ..Default::default()
+        };
+        let options = MessageOptions {
+            locale: Locale::French,
+            ..Default::default()
+        };
+        assert_eq!(
+            entry_message(&entry, &options),
+            "#### ⚠️ Advice\n\n[**Sans titre**](https://www.qfes.qld.gov.au/Current-Incidents)\n\nAucun contenu\n\n**Publié:** inconnue\n**Lien:** https://www.qfes.qld.gov.au/Current-Incidents"
+        );
+    }
+
+    #[test]
+    fn custom_template() {
+        let mut entry = Entry {
+            categories: vec!["Advice".to_string()],
+            title: Some("Fire near Kumbarilla".to_string()),
+            ..Default::default()
+        };
+        let options = MessageOptions {
+            template: Some("{category}: {title} ({distance}) {unknown} {".to_string()),
+            reference: Some((-27.46844, 153.02334)),
+            ..Default::default()
+        };
+        // Distance is unknown without a point
+        assert_eq!(
+            entry_message(&entry, &options),
+            "Advice: Fire near Kumbarilla ({distance}) {unknown} {"
+        );
+
+        entry.point = Some((-27.127664662091, 152.87902054721));
+        assert_eq!(
+            entry_message(&entry, &options),
+            "Advice: Fire near Kumbarilla (40.5 km) {unknown} {\n**Map:** https://www.openstreetmap.org/?mlat=-27.1277&mlon=152.8790#map=12/-27.1277/152.8790"
+        );
+
+        entry.categories.push("Bushfire".to_string());
+        let options = MessageOptions {
+            template: Some("{categories}".to_string()),
+            ..Default::default()
+        };
+        assert!(entry_message(&entry, &options).starts_with("Advice, Bushfire\n**Map:**"));
+
+        // Other missing fields use fallback wording rather than the placeholder
+        let options = MessageOptions {
+            template: Some("{title}: {content} ({published})".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            entry_message(&Entry::default(), &options),
+            "Untitled: No content (unknown)"
+        );
+    }
+
+    #[test]
+    fn emergency_attachment() {
+        let entry = Entry {
+            categories: vec!["Emergency Warning".to_string(), "Bushfire".to_string()],
+            title: Some("Fire near Kumbarilla".to_string()),
+            content: Some("Leave now".to_string()),
+            point: Some((-27.127664662091, 152.87902054721)),
+            ..Default::default()
+        };
+        let options = MessageOptions {
+            reference: Some((-27.46844, 153.02334)),
+            attachments: true,
+            ..Default::default()
+        };
+        let attachment = entry_attachment(&entry, &options);
+        assert_eq!(
+            attachment,
+            object! {
+                fallback: entry_message(&entry, &options),
+                color: "#d0021b",
+                title: "Fire near Kumbarilla",
+                title_link: "https://www.openstreetmap.org/?mlat=-27.1277&mlon=152.8790#map=12/-27.1277/152.8790",
+                text: "Leave now",
+                fields: [
+                    { short: true, title: "Category", value: "Emergency Warning" },
+                    { short: true, title: "Published", value: "unknown" },
+                    { short: true, title: "Distance", value: "40.5 km" },
+                ],
+                pretext: "@channel",
+                footer: "[Map](https://www.openstreetmap.org/?mlat=-27.1277&mlon=152.8790#map=12/-27.1277/152.8790)",
+            }
+        );
+
+        // Notifiers without attachment support get the text message
+        let notifier = RecordingNotifier::default();
+        notify_entry(
+            &entry,
+            &options,
+            OffsetDateTime::UNIX_EPOCH,
+            None,
+            &notifier,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(
+            *notifier.messages.borrow(),
+            vec![entry_message(&entry, &options)]
+        );
+    }
+}
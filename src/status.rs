@@ -0,0 +1,57 @@
+//! Status shared between the feed monitor and the HTTP server.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use time::OffsetDateTime;
+
+use crate::bushfire::Severity;
+
+/// Number of notifications kept in the history.
+pub const HISTORY_LENGTH: usize = 50;
+
+#[derive(Debug, Default)]
+pub struct Status {
+    /// Set once the datastore is loaded and the feed has been polled successfully
+    ready: AtomicBool,
+    /// The most recent notifications, oldest first
+    history: Mutex<VecDeque<Notification>>,
+}
+
+/// A notification the monitor attempted to post, listed at /history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub id: String,
+    pub title: Option<String>,
+    pub severity: Severity,
+    pub time: OffsetDateTime,
+    /// The notification was posted, rather than queued to retry
+    pub delivered: bool,
+}
+
+impl Status {
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    /// Add `notification` to the history, dropping the oldest once it is full.
+    pub fn record_notification(&self, notification: Notification) {
+        // NOTE(unwrap): The lock is never held across a panic
+        let mut history = self.history.lock().unwrap();
+        if history.len() >= HISTORY_LENGTH {
+            history.pop_front();
+        }
+        history.push_back(notification);
+    }
+
+    /// The most recent notifications, newest first.
+    pub fn history(&self) -> Vec<Notification> {
+        // NOTE(unwrap): The lock is never held across a panic
+        self.history.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
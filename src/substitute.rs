@@ -0,0 +1,895 @@
+//! Substitute links to sites with privacy friendly alternatives.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs, io};
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use url::Url;
+
+use crate::metrics;
+
+/// Configuration for URL substitution.
+#[derive(Debug, Clone, Default)]
+pub struct Substitutions {
+    /// Only rewrite the first occurrence of each URL.
+    pub dedupe: bool,
+    /// Query parameters removed from all URLs, a trailing `*` matches any suffix.
+    pub strip_params: Vec<String>,
+    /// Query parameters that are never removed, overriding `strip_params` and host rules.
+    pub preserve_params: Vec<String>,
+    /// Keep the original URL first, annotated with the rewritten one.
+    pub expand: bool,
+    /// Additional rules, checked before the built-in ones.
+    pub rules: Vec<Rule>,
+    /// Names of built-in rules that are skipped.
+    pub disabled_rules: Vec<String>,
+    /// Link sources through an archive service instead of directly.
+    pub source_archive: Option<Archive>,
+    /// Show Twitter links as an fxtwitter embed, followed by the Nitter and source links.
+    pub fxtwitter: bool,
+    /// Schemes of URLs that may be rewritten, [DEFAULT_SCHEMES] if empty.
+    pub schemes: Vec<String>,
+    /// Leave URLs after this many have been rewritten in one message untouched.
+    pub max_rewrites: Option<usize>,
+}
+
+/// Schemes of URLs that are rewritten unless configured otherwise.
+pub const DEFAULT_SCHEMES: &[&str] = &["http", "https"];
+
+/// A web archive that source links can point to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Archive {
+    /// archive.today, linking to the newest snapshot
+    ArchiveToday,
+    /// The Internet Archive's Wayback Machine
+    Wayback,
+}
+
+/// Rewrites URLs on some hosts to an alternative host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    /// Identifies the rule in metrics.
+    pub name: String,
+    /// Hosts the rule applies to, including their subdomains.
+    pub hosts: Vec<String>,
+    /// Host that matching URLs are rewritten to.
+    pub replacement: String,
+    /// Remove the whole query string rather than just the parameters in the strip list.
+    pub strip_query: bool,
+    /// Link to the original URL after the rewritten one.
+    pub source: bool,
+    /// What the replacement host is for.
+    pub kind: RuleKind,
+}
+
+/// The purpose of a [Rule]'s replacement host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RuleKind {
+    /// A privacy friendly frontend.
+    #[default]
+    Privacy,
+    /// A proxy that renders a preview when embedded in chat, checked before privacy rules.
+    Embed,
+}
+
+static BUILTIN_RULES: Lazy<Vec<Rule>> = Lazy::new(|| {
+    vec![
+        Rule {
+            name: String::from("twitter"),
+            hosts: vec![String::from("x.com"), String::from("twitter.com")],
+            replacement: String::from("nitter.net"),
+            // Nitter doesn't like Twitter's new tracking params so strip query string and hope
+            // for the best.
+            strip_query: true,
+            source: true,
+            kind: RuleKind::Privacy,
+        },
+        Rule {
+            name: String::from("medium"),
+            hosts: vec![String::from("medium.com")],
+            replacement: String::from("scribe.rip"),
+            strip_query: false,
+            source: true,
+            kind: RuleKind::Privacy,
+        },
+    ]
+});
+
+/// Host of the embed friendly Twitter proxy.
+const FXTWITTER_HOST: &str = "fxtwitter.com";
+
+/// Matches a URL, along with surrounding angle brackets of a Markdown autolink.
+static URL_REGEX: Lazy<Regex> = Lazy::new(||
+    // https://www.regextester.com/94502
+    Regex::new(r"(?P<open><)?(?P<url>[[:alpha:]][[:alnum:]+.-]*://[[:word:].-]+(?:\.[[:word:].-]+)+[[:word:]\-._~:/?#\[\]@!$&'()*+,;=]+)(?P<close>>)?").unwrap());
+
+static LINK_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[[^\]]*\]\([^)]*\)").unwrap());
+
+/// Hosts of URL shorteners whose links are expanded by [Shortlinks].
+const SHORTLINK_HOSTS: &[&str] = &["t.co", "bit.ly", "buff.ly", "tinyurl.com"];
+
+/// Shortened links looked up per message unless configured otherwise.
+pub const DEFAULT_MAX_SHORTLINK_LOOKUPS: usize = 5;
+
+/// Resolves where shortened links redirect to.
+pub trait Resolver {
+    /// The URL `url` redirects to, if it does.
+    fn resolve(&self, url: &str) -> Option<String>;
+}
+
+impl Resolver for ureq::Agent {
+    fn resolve(&self, url: &str) -> Option<String> {
+        // The agent must not follow redirects for the location to be available
+        let response = self.head(url).call().ok()?;
+        if !(300..400).contains(&response.status()) {
+            return None;
+        }
+        let location = response.header("Location")?;
+        // NOTE(unwrap): url matched the URL regex so is parseable
+        let base: Url = url.parse().unwrap();
+        base.join(location).ok().map(String::from)
+    }
+}
+
+/// Expands links on URL shortener hosts to the URL they redirect to.
+pub struct Shortlinks {
+    pub resolver: Box<dyn Resolver + Send + Sync>,
+    /// Maximum number of links looked up for one message, others are left unexpanded.
+    pub max_lookups: usize,
+}
+
+/// URLs rewritten so far in one message.
+#[derive(Default)]
+struct Rewrites {
+    /// The original URLs
+    seen: HashSet<String>,
+    /// Number of URLs rewritten
+    count: usize,
+}
+
+/// Appended to text shortened by [truncate].
+const TRUNCATED: &str = "… (truncated)";
+
+impl Substitutions {
+    /// Replace URLs in `text` with their privacy friendly alternatives.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut rewrites = Rewrites::default();
+        URL_REGEX.replace_all(text, |captures: &Captures<'_>| {
+            self.replacement(captures, &mut rewrites)
+        })
+    }
+
+    /// Write `text` to `sink` with URLs replaced as by [Substitutions::apply], without building
+    /// the whole result in memory.
+    ///
+    /// This is only a library API, `/nit` responses are built in memory with [Substitutions::apply].
+    pub fn apply_to<W: Write>(&self, text: &str, sink: &mut W) -> io::Result<()> {
+        let mut rewrites = Rewrites::default();
+        let mut last = 0;
+        for captures in URL_REGEX.captures_iter(text) {
+            // NOTE(unwrap): captures 0 should always be present
+            let matched = captures.get(0).unwrap();
+            sink.write_all(&text.as_bytes()[last..matched.start()])?;
+            sink.write_all(self.replacement(&captures, &mut rewrites).as_bytes())?;
+            last = matched.end();
+        }
+        sink.write_all(&text.as_bytes()[last..])
+    }
+
+    /// The text that replaces a match of [URL_REGEX], given the `rewrites` made so far.
+    fn replacement(&self, captures: &Captures<'_>, rewrites: &mut Rewrites) -> String {
+        // NOTE(unwrap): captures 0 should always be present
+        let matched = captures.get(0).unwrap().as_str();
+        if self
+            .max_rewrites
+            .is_some_and(|max_rewrites| rewrites.count >= max_rewrites)
+        {
+            return matched.to_string();
+        }
+        // Only a matching pair of brackets is an autolink, a lone one is left where it is
+        let (open, close) = (captures.name("open"), captures.name("close"));
+        let autolink = open.is_some() && close.is_some();
+        let url0 = &captures["url"];
+        let replacement = match self.maybe_replace_url(url0, autolink) {
+            // Leave repeats of a URL that has already been rewritten as they are so the
+            // source suffix is only included once
+            Some(_) if self.dedupe && !rewrites.seen.insert(url0.to_string()) => {
+                return matched.to_string()
+            }
+            Some(replacement) => replacement,
+            None => return matched.to_string(),
+        };
+        rewrites.count += 1;
+        if autolink {
+            replacement
+        } else {
+            let open = open.map_or("", |open| open.as_str());
+            let close = close.map_or("", |close| close.as_str());
+            format!("{open}{replacement}{close}")
+        }
+    }
+
+    /// Rewrite `url0` if there is a rule for its host or it has parameters to strip.
+    ///
+    /// URLs are wrapped in angle brackets in the output if `url0` was an `autolink`.
+    fn maybe_replace_url(&self, url0: &str, autolink: bool) -> Option<String> {
+        // NOTE(unwrap): url0 should be parseable as a URL due to matching the regex.
+        let mut url: Url = url0.parse().unwrap();
+        if !self.rewrites_scheme(url.scheme()) {
+            return None;
+        }
+
+        let rule = url.host_str().and_then(|host| {
+            let builtin = BUILTIN_RULES
+                .iter()
+                .filter(|rule| !self.disabled_rules.contains(&rule.name));
+            let mut rules = self.rules.iter().chain(builtin);
+            // Embed proxies don't make sense next to the original URL
+            let embed = (!self.expand)
+                .then(|| {
+                    rules
+                        .clone()
+                        .find(|rule| rule.kind == RuleKind::Embed && rule.matches(host))
+                })
+                .flatten();
+            embed
+                .or_else(|| rules.find(|rule| rule.kind == RuleKind::Privacy && rule.matches(host)))
+        });
+        if let Some(rule) = rule {
+            let _ = url.set_host(Some(&rule.replacement));
+            self.strip_params(&mut url, rule.strip_query);
+            metrics::SUBSTITUTIONS.inc(&rule.name);
+            if self.fxtwitter && rule.name == "twitter" && !self.expand {
+                return Some(self.embed(&url, url0, autolink));
+            }
+            Some(self.annotate(&url, url0, rule.source, autolink))
+        } else if self.strip_params(&mut url, false) {
+            Some(self.annotate(&url, url0, false, autolink))
+        } else {
+            // Leave the original url
+            None
+        }
+    }
+
+    /// Determine if URLs with `scheme` may be rewritten.
+    fn rewrites_scheme(&self, scheme: &str) -> bool {
+        if self.schemes.is_empty() {
+            DEFAULT_SCHEMES.contains(&scheme)
+        } else {
+            self.schemes.iter().any(|allowed| allowed == scheme)
+        }
+    }
+
+    /// Format the rewritten `url` along with the original, `url0`.
+    ///
+    /// The source is only linked when the host was `rewritten`.
+    fn annotate(&self, url: &Url, url0: &str, rewritten: bool, autolink: bool) -> String {
+        let wrap = |url: &str| {
+            if autolink {
+                format!("<{url}>")
+            } else {
+                url.to_string()
+            }
+        };
+        match (self.expand, rewritten) {
+            (true, _) => format!("{} (clean: {})", wrap(url0), wrap(url.as_str())),
+            (false, true) => {
+                format!("{} ([source]({}))", wrap(url.as_str()), self.source(url0))
+            }
+            (false, false) => wrap(url.as_str()),
+        }
+    }
+
+    /// Format an fxtwitter link for the rewritten Nitter `url`, followed by links to Nitter and
+    /// the original, `url0`.
+    fn embed(&self, url: &Url, url0: &str, autolink: bool) -> String {
+        let mut embed = url.clone();
+        let _ = embed.set_host(Some(FXTWITTER_HOST));
+        let embed = if autolink {
+            format!("<{embed}>")
+        } else {
+            embed.to_string()
+        };
+        format!(
+            "{embed} ([nitter]({url})) ([source]({}))",
+            self.source(url0)
+        )
+    }
+
+    /// Link to the original URL, through the archive service if there is one.
+    fn source<'a>(&self, url0: &'a str) -> Cow<'a, str> {
+        match self.source_archive {
+            Some(archive) => Cow::Owned(archive.url(url0)),
+            None => Cow::Borrowed(url0),
+        }
+    }
+
+    /// Remove query parameters in the strip list, or all of them if `strip_all` is set, unless
+    /// they are preserved. Returns true if any were removed.
+    fn strip_params(&self, url: &mut Url, strip_all: bool) -> bool {
+        let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        let kept: Vec<&(String, String)> = pairs
+            .iter()
+            .filter(|(name, _)| {
+                matches(&self.preserve_params, name)
+                    || !(strip_all || matches(&self.strip_params, name))
+            })
+            .collect();
+        if kept.len() == pairs.len() {
+            return false;
+        }
+
+        if kept.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(kept);
+        }
+        true
+    }
+}
+
+impl Shortlinks {
+    /// Replace shortened links in `text` with the URL they redirect to.
+    pub fn expand<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut resolved: HashMap<String, Option<String>> = HashMap::new();
+        URL_REGEX.replace_all(text, |captures: &Captures<'_>| {
+            // NOTE(unwrap): captures 0 and url should always be present
+            let matched = captures.get(0).unwrap();
+            let url = captures.name("url").unwrap();
+            let shortlink = Url::parse(url.as_str())
+                .ok()
+                .filter(|url| DEFAULT_SCHEMES.contains(&url.scheme()))
+                .and_then(|url| url.host_str().map(|host| SHORTLINK_HOSTS.contains(&host)))
+                .unwrap_or(false);
+            if !shortlink {
+                return matched.as_str().to_string();
+            }
+
+            // Repeats of a link are only looked up once
+            if !resolved.contains_key(url.as_str()) {
+                if resolved.len() >= self.max_lookups {
+                    return matched.as_str().to_string();
+                }
+                let target = self.resolver.resolve(url.as_str());
+                resolved.insert(url.as_str().to_string(), target);
+            }
+            match &resolved[url.as_str()] {
+                Some(target) => {
+                    // Keep any brackets around the link
+                    let start = url.start() - matched.start();
+                    let end = url.end() - matched.start();
+                    let matched = matched.as_str();
+                    format!("{}{}{}", &matched[..start], target, &matched[end..])
+                }
+                None => matched.as_str().to_string(),
+            }
+        })
+    }
+}
+
+/// Shorten `text` to at most `max_chars` characters, noting that it was truncated.
+///
+/// The text is cut at whitespace and never within a URL or Markdown link.
+pub fn truncate(text: &str, max_chars: usize) -> Cow<'_, str> {
+    if text.chars().count() <= max_chars {
+        return Cow::Borrowed(text);
+    }
+
+    let limit = max_chars.saturating_sub(TRUNCATED.chars().count());
+    // NOTE(unwrap): text has more than max_chars characters
+    let mut cut = text.char_indices().nth(limit).unwrap().0;
+    let spans: Vec<_> = URL_REGEX
+        .find_iter(text)
+        .chain(LINK_REGEX.find_iter(text))
+        .map(|span| span.range())
+        .collect();
+    loop {
+        let mut boundary = cut;
+        if !text[boundary..].starts_with(char::is_whitespace) {
+            boundary = text[..boundary].rfind(char::is_whitespace).unwrap_or(0);
+        }
+        if let Some(span) = spans
+            .iter()
+            .find(|span| span.start < boundary && boundary < span.end)
+        {
+            boundary = span.start;
+        }
+        if boundary == cut {
+            break;
+        }
+        cut = boundary;
+    }
+
+    Cow::Owned(format!("{}{TRUNCATED}", text[..cut].trim_end()))
+}
+
+impl Archive {
+    /// Link to the archived copy of `url`.
+    fn url(&self, url: &str) -> String {
+        match self {
+            Archive::ArchiveToday => format!("https://archive.ph/newest/{url}"),
+            Archive::Wayback => format!("https://web.archive.org/web/{url}"),
+        }
+    }
+}
+
+impl FromStr for Archive {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "archive.today" | "archive.ph" => Ok(Archive::ArchiveToday),
+            "wayback" | "web.archive.org" => Ok(Archive::Wayback),
+            _ => Err(format!(
+                "unknown archive '{s}', expected archive.today or wayback"
+            )),
+        }
+    }
+}
+
+/// Determine if `name` is the name of a built-in rule.
+pub fn is_builtin_rule(name: &str) -> bool {
+    BUILTIN_RULES.iter().any(|rule| rule.name == name)
+}
+
+impl Rule {
+    /// Determine if `host` is one of the rule's hosts or a subdomain of one.
+    fn matches(&self, host: &str) -> bool {
+        self.hosts.iter().any(|rule_host| {
+            host.strip_suffix(rule_host.as_str())
+                .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with('.'))
+        })
+    }
+
+    fn from_json(rule: &json::JsonValue) -> Result<Rule, String> {
+        let string = |name: &str| {
+            rule[name]
+                .as_str()
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| format!("{name} must be a string"))
+        };
+        let flag = |name: &str, default| match &rule[name] {
+            json::JsonValue::Null => Ok(default),
+            value => value
+                .as_bool()
+                .ok_or_else(|| format!("{name} must be true or false")),
+        };
+
+        let name = string("name")?;
+        let kind = match &rule["kind"] {
+            json::JsonValue::Null => RuleKind::Privacy,
+            value => match value.as_str() {
+                Some("privacy") => RuleKind::Privacy,
+                Some("embed") => RuleKind::Embed,
+                _ => return Err(format!("{name}: kind must be privacy or embed")),
+            },
+        };
+        let hosts = rule["hosts"]
+            .members()
+            .map(|host| host.as_str().map(ToOwned::to_owned))
+            .collect::<Option<Vec<_>>>()
+            .filter(|hosts| !hosts.is_empty())
+            .ok_or_else(|| format!("{name}: hosts must be a non-empty list of strings"))?;
+        let replacement = string("replacement").map_err(|err| format!("{name}: {err}"))?;
+        let valid_host = Url::parse(&format!("https://{replacement}/"))
+            .is_ok_and(|url| url.host_str() == Some(replacement.as_str()));
+        if !valid_host {
+            return Err(format!("{name}: invalid replacement host '{replacement}'"));
+        }
+        Ok(Rule {
+            strip_query: flag("strip_query", false).map_err(|err| format!("{name}: {err}"))?,
+            source: flag("source", true).map_err(|err| format!("{name}: {err}"))?,
+            name,
+            hosts,
+            replacement,
+            kind,
+        })
+    }
+}
+
+/// Load rewrite rules from a JSON file containing an array of rules.
+///
+/// Each rule has a `name`, a list of `hosts`, the `replacement` host, and optionally
+/// `strip_query` (default false), `source` (default true), and `kind`, either `privacy`
+/// (default) or `embed`.
+pub fn load_rules(path: &Path) -> Result<Vec<Rule>, io::Error> {
+    let text = fs::read_to_string(path)?;
+    let rules = json::parse(&text).map_err(io::Error::other)?;
+    if !rules.is_array() {
+        return Err(io::Error::other("expected an array of rules"));
+    }
+    rules
+        .members()
+        .enumerate()
+        .map(|(i, rule)| {
+            Rule::from_json(rule).map_err(|err| io::Error::other(format!("rule {}: {err}", i + 1)))
+        })
+        .collect()
+}
+
+/// Determine if the parameter `name` matches any of `patterns`.
+fn matches(patterns: &[String], name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => pattern == name,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substitute_urls(text: &str) -> Cow<'_, str> {
+        Substitutions::default().apply(text)
+    }
+
+    #[test]
+    fn other_schemes_untouched() {
+        for text in [
+            "ftp://twitter.com/wezm/file.txt",
+            "gopher://medium.com/@wezm/post?utm_source=x",
+            "mailto:wezm@twitter.com",
+        ] {
+            assert_eq!(substitute_urls(text), text);
+        }
+
+        let substitutions = Substitutions {
+            schemes: vec![String::from("https")],
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply("http://twitter.com/wezm https://twitter.com/wezm"),
+            "http://twitter.com/wezm https://nitter.net/wezm ([source](https://twitter.com/wezm))"
+        );
+    }
+
+    #[test]
+    fn bare_domain() {
+        // Links to the site itself go to the Nitter home page
+        assert_eq!(
+            substitute_urls("https://twitter.com/"),
+            "https://nitter.net/ ([source](https://twitter.com/))"
+        );
+        assert_eq!(
+            substitute_urls("https://x.com"),
+            "https://nitter.net/ ([source](https://x.com))"
+        );
+        assert_eq!(
+            substitute_urls("https://twitter.com/wezm or https://x.com/?s=20"),
+            "https://nitter.net/wezm ([source](https://twitter.com/wezm)) or https://nitter.net/ ([source](https://x.com/?s=20))"
+        );
+    }
+
+    #[test]
+    fn streaming_matches_apply() {
+        let substitutions = Substitutions {
+            dedupe: true,
+            ..Default::default()
+        };
+        let mut text = String::new();
+        for i in 0..1000 {
+            text.push_str(&format!(
+                "see https://twitter.com/user{} and <https://www.youtube.com/watch?v={i}&t=1>, or https://example.com/{i}. ",
+                i % 100
+            ));
+        }
+
+        let mut streamed = Vec::new();
+        substitutions.apply_to(&text, &mut streamed).unwrap();
+        assert_eq!(
+            String::from_utf8(streamed).unwrap(),
+            substitutions.apply(&text)
+        );
+    }
+
+    #[test]
+    fn max_rewrites() {
+        let substitutions = Substitutions {
+            max_rewrites: Some(2),
+            ..Default::default()
+        };
+        let text = "https://twitter.com/a https://example.com/b https://medium.com/@c/post https://x.com/d";
+        assert_eq!(
+            substitutions.apply(text),
+            "https://nitter.net/a ([source](https://twitter.com/a)) https://example.com/b https://scribe.rip/@c/post ([source](https://medium.com/@c/post)) https://x.com/d"
+        );
+        let mut streamed = Vec::new();
+        substitutions.apply_to(text, &mut streamed).unwrap();
+        assert_eq!(
+            String::from_utf8(streamed).unwrap(),
+            substitutions.apply(text)
+        );
+        assert_eq!(substitute_urls(text).matches("[source]").count(), 3);
+    }
+
+    #[test]
+    fn dedupe_repeated_url() {
+        let substitutions = Substitutions {
+            dedupe: true,
+            ..Default::default()
+        };
+        let val =
+            substitutions.apply("https://twitter.com/wezm and again https://twitter.com/wezm");
+        assert_eq!(
+            val,
+            "https://nitter.net/wezm ([source](https://twitter.com/wezm)) and again https://twitter.com/wezm",
+        );
+        assert_eq!(val.matches("([source]").count(), 1);
+
+        // Without dedupe both are rewritten
+        let val = substitute_urls("https://twitter.com/wezm and again https://twitter.com/wezm");
+        assert_eq!(val.matches("([source]").count(), 2);
+    }
+
+    #[test]
+    fn preserve_params() {
+        let substitutions = Substitutions {
+            strip_params: vec!["utm_*".to_string(), "lang".to_string()],
+            preserve_params: vec!["lang".to_string(), "page".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply("https://example.com/docs?page=2&utm_source=feed&lang=en"),
+            "https://example.com/docs?page=2&lang=en"
+        );
+        // Nothing to strip
+        assert_eq!(
+            substitutions.apply("https://example.com/docs?page=2"),
+            "https://example.com/docs?page=2"
+        );
+        // Preserved params are kept even where the whole query is normally removed
+        assert_eq!(
+            substitutions.apply("https://twitter.com/wezm?lang=en&s=20"),
+            "https://nitter.net/wezm?lang=en ([source](https://twitter.com/wezm?lang=en&s=20))"
+        );
+    }
+
+    #[test]
+    fn expand_emphasis() {
+        let url = "https://twitter.com/wezm/status/1323096439602339840?s=20";
+        assert_eq!(
+            substitute_urls(url),
+            "https://nitter.net/wezm/status/1323096439602339840 ([source](https://twitter.com/wezm/status/1323096439602339840?s=20))"
+        );
+
+        let substitutions = Substitutions {
+            expand: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply(url),
+            "https://twitter.com/wezm/status/1323096439602339840?s=20 (clean: https://nitter.net/wezm/status/1323096439602339840)"
+        );
+    }
+
+    #[test]
+    fn angle_bracket_autolinks() {
+        assert_eq!(
+            substitute_urls("see <https://twitter.com/wezm/status/1323096439602339840?s=20> now"),
+            "see <https://nitter.net/wezm/status/1323096439602339840> ([source](https://twitter.com/wezm/status/1323096439602339840?s=20)) now"
+        );
+
+        let substitutions = Substitutions {
+            expand: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply("<https://medium.com/@wezm/post>"),
+            "<https://medium.com/@wezm/post> (clean: <https://scribe.rip/@wezm/post>)"
+        );
+
+        // Unmatched brackets stay where they were
+        assert_eq!(
+            substitute_urls("<https://twitter.com/wezm"),
+            "<https://nitter.net/wezm ([source](https://twitter.com/wezm))"
+        );
+        assert_eq!(
+            substitute_urls("<https://example.com/page>"),
+            "<https://example.com/page>"
+        );
+    }
+
+    #[test]
+    fn shortlink_lookup_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        struct CountingResolver(Arc<AtomicUsize>);
+
+        impl Resolver for CountingResolver {
+            fn resolve(&self, url: &str) -> Option<String> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                let id = url.rsplit('/').next().unwrap();
+                Some(format!("https://twitter.com/wezm/status/{id}"))
+            }
+        }
+
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let shortlinks = Shortlinks {
+            resolver: Box::new(CountingResolver(Arc::clone(&lookups))),
+            max_lookups: 2,
+        };
+        let text =
+            "https://t.co/1 <https://t.co/2> https://t.co/1 https://t.co/3 https://example.com/4";
+        assert_eq!(
+            shortlinks.expand(text),
+            "https://twitter.com/wezm/status/1 <https://twitter.com/wezm/status/2> https://twitter.com/wezm/status/1 https://t.co/3 https://example.com/4"
+        );
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn truncate_on_safe_boundary() {
+        assert_eq!(truncate("short text", 20), "short text");
+
+        let text = substitute_urls("see https://twitter.com/wezm/status/1323096439602339840 now");
+        // Long enough for the rewritten URL but not the whole source link
+        let truncated = truncate(&text, 70);
+        assert_eq!(
+            truncated,
+            "see https://nitter.net/wezm/status/1323096439602339840… (truncated)"
+        );
+        assert!(truncated.chars().count() <= 70);
+
+        // Too short for the URL at all
+        assert_eq!(truncate(&text, 30), "see… (truncated)");
+
+        let text = "read [the whole story](https://example.com/story) today";
+        assert_eq!(truncate(text, 40), "read… (truncated)");
+    }
+
+    #[test]
+    fn rules_file() {
+        let path = std::env::temp_dir().join(format!("wizards-bot-rules-{}", std::process::id()));
+        fs::write(
+            &path,
+            r#"[{"name": "reddit", "hosts": ["reddit.com"], "replacement": "old.reddit.com", "strip_query": true, "source": false}]"#,
+        )
+        .unwrap();
+        let substitutions = Substitutions {
+            rules: load_rules(&path).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply("https://www.reddit.com/r/brisbane?utm_source=share and https://twitter.com/wezm"),
+            "https://old.reddit.com/r/brisbane and https://nitter.net/wezm ([source](https://twitter.com/wezm))"
+        );
+
+        fs::write(&path, r#"[{"name": "reddit", "hosts": ["reddit.com"]}]"#).unwrap();
+        let err = load_rules(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "rule 1: reddit: replacement must be a string"
+        );
+        fs::write(
+            &path,
+            r#"[{"name": "bad", "hosts": ["a.com"], "replacement": "b.com/path"}]"#,
+        )
+        .unwrap();
+        let err = load_rules(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "rule 1: bad: invalid replacement host 'b.com/path'"
+        );
+        fs::write(&path, "{}").unwrap();
+        assert!(load_rules(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn embed_rules() {
+        let path =
+            std::env::temp_dir().join(format!("wizards-bot-embed-rules-{}", std::process::id()));
+        fs::write(
+            &path,
+            r#"[
+                {"name": "reddit", "hosts": ["reddit.com"], "replacement": "old.reddit.com"},
+                {"name": "rxddit", "hosts": ["reddit.com"], "replacement": "rxddit.com", "kind": "embed"},
+                {"name": "instagram", "hosts": ["instagram.com"], "replacement": "ddinstagram.com", "kind": "embed", "source": false}
+            ]"#,
+        )
+        .unwrap();
+        let rules = load_rules(&path).unwrap();
+        assert_eq!(rules[0].kind, RuleKind::Privacy);
+        assert_eq!(rules[1].kind, RuleKind::Embed);
+        let substitutions = Substitutions {
+            rules,
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply("https://www.reddit.com/r/brisbane/comments/abc?context=3 and https://www.instagram.com/p/xyz/"),
+            "https://rxddit.com/r/brisbane/comments/abc?context=3 ([source](https://www.reddit.com/r/brisbane/comments/abc?context=3)) and https://ddinstagram.com/p/xyz/"
+        );
+        // Embeds aren't used when the original URL is kept
+        let substitutions = Substitutions {
+            expand: true,
+            ..substitutions
+        };
+        assert_eq!(
+            substitutions.apply("https://reddit.com/r/brisbane"),
+            "https://reddit.com/r/brisbane (clean: https://old.reddit.com/r/brisbane)"
+        );
+
+        fs::write(
+            &path,
+            r#"[{"name": "bad", "hosts": ["a.com"], "replacement": "b.com", "kind": "mirror"}]"#,
+        )
+        .unwrap();
+        let err = load_rules(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "rule 1: bad: kind must be privacy or embed"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn archived_source() {
+        let url = "https://twitter.com/wezm/status/1323096439602339840?s=20";
+        assert_eq!(
+            substitute_urls(url),
+            "https://nitter.net/wezm/status/1323096439602339840 ([source](https://twitter.com/wezm/status/1323096439602339840?s=20))"
+        );
+
+        let substitutions = Substitutions {
+            source_archive: Some(Archive::Wayback),
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply(url),
+            "https://nitter.net/wezm/status/1323096439602339840 ([source](https://web.archive.org/web/https://twitter.com/wezm/status/1323096439602339840?s=20))"
+        );
+        let substitutions = Substitutions {
+            source_archive: Some(Archive::ArchiveToday),
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply("https://medium.com/@wezm/post"),
+            "https://scribe.rip/@wezm/post ([source](https://archive.ph/newest/https://medium.com/@wezm/post))"
+        );
+    }
+
+    #[test]
+    fn disabled_rules() {
+        let substitutions = Substitutions {
+            disabled_rules: vec![String::from("medium")],
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply("https://medium.com/@wezm/post https://twitter.com/wezm"),
+            "https://medium.com/@wezm/post https://nitter.net/wezm ([source](https://twitter.com/wezm))"
+        );
+    }
+
+    #[test]
+    fn substitution_metrics() {
+        // Other tests rewrite URLs concurrently so only check that the count went up
+        let before = metrics::SUBSTITUTIONS.get("twitter");
+        substitute_urls("https://twitter.com/wezm");
+        assert!(metrics::SUBSTITUTIONS.get("twitter") > before);
+        assert!(metrics::render().contains("\nsubstitutions_total{host=\"twitter\"} "));
+    }
+
+    #[test]
+    fn fxtwitter() {
+        let substitutions = Substitutions {
+            fxtwitter: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            substitutions.apply("look https://x.com/wezm/status/1323096439602339840?s=20 and https://medium.com/@wezm/post"),
+            "look https://fxtwitter.com/wezm/status/1323096439602339840 ([nitter](https://nitter.net/wezm/status/1323096439602339840)) ([source](https://x.com/wezm/status/1323096439602339840?s=20)) and https://scribe.rip/@wezm/post ([source](https://medium.com/@wezm/post))"
+        );
+        assert_eq!(
+            substitutions.apply("<https://twitter.com/wezm>"),
+            "<https://fxtwitter.com/wezm> ([nitter](https://nitter.net/wezm)) ([source](https://twitter.com/wezm))"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Replay captured feed snapshots through the bot, checking which notifications are posted.
+//!
+//! Each snapshot in a fixture directory is served as the feed in turn, and the bot is run once
+//! per snapshot with a shared datastore, as it would be from cron.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Serves the current snapshot at /feed.xml and records messages posted to /hook.
+struct Upstream {
+    addr: SocketAddr,
+    feed: Arc<Mutex<String>>,
+    messages: Arc<Mutex<Vec<String>>>,
+}
+
+/// Drives the bot through a directory of feed snapshots.
+struct Replay {
+    upstream: Upstream,
+    snapshots: Vec<PathBuf>,
+    data_path: PathBuf,
+}
+
+impl Upstream {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let feed = Arc::new(Mutex::new(String::new()));
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let (served, received) = (Arc::clone(&feed), Arc::clone(&messages));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                handle(stream, &served, &received);
+            }
+        });
+        Upstream {
+            addr,
+            feed,
+            messages,
+        }
+    }
+}
+
+fn handle(mut stream: TcpStream, feed: &Mutex<String>, messages: &Mutex<Vec<String>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+
+    let response = if request_line.starts_with("GET /feed.xml ") {
+        feed.lock().unwrap().clone()
+    } else if request_line.starts_with("POST /hook ") {
+        let post = json::parse(&String::from_utf8(body).unwrap()).unwrap();
+        messages
+            .lock()
+            .unwrap()
+            .push(post["text"].as_str().unwrap().to_string());
+        String::from("ok")
+    } else {
+        panic!("unexpected request: {request_line}");
+    };
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+        response.len()
+    )
+    .unwrap();
+}
+
+impl Replay {
+    /// Prepare to replay the snapshots in `fixture`, in file name order.
+    fn new(fixture: &str) -> Self {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(fixture);
+        let mut snapshots: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "xml"))
+            .collect();
+        snapshots.sort();
+
+        let data_path = std::env::temp_dir().join(format!(
+            "wizards-bot-replay-{fixture}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&data_path);
+        Replay {
+            upstream: Upstream::start(),
+            snapshots,
+            data_path,
+        }
+    }
+
+    /// Serve each snapshot and run the bot once, checking the messages posted at each step
+    /// contain the `expected` titles.
+    fn run(&self, expected: &[&[&str]]) {
+        assert_eq!(self.snapshots.len(), expected.len());
+        for (snapshot, expected) in self.snapshots.iter().zip(expected) {
+            *self.upstream.feed.lock().unwrap() = fs::read_to_string(snapshot).unwrap();
+            let messages = self.poll();
+            let name = snapshot.file_name().unwrap().to_string_lossy();
+            assert_eq!(
+                messages.len(),
+                expected.len(),
+                "{name}: unexpected notifications {messages:#?}"
+            );
+            for (message, title) in messages.iter().zip(*expected) {
+                assert!(
+                    message.contains(&format!("[**{title}**]")),
+                    "{name}: expected {title} in {message}"
+                );
+            }
+        }
+    }
+
+    /// Run the bot once, returning the messages it posted.
+    fn poll(&self) -> Vec<String> {
+        let addr = self.upstream.addr;
+        let output = Command::new(env!("CARGO_BIN_EXE_wizards-bot"))
+            .env_clear()
+            .env("MM_SLASH_TOKEN", "test")
+            .env("MM_BUSHFIRE_WEBHOOK", format!("http://{addr}/hook"))
+            .env("WIZARDS_BOT_DATA_PATH", &self.data_path)
+            .env("WIZARDS_BOT_BUSHFIRE_POINT", "-27.46844,153.02334")
+            .env("WIZARDS_BOT_FEED_URL", format!("http://{addr}/feed.xml"))
+            .env("WIZARDS_BOT_RUN_MODE", "once")
+            .env("WIZARDS_BOT_MAP_PROVIDER", "none")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "bot failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        std::mem::take(&mut *self.upstream.messages.lock().unwrap())
+    }
+}
+
+impl Drop for Replay {
+    fn drop(&mut self) {
+        let mut retry_path = self.data_path.clone().into_os_string();
+        retry_path.push(".retry");
+        let _ = fs::remove_file(&self.data_path);
+        let _ = fs::remove_file(retry_path);
+    }
+}
+
+#[test]
+fn replay_feed_transitions() {
+    Replay::new("replay").run(&[
+        // Only the incident within range is notified
+        &["Fire near Mount Coot-tha"],
+        // Incidents already notified aren't notified again
+        &["Fire near Stones Corner"],
+        // Updates to notified incidents aren't notified by default
+        &["Fire near Carindale"],
+    ]);
+}